@@ -0,0 +1,14 @@
+//! Helpers shared by the benchmarks, included in each with `#[path = "common.rs"] mod common;`.
+
+///shuffles the keys with a small deterministic generator so runs are comparable
+pub fn keys(n: u64) -> Vec<u64> {
+    let mut keys: Vec<u64> = (0..n).collect();
+    let mut seed: u64 = 0x2545F4914F6CDD1D;
+    for i in (1..keys.len()).rev() {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        keys.swap(i, (seed % (i as u64 + 1)) as usize);
+    }
+    keys
+}
@@ -0,0 +1,45 @@
+//! Times inserts and removes on trees of growing size.
+//!
+//! Each rebalancing step only touches the nodes it rotates, so one insert or remove costs
+//! O(log n). If that holds, the time per operation divided by log2(n) stays about the same as
+//! n grows by orders of magnitude. Run with `cargo bench`.
+extern crate avltree_map;
+
+#[path = "common.rs"]
+mod common;
+
+use common::keys;
+use std::time::Instant;
+use avltree_map::AvlTree;
+
+///nanoseconds per operation for running f over every key
+fn time_per_op<F: FnMut(u64)>(keys: &[u64], mut f: F) -> f64 {
+    let start = Instant::now();
+    for &key in keys {
+        f(key);
+    }
+    let elapsed = start.elapsed();
+    (elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos())) / keys.len() as f64
+}
+
+fn main() {
+    println!("{:>9} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+             "n", "seq insert", "/log2 n", "rand insert", "/log2 n", "remove", "/log2 n");
+    for &n in [1_000u64, 10_000, 100_000, 1_000_000].iter() {
+        let log = (n as f64).log2();
+        let sequential: Vec<u64> = (0..n).collect();
+        let shuffled = keys(n);
+
+        //sequential inserts rotate at almost every level on the way back up
+        let mut tree = AvlTree::new();
+        let seq_insert = time_per_op(&sequential, |key| { tree.insert(key, key); });
+
+        let mut tree = AvlTree::new();
+        let rand_insert = time_per_op(&shuffled, |key| { tree.insert(key, key); });
+        let remove = time_per_op(&sequential, |key| { tree.remove(&key); });
+        assert!(tree.is_empty());
+
+        println!("{:>9} {:>12.1} {:>12.2} {:>12.1} {:>12.2} {:>12.1} {:>12.2}",
+                 n, seq_insert, seq_insert / log, rand_insert, rand_insert / log, remove, remove / log);
+    }
+}
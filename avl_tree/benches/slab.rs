@@ -0,0 +1,76 @@
+//! Compares the boxed AvlTree with the SlabAvlTree.
+//!
+//! The insert-heavy workload builds a tree from shuffled keys and drops it. The remove-heavy
+//! workload builds a tree and then removes most of it while inserting a few new keys, so the
+//! slab tree keeps taking slots off its free list. Run with `cargo bench --bench slab`.
+extern crate avltree_map;
+
+#[path = "common.rs"]
+mod common;
+
+use common::keys;
+use std::time::Instant;
+use avltree_map::AvlTree;
+use avltree_map::slab::SlabAvlTree;
+
+///nanoseconds per key for running f once
+fn time_per_key<F: FnOnce()>(n: u64, f: F) -> f64 {
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed();
+    (elapsed.as_secs() as f64 * 1e9 + f64::from(elapsed.subsec_nanos())) / n as f64
+}
+
+fn main() {
+    println!("{:>9} {:>12} {:>12} {:>12} {:>12}",
+             "n", "box insert", "slab insert", "box remove", "slab remove");
+    for &n in [1_000u64, 10_000, 100_000, 1_000_000].iter() {
+        let shuffled = keys(n);
+
+        let box_insert = time_per_key(n, || {
+            let mut tree = AvlTree::new();
+            for &key in &shuffled {
+                tree.insert(key, key);
+            }
+        });
+        let slab_insert = time_per_key(n, || {
+            let mut tree = SlabAvlTree::new();
+            for &key in &shuffled {
+                tree.insert(key, key);
+            }
+        });
+
+        //removes three keys for every new one, so the tree shrinks to a quarter of its size
+        let box_remove = {
+            let mut tree = AvlTree::new();
+            for &key in &shuffled {
+                tree.insert(key, key);
+            }
+            time_per_key(n, || {
+                for (i, &key) in shuffled.iter().enumerate() {
+                    tree.remove(&key);
+                    if i % 3 == 0 {
+                        tree.insert(key + n, key);
+                    }
+                }
+            })
+        };
+        let slab_remove = {
+            let mut tree = SlabAvlTree::new();
+            for &key in &shuffled {
+                tree.insert(key, key);
+            }
+            time_per_key(n, || {
+                for (i, &key) in shuffled.iter().enumerate() {
+                    tree.remove(&key);
+                    if i % 3 == 0 {
+                        tree.insert(key + n, key);
+                    }
+                }
+            })
+        };
+
+        println!("{:>9} {:>12.1} {:>12.1} {:>12.1} {:>12.1}",
+                 n, box_insert, slab_insert, box_remove, slab_remove);
+    }
+}
@@ -0,0 +1,1072 @@
+//! A sorted map that many threads can read and write at once.
+//!
+//! `ConcurrentAvlTree` follows Bronson, Casper, Chafi and Olukotun, "A Practical Concurrent
+//! Binary Search Tree" (PPoPP 2010):
+//!
+//! * Readers take no locks. They walk down hand over hand, checking each node's version after
+//!   reading its child, and back up one level to try again when a rotation has moved keys out
+//!   from under them.
+//! * Writers lock only the nodes they change, always parents before children.
+//! * Removing a key with two children just clears its value, leaving a routing node that
+//!   stays in the tree until it has fewer than two children and can be unlinked.
+//! * Balance is relaxed: heights are fixed and rotations done after the write is visible, one
+//!   node at a time, so for a moment the tree may be out of balance. Once every writer has
+//!   returned it is a strict AVL tree again.
+//!
+//! Unlinked nodes and replaced values are freed through `crossbeam_epoch` once no reader can
+//! still be looking at them. Since a value can be replaced while another thread reads it, the
+//! map hands out clones rather than references.
+use std::cmp::{max, Ord, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicI32, AtomicU64};
+use std::sync::atomic::Ordering::SeqCst;
+use std::thread;
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+
+///version bits. a node's version only changes when it is unlinked, or when a rotation moves it
+///down and so shrinks the range of keys below it
+const UNLINKED: u64 = 1;
+const SHRINKING: u64 = 2;
+const SHRINK_COUNT: u64 = 4;
+
+///how many times to check a shrinking node before waiting on its lock
+const SPINS: usize = 100;
+
+///a reader or writer saw a rotation or unlink that invalidates what it read, and has to go
+///back up a level and try again
+struct Retry;
+
+struct Node<K, V> {
+    ///None only for the holder, which sits above the root
+    key: Option<K>,
+    ///null for a routing node, whose key has been removed
+    value: Atomic<V>,
+    height: AtomicI32,
+    version: AtomicU64,
+    parent: Atomic<Node<K,V>>,
+    children: [Atomic<Node<K,V>>; 2],
+    lock: Mutex<()>
+}
+
+///what a node needs after its children changed
+#[derive(PartialEq)]
+enum Condition {
+    Nothing,
+    Unlink,
+    Rebalance,
+    Height(i32)
+}
+
+///gives the node behind a pointer loaded while the guard was pinned
+fn node<'g, K, V>(ptr: Shared<'g, Node<K,V>>) -> Option<&'g Node<K,V>> {
+    //nodes are only freed through `defer_destroy` once they are unlinked, and an unlinked
+    //node only points at nodes that were still linked when it was unlinked, so anything
+    //reachable under a guard stays allocated until that guard is dropped
+    unsafe { ptr.as_ref() }
+}
+
+fn shared<'g, K, V>(node: &'g Node<K,V>) -> Shared<'g, Node<K,V>> {
+    Shared::from(node as *const Node<K,V>)
+}
+
+fn same<K, V>(ptr: Shared<'_, Node<K,V>>, node: &Node<K,V>) -> bool {
+    ::std::ptr::eq(ptr.as_raw(), node)
+}
+
+fn height<K, V>(ptr: Shared<'_, Node<K,V>>) -> i32 {
+    node(ptr).map_or(0, |node| node.height.load(SeqCst))
+}
+
+fn shrinking_or_unlinked(version: u64) -> bool {
+    version & (SHRINKING | UNLINKED) != 0
+}
+
+///gives back a value that was just taken out of the tree, freeing it once no reader can see it
+fn retire<'g, V>(value: Shared<'g, V>, guard: &'g Guard) -> Option<&'g V> {
+    if value.is_null() {
+        return None;
+    }
+    //the value is no longer reachable from the tree, so only threads pinned now can see it
+    unsafe {
+        guard.defer_destroy(value);
+        value.as_ref()
+    }
+}
+
+impl<K, V> Node<K,V> {
+
+    fn new(key: Option<K>, value: Option<V>) -> Self {
+        Node {
+            key,
+            value: value.map_or(Atomic::null(), Atomic::new),
+            height: AtomicI32::new(1),
+            version: AtomicU64::new(0),
+            parent: Atomic::null(),
+            children: [Atomic::null(), Atomic::null()],
+            lock: Mutex::new(())
+        }
+    }
+
+    fn key(&self) -> &K {
+        self.key.as_ref().expect("the holder has no key")
+    }
+
+    fn child<'g>(&self, dir: usize, guard: &'g Guard) -> Shared<'g, Node<K,V>> {
+        self.children[dir].load(SeqCst, guard)
+    }
+
+    fn set_child(&self, dir: usize, child: Shared<'_, Node<K,V>>) {
+        self.children[dir].store(child, SeqCst);
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(SeqCst)
+    }
+
+    fn is_unlinked(&self) -> bool {
+        self.version() & UNLINKED != 0
+    }
+
+    fn value<'g>(&self, guard: &'g Guard) -> Option<&'g V> {
+        //values are retired only once they have been taken out of their node
+        unsafe { self.value.load(SeqCst, guard).as_ref() }
+    }
+
+    fn has_value(&self, guard: &Guard) -> bool {
+        !self.value.load(SeqCst, guard).is_null()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        //the lock guards no data, so a panic while holding it leaves nothing poisoned
+        self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    ///waits for a rotation moving this node down to finish
+    fn wait_until_not_shrinking(&self) {
+        for _ in 0..SPINS {
+            if self.version() & SHRINKING == 0 {
+                return;
+            }
+            thread::yield_now();
+        }
+        //rotations hold the node's lock while it is shrinking
+        drop(self.lock());
+    }
+}
+
+impl<K: Ord, V> Node<K,V> {
+
+    fn condition(&self, guard: &Guard) -> Condition {
+        let left = self.child(LEFT, guard);
+        let right = self.child(RIGHT, guard);
+        if (left.is_null() || right.is_null()) && !self.has_value(guard) {
+            return Condition::Unlink;
+        }
+        let (left, right) = (height(left), height(right));
+        let balance = left - right;
+        let replacement = 1 + max(left, right);
+        if !(-1..=1).contains(&balance) {
+            Condition::Rebalance
+        } else if replacement != self.height.load(SeqCst) {
+            Condition::Height(replacement)
+        } else {
+            Condition::Nothing
+        }
+    }
+
+    ///fixes this node's height, with its lock held. gives the next node that may need fixing
+    fn fix_height_nl<'g>(&'g self, guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        match self.condition(guard) {
+            Condition::Rebalance | Condition::Unlink => Some(self),
+            Condition::Nothing => None,
+            Condition::Height(height) => {
+                self.height.store(height, SeqCst);
+                node(self.parent.load(SeqCst, guard))
+            }
+        }
+    }
+
+    ///walks up from a damaged node, fixing heights, unlinking routing nodes and rotating until
+    ///nothing above needs it
+    fn fix_height_and_rebalance<'g>(mut damaged: Option<&'g Node<K,V>>, guard: &'g Guard) {
+        //nodes a rebalance left behind while it fixed something below them first: its parent,
+        //whose child changed, and the node itself if it was not the one handed back
+        let mut pending = Vec::new();
+        while let Some(node_) = damaged.or_else(|| pending.pop()) {
+            damaged = None;
+            //the holder has no parent and never needs fixing, and an unlinked node's parent
+            //was fixed by whoever unlinked it
+            let parent = match node(node_.parent.load(SeqCst, guard)) {
+                Some(parent) if !node_.is_unlinked() => parent,
+                _ => continue
+            };
+            match node_.condition(guard) {
+                Condition::Nothing | Condition::Height(_) => {
+                    //checked again under the lock, so that a thread fixing this node's height
+                    //from stale child heights is always followed by one that sees the new ones
+                    let _lock = node_.lock();
+                    damaged = node_.fix_height_nl(guard);
+                },
+                Condition::Unlink | Condition::Rebalance => {
+                    let _parent_lock = parent.lock();
+                    if !parent.is_unlinked() && same(node_.parent.load(SeqCst, guard), parent) {
+                        let _lock = node_.lock();
+                        damaged = node_.rebalance_nl(parent, guard);
+                        if let Some(next) = damaged {
+                            pending.push(parent);
+                            if !::std::ptr::eq(next, node_) {
+                                pending.push(node_);
+                            }
+                        }
+                    } else {
+                        //the node moved, so try it again
+                        damaged = Some(node_);
+                    }
+                }
+            }
+        }
+    }
+
+    ///removes a node with at most one child, with both locks held
+    fn attempt_unlink_nl(&self, parent: &Node<K,V>, guard: &Guard) -> bool {
+        let dir = if same(parent.child(LEFT, guard), self) {
+            LEFT
+        } else if same(parent.child(RIGHT, guard), self) {
+            RIGHT
+        } else {
+            return false;
+        };
+        let left = self.child(LEFT, guard);
+        let right = self.child(RIGHT, guard);
+        if !left.is_null() && !right.is_null() {
+            return false;
+        }
+        let splice = if left.is_null() { right } else { left };
+        //locked for the same reason as the nodes a rotation moves
+        let _splice_lock = node(splice).map(|splice| splice.lock());
+        parent.set_child(dir, splice);
+        if let Some(splice) = node(splice) {
+            splice.parent.store(shared(parent), SeqCst);
+        }
+        self.version.store(UNLINKED, SeqCst);
+        self.value.store(Shared::null(), SeqCst);
+        //nothing linked points at this node any more
+        unsafe { guard.defer_destroy(shared(self)) };
+        true
+    }
+
+    ///rebalances this node, with its lock and its parent's held
+    fn rebalance_nl<'g>(&'g self, parent: &'g Node<K,V>, guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        let left = self.child(LEFT, guard);
+        let right = self.child(RIGHT, guard);
+        if (left.is_null() || right.is_null()) && !self.has_value(guard) {
+            return if self.attempt_unlink_nl(parent, guard) { parent.fix_height_nl(guard) } else { Some(self) };
+        }
+        let (left_height, right_height) = (height(left), height(right));
+        let replacement = 1 + max(left_height, right_height);
+        let balance = left_height - right_height;
+        if balance > 1 {
+            self.rebalance_to_nl(parent, LEFT, node(left).unwrap(), right_height, guard)
+        } else if balance < -1 {
+            self.rebalance_to_nl(parent, RIGHT, node(right).unwrap(), left_height, guard)
+        } else if replacement != self.height.load(SeqCst) {
+            self.height.store(replacement, SeqCst);
+            parent.fix_height_nl(guard)
+        } else {
+            None
+        }
+    }
+
+    ///rotates the heavy child, on side `dir`, up over this node, or does a double rotation if
+    ///its inner grandchild is the taller one. holds this node's and its parent's locks
+    fn rebalance_to_nl<'g>(&'g self, parent: &'g Node<K,V>, dir: usize, child: &'g Node<K,V>,
+                           other_height: i32, guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        let _child_lock = child.lock();
+        if child.height.load(SeqCst) - other_height <= 1 {
+            //someone else already fixed it
+            return Some(self);
+        }
+        self.rotate_up_nl(parent, dir, child, other_height, guard)
+    }
+
+    ///does the rotation for `rebalance_to_nl`, with the child's lock held as well
+    fn rotate_up_nl<'g>(&'g self, parent: &'g Node<K,V>, dir: usize, child: &'g Node<K,V>,
+                        other_height: i32, guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        let other = 1 - dir;
+        let outer_height = height(child.child(dir, guard));
+        let inner = match node(child.child(other, guard)) {
+            None => return self.rotate_nl(parent, dir, child, other_height, outer_height, Shared::null(), 0, guard),
+            Some(inner) => inner
+        };
+        {
+            //every node whose parent a rotation changes is locked, so that a thread fixing its
+            //height either finishes before the rotation reads it, or carries on up from its
+            //new parent
+            let _inner_lock = inner.lock();
+            let inner_height = inner.height.load(SeqCst);
+            if outer_height >= inner_height {
+                return self.rotate_nl(parent, dir, child, other_height, outer_height, shared(inner), inner_height, guard);
+            }
+            let grandchildren = [node(inner.child(LEFT, guard)), node(inner.child(RIGHT, guard))];
+            let _grandchild_locks: Vec<_> = grandchildren.iter().flatten().map(|node| node.lock()).collect();
+            let inner_outer_height = height(inner.child(dir, guard));
+            let balance = outer_height - inner_outer_height;
+            if (-1..=1).contains(&balance)
+                && !((outer_height == 0 || inner_outer_height == 0) && !child.has_value(guard)) {
+                return self.rotate_over_nl(parent, dir, child, other_height, outer_height, inner, inner_outer_height, guard);
+            }
+        }
+        //the child would be left unbalanced by a double rotation, so rotate the inner grandchild
+        //up first, even though the child may be balanced on its own; the damage returned leads
+        //back up to this node
+        let _inner_lock = inner.lock();
+        child.rotate_up_nl(self, other, inner, outer_height, guard)
+    }
+
+    ///puts the replacement where this node was under its parent
+    fn replace_in_parent(&self, parent: &Node<K,V>, replacement: &Node<K,V>, guard: &Guard) {
+        let dir = if same(parent.child(LEFT, guard), self) { LEFT } else { RIGHT };
+        parent.set_child(dir, shared(replacement));
+        replacement.parent.store(shared(parent), SeqCst);
+    }
+
+    ///a single rotation lifting the child on side `dir` over this node
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_nl<'g>(&'g self, parent: &'g Node<K,V>, dir: usize, child: &'g Node<K,V>, other_height: i32,
+                     outer_height: i32, inner: Shared<'g, Node<K,V>>, inner_height: i32,
+                     guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        let other = 1 - dir;
+        let version = self.version();
+        self.version.store(version | SHRINKING, SeqCst);
+
+        self.set_child(dir, inner);
+        if let Some(inner) = node(inner) {
+            inner.parent.store(shared(self), SeqCst);
+        }
+        child.set_child(other, shared(self));
+        self.parent.store(shared(child), SeqCst);
+        self.replace_in_parent(parent, child, guard);
+
+        let self_height = 1 + max(inner_height, other_height);
+        self.height.store(self_height, SeqCst);
+        child.height.store(1 + max(outer_height, self_height), SeqCst);
+        self.version.store(version + SHRINK_COUNT, SeqCst);
+
+        //hand back whichever node is still damaged
+        let balance = inner_height - other_height;
+        if !(-1..=1).contains(&balance) {
+            return Some(self);
+        }
+        if (inner.is_null() || other_height == 0) && !self.has_value(guard) {
+            return Some(self);
+        }
+        let balance = outer_height - self_height;
+        if !(-1..=1).contains(&balance) || (outer_height == 0 && !child.has_value(guard)) {
+            return Some(child);
+        }
+        parent.fix_height_nl(guard)
+    }
+
+    ///a double rotation lifting the inner grandchild on side `dir` over both this node and
+    ///its child
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_over_nl<'g>(&'g self, parent: &'g Node<K,V>, dir: usize, child: &'g Node<K,V>, other_height: i32,
+                          outer_height: i32, inner: &'g Node<K,V>, inner_outer_height: i32,
+                          guard: &'g Guard) -> Option<&'g Node<K,V>> {
+        let other = 1 - dir;
+        let version = self.version();
+        let child_version = child.version();
+        let inner_outer = inner.child(dir, guard);
+        let inner_inner = inner.child(other, guard);
+        let inner_inner_height = height(inner_inner);
+        self.version.store(version | SHRINKING, SeqCst);
+        child.version.store(child_version | SHRINKING, SeqCst);
+
+        self.set_child(dir, inner_inner);
+        if let Some(inner_inner) = node(inner_inner) {
+            inner_inner.parent.store(shared(self), SeqCst);
+        }
+        child.set_child(other, inner_outer);
+        if let Some(inner_outer) = node(inner_outer) {
+            inner_outer.parent.store(shared(child), SeqCst);
+        }
+        inner.set_child(dir, shared(child));
+        child.parent.store(shared(inner), SeqCst);
+        inner.set_child(other, shared(self));
+        self.parent.store(shared(inner), SeqCst);
+        self.replace_in_parent(parent, inner, guard);
+
+        let self_height = 1 + max(inner_inner_height, other_height);
+        self.height.store(self_height, SeqCst);
+        let child_height = 1 + max(outer_height, inner_outer_height);
+        child.height.store(child_height, SeqCst);
+        inner.height.store(1 + max(child_height, self_height), SeqCst);
+        self.version.store(version + SHRINK_COUNT, SeqCst);
+        child.version.store(child_version + SHRINK_COUNT, SeqCst);
+
+        let balance = inner_inner_height - other_height;
+        if !(-1..=1).contains(&balance) {
+            return Some(self);
+        }
+        if (inner_inner.is_null() || other_height == 0) && !self.has_value(guard) {
+            return Some(self);
+        }
+        let balance = child_height - self_height;
+        if !(-1..=1).contains(&balance) {
+            return Some(inner);
+        }
+        parent.fix_height_nl(guard)
+    }
+}
+
+/// A sorted map based on an AVL tree that many threads can use at once through a shared
+/// reference, in the style of Bronson et al.'s optimistic concurrent AVL tree.
+///
+/// Reads take no locks and writes lock only the few nodes they change, so threads working on
+/// different parts of the tree rarely wait for each other. `insert`, `get`, `remove` and
+/// `contains_key` are linearizable. `range` is weakly consistent: it gives every entry that
+/// was in the range for the whole call, in sorted order, and may or may not include entries
+/// that were inserted or removed while it ran.
+///
+/// Removed nodes and replaced values are freed later, by whichever thread the collector runs
+/// them on, so keys and values must be `Send + 'static`:
+///
+///  ```compile_fail
+///  use std::rc::Rc;
+///  use avltree_map::concurrent::ConcurrentAvlTree;
+///
+///  let tree = ConcurrentAvlTree::new();
+///  tree.insert(1, Rc::new(1));
+///  ```
+///
+/// #Examples
+///
+///  ```
+///  use std::sync::Arc;
+///  use std::thread;
+///  use avltree_map::concurrent::ConcurrentAvlTree;
+///
+///  let tree = Arc::new(ConcurrentAvlTree::new());
+///  let threads: Vec<_> = (0..4).map(|t| {
+///      let tree = tree.clone();
+///      thread::spawn(move || {
+///          for i in 0..100 {
+///              tree.insert(i * 4 + t, t);
+///          }
+///      })
+///  }).collect();
+///  for thread in threads {
+///      thread.join().unwrap();
+///  }
+///
+///  assert_eq!(tree.get(&7), Some(3));
+///  assert_eq!(tree.range(Some(&10), Some(&13)), vec![(10, 2), (11, 3), (12, 0), (13, 1)]);
+///  ```
+pub struct ConcurrentAvlTree<K: Ord, V> {
+    ///sits above the root, which is its right child. it is never rotated or unlinked, so
+    ///every search can start from it
+    holder: Box<Node<K,V>>
+}
+
+impl<K: Ord + Send + 'static, V: Clone + Send + 'static> ConcurrentAvlTree<K,V> {
+
+    /// Creates a new empty ConcurrentAvlTree
+    pub fn new() -> Self {
+        let holder = Node::new(None, None);
+        holder.height.store(0, SeqCst);
+        ConcurrentAvlTree { holder: Box::new(holder) }
+    }
+
+    /// Returns None if the key is not present, or a clone of its value if it is.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  tree.insert(1, "a");
+    ///  assert_eq!(tree.get(&1), Some("a"));
+    ///  assert_eq!(tree.get(&2), None);
+    ///  ```
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = &epoch::pin();
+        self.find(key, guard).cloned()
+    }
+
+    /// Checks if the key is present
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  tree.insert(1, "a");
+    ///  assert!(tree.contains_key(&1));
+    ///  assert!(!tree.contains_key(&2));
+    ///  ```
+    pub fn contains_key(&self, key: &K) -> bool {
+        let guard = &epoch::pin();
+        self.find(key, guard).is_some()
+    }
+
+    fn find<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        loop {
+            let holder = &*self.holder;
+            if let Ok(found) = Self::attempt_get(key, holder, RIGHT, holder.version(), guard) {
+                return found;
+            }
+        }
+    }
+
+    ///looks for the key below the child on side `dir`, given the version the node had when
+    ///the search reached it
+    fn attempt_get<'g>(key: &K, node_: &'g Node<K,V>, dir: usize, version: u64, guard: &'g Guard) -> Result<Option<&'g V>, Retry> {
+        loop {
+            let child = node_.child(dir, guard);
+            if node_.version() != version {
+                return Err(Retry);
+            }
+            let child = match node(child) {
+                None => return Ok(None),
+                Some(child) => child
+            };
+            let order = key.cmp(child.key());
+            if order == Ordering::Equal {
+                return Ok(child.value(guard));
+            }
+            let child_version = child.version();
+            if shrinking_or_unlinked(child_version) {
+                child.wait_until_not_shrinking();
+            } else if same(node_.child(dir, guard), child) {
+                if node_.version() != version {
+                    return Err(Retry);
+                }
+                let dir = if order == Ordering::Less { LEFT } else { RIGHT };
+                if let Ok(found) = Self::attempt_get(key, child, dir, child_version, guard) {
+                    return Ok(found);
+                }
+            }
+            //otherwise the child changed, so read it again
+        }
+    }
+
+    /// Inserts a key,value pair into the tree. Returns None if the key was not present in the
+    /// tree already. If the key was present, then the key is updated with the new value and a
+    /// clone of the old value is returned.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  assert_eq!(tree.insert(37, "a"), None);
+    ///  assert_eq!(tree.insert(37, "b"), Some("a"));
+    ///  assert_eq!(tree.get(&37), Some("b"));
+    ///  ```
+    pub fn insert(&self, key: K, val: V) -> Option<V> {
+        let guard = &epoch::pin();
+        let new = Owned::new(Node::new(Some(key), Some(val))).into_shared(guard);
+        let new_node = node(new).unwrap();
+        let old = self.update(new_node.key(), Some(new_node), guard).cloned();
+        //a node is given a parent just before it is linked in. one that never was gave its
+        //value to the existing node for its key, and no other thread has seen it
+        if new_node.parent.load(SeqCst, guard).is_null() {
+            drop(unsafe { new.into_owned() });
+        }
+        old
+    }
+
+    /// Removes a key from the tree, returning a clone of its value if the key was present
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  tree.insert(1, "a");
+    ///  assert_eq!(tree.remove(&1), Some("a"));
+    ///  assert_eq!(tree.remove(&1), None);
+    ///  ```
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let guard = &epoch::pin();
+        self.update(key, None, guard).cloned()
+    }
+
+    ///inserts the new node's entry, or removes the key when there is no new node. gives the
+    ///old value
+    fn update<'g>(&'g self, key: &K, new: Option<&'g Node<K,V>>, guard: &'g Guard) -> Option<&'g V> {
+        loop {
+            let holder = &*self.holder;
+            if let Ok(old) = Self::attempt_update(key, new, holder, RIGHT, holder.version(), guard) {
+                return old;
+            }
+        }
+    }
+
+    fn attempt_update<'g>(key: &K, new: Option<&'g Node<K,V>>, node_: &'g Node<K,V>, dir: usize, version: u64,
+                          guard: &'g Guard) -> Result<Option<&'g V>, Retry> {
+        loop {
+            let child = node_.child(dir, guard);
+            if node_.version() != version {
+                return Err(Retry);
+            }
+            let child = match node(child) {
+                Some(child) => child,
+                None => {
+                    let new = match new {
+                        None => return Ok(None),
+                        Some(new) => new
+                    };
+                    let damaged = {
+                        let _lock = node_.lock();
+                        if node_.version() != version {
+                            return Err(Retry);
+                        }
+                        if !node_.child(dir, guard).is_null() {
+                            //lost a race with another insert here
+                            continue;
+                        }
+                        new.parent.store(shared(node_), SeqCst);
+                        node_.set_child(dir, shared(new));
+                        node_.fix_height_nl(guard)
+                    };
+                    Node::fix_height_and_rebalance(damaged, guard);
+                    return Ok(None);
+                }
+            };
+            let order = key.cmp(child.key());
+            if order == Ordering::Equal {
+                return Self::attempt_node_update(new, node_, child, guard);
+            }
+            let child_version = child.version();
+            if shrinking_or_unlinked(child_version) {
+                child.wait_until_not_shrinking();
+            } else if same(node_.child(dir, guard), child) {
+                if node_.version() != version {
+                    return Err(Retry);
+                }
+                let dir = if order == Ordering::Less { LEFT } else { RIGHT };
+                if let Ok(old) = Self::attempt_update(key, new, child, dir, child_version, guard) {
+                    return Ok(old);
+                }
+            }
+        }
+    }
+
+    ///updates the node found for the key
+    fn attempt_node_update<'g>(new: Option<&'g Node<K,V>>, parent: &'g Node<K,V>, node_: &'g Node<K,V>,
+                               guard: &'g Guard) -> Result<Option<&'g V>, Retry> {
+        if let Some(new) = new {
+            let _lock = node_.lock();
+            if node_.is_unlinked() {
+                return Err(Retry);
+            }
+            let value = new.value.swap(Shared::null(), SeqCst, guard);
+            return Ok(retire(node_.value.swap(value, SeqCst, guard), guard));
+        }
+
+        if !node_.has_value(guard) {
+            return Ok(None);
+        }
+        if node_.child(LEFT, guard).is_null() || node_.child(RIGHT, guard).is_null() {
+            //the node can be unlinked along with its value
+            let (old, damaged) = {
+                let _parent_lock = parent.lock();
+                if parent.is_unlinked() || !same(node_.parent.load(SeqCst, guard), parent) {
+                    return Err(Retry);
+                }
+                let _lock = node_.lock();
+                let old = node_.value.load(SeqCst, guard);
+                if old.is_null() {
+                    return Ok(None);
+                }
+                if !node_.attempt_unlink_nl(parent, guard) {
+                    return Err(Retry);
+                }
+                (old, parent.fix_height_nl(guard))
+            };
+            Node::fix_height_and_rebalance(damaged, guard);
+            Ok(retire(old, guard))
+        } else {
+            //the node has to stay to route searches, so only its value goes
+            let _lock = node_.lock();
+            if node_.is_unlinked() {
+                return Err(Retry);
+            }
+            if node_.child(LEFT, guard).is_null() || node_.child(RIGHT, guard).is_null() {
+                //it lost a child, so it should be unlinked instead
+                return Err(Retry);
+            }
+            Ok(retire(node_.value.swap(Shared::null(), SeqCst, guard), guard))
+        }
+    }
+}
+
+impl<K: Ord + Send + 'static, V: Clone + Send + 'static> ConcurrentAvlTree<K,V> {
+
+    /// Checks if the tree is empty
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  assert!(tree.is_empty());
+    ///  tree.insert(1, "a");
+    ///  assert!(!tree.is_empty());
+    ///  ```
+    pub fn is_empty(&self) -> bool {
+        self.entries(None, None, 1, &epoch::pin()).is_empty()
+    }
+
+    /// Gives clones of the key-value pairs in the tree that fall within the given start and
+    /// end points (inclusive) in sorted order. None leaves that side unbounded.
+    ///
+    /// The range is read without locks while other threads keep working, so it is weakly
+    /// consistent: entries that were in the range for the whole call are always included, and
+    /// entries inserted or removed during the call may or may not be.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::concurrent::ConcurrentAvlTree;
+    ///
+    ///  let tree = ConcurrentAvlTree::new();
+    ///  for i in 0..10 {
+    ///      tree.insert(i, i * 10);
+    ///  }
+    ///  assert_eq!(tree.range(Some(&3), Some(&5)), vec![(3, 30), (4, 40), (5, 50)]);
+    ///  assert_eq!(tree.range(Some(&8), None), vec![(8, 80), (9, 90)]);
+    ///  ```
+    pub fn range(&self, start: Option<&K>, end: Option<&K>) -> Vec<(K,V)> where K: Clone {
+        let guard = &epoch::pin();
+        self.entries(start, end, usize::MAX, guard).into_iter().map(|(key, val)| (key.clone(), val.clone())).collect()
+    }
+
+    ///gives at most `limit` entries in the range, borrowed for as long as the guard is pinned
+    fn entries<'g>(&'g self, start: Option<&K>, end: Option<&K>, limit: usize, guard: &'g Guard) -> Vec<(&'g K, &'g V)> {
+        let mut out = Vec::new();
+        let holder = &*self.holder;
+        while Self::attempt_range_child(holder, holder.version(), RIGHT, start, end, limit, &mut out, guard).is_err() {
+            out.clear();
+        }
+        out
+    }
+
+    ///collects the range from below the child on side `dir`. on a retry, whatever was added
+    ///from that child is thrown away before reading it again
+    #[allow(clippy::too_many_arguments)]
+    fn attempt_range_child<'g>(node_: &'g Node<K,V>, version: u64, dir: usize, start: Option<&K>, end: Option<&K>,
+                               limit: usize, out: &mut Vec<(&'g K, &'g V)>, guard: &'g Guard) -> Result<(), Retry> {
+        loop {
+            let child = node_.child(dir, guard);
+            if node_.version() != version {
+                return Err(Retry);
+            }
+            let child = match node(child) {
+                None => return Ok(()),
+                Some(child) => child
+            };
+            let child_version = child.version();
+            if shrinking_or_unlinked(child_version) {
+                child.wait_until_not_shrinking();
+            } else if same(node_.child(dir, guard), child) {
+                if node_.version() != version {
+                    return Err(Retry);
+                }
+                let mark = out.len();
+                if Self::attempt_range(child, child_version, start, end, limit, out, guard).is_ok() {
+                    return Ok(());
+                }
+                out.truncate(mark);
+            }
+        }
+    }
+
+    fn attempt_range<'g>(node_: &'g Node<K,V>, version: u64, start: Option<&K>, end: Option<&K>, limit: usize,
+                         out: &mut Vec<(&'g K, &'g V)>, guard: &'g Guard) -> Result<(), Retry> {
+        let key = node_.key();
+        if start.is_none_or(|start| key > start) {
+            Self::attempt_range_child(node_, version, LEFT, start, end, limit, out, guard)?;
+        }
+        if out.len() >= limit {
+            return Ok(());
+        }
+        if start.is_none_or(|start| key >= start) && end.is_none_or(|end| key <= end) {
+            if let Some(val) = node_.value(guard) {
+                out.push((key, val));
+            }
+        }
+        if out.len() < limit && end.is_none_or(|end| key < end) {
+            Self::attempt_range_child(node_, version, RIGHT, start, end, limit, out, guard)?;
+        }
+        //a rotation that moved this node down partway through may have made this subtree give
+        //keys that belong above it
+        if node_.version() != version {
+            return Err(Retry);
+        }
+        Ok(())
+    }
+}
+
+impl<K: Ord + Send + 'static, V: Clone + Send + 'static> Default for ConcurrentAvlTree<K,V> {
+    fn default() -> Self {
+        ConcurrentAvlTree::new()
+    }
+}
+
+impl<K: Ord, V> Drop for ConcurrentAvlTree<K,V> {
+    fn drop(&mut self) {
+        //nothing else can be using the tree, and unlinked nodes were already handed to the
+        //collector, so everything still linked belongs to the tree alone
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut stack = vec![self.holder.child(RIGHT, guard)];
+            while let Some(ptr) = stack.pop() {
+                if ptr.is_null() {
+                    continue;
+                }
+                let owned = ptr.into_owned();
+                stack.push(owned.child(LEFT, guard));
+                stack.push(owned.child(RIGHT, guard));
+                let value = owned.value.load(SeqCst, guard);
+                if !value.is_null() {
+                    drop(value.into_owned());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::sync::{Arc, Barrier};
+    use std::sync::atomic::AtomicU64;
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::thread;
+
+    use crossbeam_epoch as epoch;
+    use ordered_map::conformance::XorShift;
+    use super::{node, ConcurrentAvlTree, Node, LEFT, RIGHT};
+
+    ///checks that a tree nobody is changing is a strict AVL tree with correct parent links and
+    ///no routing nodes left that could have been unlinked. gives the keys that have values
+    fn check<V: Clone>(tree: &mut ConcurrentAvlTree<u64, V>) -> Vec<u64> {
+        fn walk<V>(node_: &Node<u64, V>, keys: &mut Vec<u64>, guard: &epoch::Guard) -> i32 {
+            let mut heights = [0; 2];
+            for &dir in [LEFT, RIGHT].iter() {
+                if dir == RIGHT && node_.has_value(guard) {
+                    keys.push(*node_.key());
+                }
+                if let Some(child) = node(node_.child(dir, guard)) {
+                    assert!(::std::ptr::eq(node(child.parent.load(SeqCst, guard)).unwrap(), node_), "bad parent link");
+                    heights[dir] = walk(child, keys, guard);
+                }
+            }
+            let height = 1 + heights[LEFT].max(heights[RIGHT]);
+            assert_eq!(node_.height.load(SeqCst), height, "wrong height at {}", node_.key());
+            assert!((heights[LEFT] - heights[RIGHT]).abs() <= 1, "unbalanced at {}", node_.key());
+            assert!(node_.has_value(guard) || heights[LEFT] > 0 && heights[RIGHT] > 0, "routing node {} not unlinked", node_.key());
+            height
+        }
+        let guard = &epoch::pin();
+        let mut keys = Vec::new();
+        if let Some(root) = node(tree.holder.child(RIGHT, guard)) {
+            walk(root, &mut keys, guard);
+        }
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "keys out of order");
+        keys
+    }
+
+    #[test]
+    fn test_single_thread() {
+        let mut tree = ConcurrentAvlTree::new();
+        let mut model = BTreeMap::new();
+        let mut rng = XorShift::new(7);
+        for step in 0..20000 {
+            let key = rng.next(500);
+            match rng.next(10) {
+                0..=4 => assert_eq!(tree.insert(key, step), model.insert(key, step)),
+                5..=7 => assert_eq!(tree.remove(&key), model.remove(&key)),
+                8 => assert_eq!(tree.get(&key), model.get(&key).cloned()),
+                _ => {
+                    let end = key + rng.next(40);
+                    let expected: Vec<_> = model.range(key..=end).map(|(k, v)| (*k, *v)).collect();
+                    assert_eq!(tree.range(Some(&key), Some(&end)), expected);
+                }
+            }
+        }
+        let keys: Vec<_> = model.keys().cloned().collect();
+        assert_eq!(check(&mut tree), keys);
+        assert_eq!(tree.range(None, None), model.into_iter().collect::<Vec<_>>());
+
+        for key in keys {
+            tree.remove(&key);
+        }
+        assert!(tree.is_empty());
+        assert!(check(&mut tree).is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_stress() {
+        const THREADS: u64 = 8;
+        const KEYS: u64 = 2000;
+        let tree = Arc::new(ConcurrentAvlTree::new());
+        let writers: Vec<_> = (0..THREADS).map(|t| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                //each thread owns the keys equal to its number mod THREADS, so it knows
+                //exactly what the tree must hold for them
+                let mut model = BTreeMap::new();
+                let mut rng = XorShift::new(t + 1);
+                for step in 0..20000 {
+                    let key = rng.next(KEYS / THREADS) * THREADS + t;
+                    match rng.next(3) {
+                        0 | 1 => assert_eq!(tree.insert(key, step), model.insert(key, step)),
+                        _ => assert_eq!(tree.remove(&key), model.remove(&key))
+                    }
+                    if step % 64 == 0 {
+                        assert_eq!(tree.get(&key), model.get(&key).cloned());
+                    }
+                }
+                model
+            })
+        }).collect();
+        let readers: Vec<_> = (0..2).map(|r| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                let mut rng = XorShift::new(r + 100);
+                for _ in 0..500 {
+                    let start = rng.next(KEYS);
+                    let end = start + rng.next(200);
+                    let found = tree.range(Some(&start), Some(&end));
+                    assert!(found.windows(2).all(|pair| pair[0].0 < pair[1].0));
+                    assert!(found.iter().all(|&(key, _)| key >= start && key <= end));
+                }
+            })
+        }).collect();
+
+        let mut expected = BTreeMap::new();
+        for writer in writers {
+            expected.extend(writer.join().unwrap());
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let mut tree = Arc::try_unwrap(tree).ok().unwrap();
+        assert_eq!(check(&mut tree), expected.keys().cloned().collect::<Vec<_>>());
+        assert_eq!(tree.range(None, None), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Op {
+        Insert(u64),
+        Remove,
+        Get
+    }
+
+    ///one finished operation on a key, with the clock readings from just before it was called
+    ///and just after it returned
+    #[derive(Clone, Copy, Debug)]
+    struct Event {
+        op: Op,
+        result: Option<u64>,
+        call: u64,
+        ret: u64
+    }
+
+    ///searches for an order of the events that respects real time and in which every result
+    ///matches a sequential map
+    fn linearizable(events: &[Event], state: Option<u64>, done: u64, failed: &mut HashSet<(u64, Option<u64>)>) -> bool {
+        if done.count_ones() as usize == events.len() {
+            return true;
+        }
+        if failed.contains(&(done, state)) {
+            return false;
+        }
+        let pending = |i: usize| done & (1 << i) == 0;
+        for (i, event) in events.iter().enumerate().filter(|&(i, _)| pending(i)) {
+            //an event can go next only if nothing still pending returned before it was called
+            if events.iter().enumerate().any(|(j, other)| pending(j) && other.ret < event.call) {
+                continue;
+            }
+            if event.result != state {
+                continue;
+            }
+            let next = match event.op {
+                Op::Insert(val) => Some(val),
+                Op::Remove => None,
+                Op::Get => state
+            };
+            if linearizable(events, next, done | (1 << i), failed) {
+                return true;
+            }
+        }
+        failed.insert((done, state));
+        false
+    }
+
+    #[test]
+    fn test_linearizable() {
+        const THREADS: u64 = 4;
+        const OPS: u64 = 12;
+        const KEYS: u64 = 3;
+        for round in 0..300 {
+            let tree = Arc::new(ConcurrentAvlTree::new());
+            //a few neighbours so that operations on the tested keys also rotate
+            for key in 0..8 {
+                tree.insert(key * 10 + 100, 0);
+            }
+            let clock = Arc::new(AtomicU64::new(0));
+            let barrier = Arc::new(Barrier::new(THREADS as usize));
+            let threads: Vec<_> = (0..THREADS).map(|t| {
+                let (tree, clock, barrier) = (tree.clone(), clock.clone(), barrier.clone());
+                thread::spawn(move || {
+                    let mut rng = XorShift::new(round * THREADS + t + 1);
+                    let mut history = Vec::new();
+                    barrier.wait();
+                    for i in 0..OPS {
+                        let key = rng.next(KEYS) * 50 + 95;
+                        let op = match rng.next(3) {
+                            0 => Op::Insert(t * 1000 + i + 1),
+                            1 => Op::Remove,
+                            _ => Op::Get
+                        };
+                        let call = clock.fetch_add(1, SeqCst);
+                        let result = match op {
+                            Op::Insert(val) => tree.insert(key, val),
+                            Op::Remove => tree.remove(&key),
+                            Op::Get => tree.get(&key)
+                        };
+                        let ret = clock.fetch_add(1, SeqCst);
+                        history.push((key, Event { op, result, call, ret }));
+                    }
+                    history
+                })
+            }).collect();
+
+            let mut by_key: HashMap<u64, Vec<Event>> = HashMap::new();
+            for thread in threads {
+                for (key, event) in thread.join().unwrap() {
+                    by_key.entry(key).or_default().push(event);
+                }
+            }
+            //a map is linearizable exactly when the history of every key is
+            for (key, events) in by_key {
+                assert!(linearizable(&events, None, 0, &mut HashSet::new()),
+                        "round {}: history of key {} is not linearizable: {:?}", round, key, events);
+            }
+            check(&mut Arc::try_unwrap(tree).ok().unwrap());
+        }
+    }
+}
@@ -0,0 +1,669 @@
+//! Cursors that walk an AvlTree one entry at a time, and edit it in place.
+//!
+//! A cursor keeps the path from the root down to its entry, so stepping to a neighbouring
+//! entry only walks the few links between the two, O(1) on average, rather than searching
+//! from the root again. A cursor can also rest on a "ghost" position between the last entry
+//! and the first, where there is no entry: stepping forwards from the last entry reaches it,
+//! and stepping forwards again reaches the first.
+//!
+//! `CursorMut` records its path as the left and right turns taken from the root. When an edit
+//! rebalances the tree, each rotation on the path is replayed on those turns, so the cursor
+//! stays on the same entry without having to search for it.
+use core::cmp::Ord;
+use core::ptr::NonNull;
+use alloc::vec::Vec;
+
+use {AvlNode, AvlTree, Turn};
+use summary::Summary;
+
+///a way of reaching a node's children, so that the same walking code serves shared and
+///mutable cursors
+trait Link<K: Ord, V, S: Summary<K,V>>: Copy {
+    fn node(&self) -> &AvlNode<K,V,S>;
+
+    fn child(self, right: bool) -> Option<Self>;
+}
+
+impl<K: Ord, V, S: Summary<K,V>> Link<K,V,S> for &AvlNode<K,V,S> {
+    fn node(&self) -> &AvlNode<K,V,S> {
+        self
+    }
+
+    fn child(self, right: bool) -> Option<Self> {
+        let subtree = if right { &self.right } else { &self.left };
+        subtree.0.as_deref()
+    }
+}
+
+///a node reached through the tree a `CursorMut` borrows mutably
+struct NodePtr<K: Ord, V, S: Summary<K,V>>(NonNull<AvlNode<K,V,S>>);
+
+impl<K: Ord, V, S: Summary<K,V>> Clone for NodePtr<K,V,S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> Copy for NodePtr<K,V,S> {}
+
+impl<K: Ord, V, S: Summary<K,V>> Link<K,V,S> for NodePtr<K,V,S> {
+    fn node(&self) -> &AvlNode<K,V,S> {
+        //the cursor holds the only borrow of the tree, and throws its pointers away before
+        //changing the tree's shape
+        unsafe { self.0.as_ref() }
+    }
+
+    fn child(self, right: bool) -> Option<Self> {
+        let node = unsafe { &mut *self.0.as_ptr() };
+        let subtree = if right { &mut node.right } else { &mut node.left };
+        subtree.0.as_mut().map(|child| NodePtr(NonNull::from(&mut **child)))
+    }
+}
+
+///the nodes from the root down to a cursor's entry, and the turns between them, true being
+///right. empty at the ghost position
+struct Path<L> {
+    nodes: Vec<L>,
+    turns: Vec<bool>
+}
+
+impl<L> Path<L> {
+    fn new() -> Self {
+        Path { nodes: Vec::new(), turns: Vec::new() }
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.turns.clear();
+    }
+
+    fn current<K: Ord, V, S: Summary<K,V>>(&self) -> Option<L> where L: Link<K,V,S> {
+        self.nodes.last().cloned()
+    }
+
+    ///steps down to a child of the current node, if it has one on that side
+    fn down<K: Ord, V, S: Summary<K,V>>(&mut self, right: bool) -> bool where L: Link<K,V,S> {
+        match self.current().and_then(|node| node.child(right)) {
+            None => false,
+            Some(child) => {
+                self.nodes.push(child);
+                self.turns.push(right);
+                true
+            }
+        }
+    }
+
+    ///steps up to the parent, giving the side the current node was on, or None if it was the
+    ///root, which leaves the path at the ghost position
+    fn up(&mut self) -> Option<bool> {
+        self.nodes.pop();
+        self.turns.pop()
+    }
+
+    ///steps to the next entry on one side: the next one when right is true, else the previous
+    fn step<K: Ord, V, S: Summary<K,V>>(&mut self, root: Option<L>, right: bool) where L: Link<K,V,S> {
+        if self.nodes.is_empty() {
+            if let Some(root) = root {
+                self.nodes.push(root);
+                while self.down(!right) {}
+            }
+        } else if self.down(right) {
+            while self.down(!right) {}
+        } else {
+            //climb until coming up from the other side
+            while let Some(side) = self.up() {
+                if side != right {
+                    break;
+                }
+            }
+        }
+    }
+
+    ///the entry step would move to, without moving
+    fn peek<K: Ord, V, S: Summary<K,V>>(&self, root: Option<L>, right: bool) -> Option<L> where L: Link<K,V,S> {
+        let extreme = |mut node: L| {
+            while let Some(next) = node.child(!right) {
+                node = next;
+            }
+            node
+        };
+        match self.current() {
+            None => root.map(extreme),
+            Some(node) => match node.child(right) {
+                Some(child) => Some(extreme(child)),
+                None => self.turns.iter().rposition(|&side| side != right).map(|index| self.nodes[index])
+            }
+        }
+    }
+
+    ///moves to the first entry whose key is not before the wanted ones, or to the ghost
+    ///position if there is none
+    fn seek<K: Ord, V, S: Summary<K,V>, F: Fn(&K) -> bool>(&mut self, root: Option<L>, wanted: F) where L: Link<K,V,S> {
+        self.clear();
+        let mut found = 0;
+        let mut next = root;
+        while let Some(node) = next {
+            self.nodes.push(node);
+            let right = !wanted(&node.node().key);
+            if !right {
+                found = self.nodes.len();
+            }
+            next = node.child(right);
+            if next.is_some() {
+                self.turns.push(right);
+            }
+        }
+        self.nodes.truncate(found);
+        self.turns.truncate(found.saturating_sub(1));
+    }
+
+    ///follows the given turns down from the root
+    fn follow<K: Ord, V, S: Summary<K,V>>(&mut self, root: Option<L>, turns: Option<Vec<bool>>) where L: Link<K,V,S> {
+        self.clear();
+        if let (Some(root), Some(turns)) = (root, turns) {
+            self.nodes.push(root);
+            for right in turns {
+                let found = self.down(right);
+                debug_assert!(found, "cursor path lost");
+            }
+        }
+    }
+}
+
+/// A cursor over an AvlTree that can move between entries and read them. Made by
+/// `AvlTree::lower_bound_cursor` and `AvlTree::upper_bound_cursor`.
+pub struct Cursor<'a, K: 'a + Ord, V: 'a, S: 'a + Summary<K,V>> {
+    root: Option<&'a AvlNode<K,V,S>>,
+    path: Path<&'a AvlNode<K,V,S>>
+}
+
+/// A cursor over an AvlTree that can also change values, insert entries next to its own and
+/// remove its own. Made by `AvlTree::lower_bound_cursor_mut` and
+/// `AvlTree::upper_bound_cursor_mut`.
+pub struct CursorMut<'a, K: 'a + Ord, V: 'a, S: 'a + Summary<K,V>> {
+    tree: &'a mut AvlTree<K,V,S>,
+    path: Path<NodePtr<K,V,S>>,
+    ///set when a value may have been changed through value_mut, so the summaries on the
+    ///path need recomputing
+    dirty: bool
+}
+
+impl<K: Ord, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Gives a cursor on the first entry whose key is not less than the given key, or on the
+    /// ghost position if there is none.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  for key in vec![10, 20, 30] {
+    ///      tree.insert(key, key / 10);
+    ///  }
+    ///
+    ///  let mut cursor = tree.lower_bound_cursor(&15);
+    ///  assert_eq!(cursor.key(), Some(&20));
+    ///  assert_eq!(cursor.peek_prev(), Some((&10, &1)));
+    ///  cursor.move_next();
+    ///  cursor.move_next();
+    ///  assert_eq!(cursor.key(), None);
+    ///  cursor.move_next();
+    ///  assert_eq!(cursor.key(), Some(&10));
+    ///  ```
+    pub fn lower_bound_cursor(&self, key: &K) -> Cursor<'_, K,V,S> {
+        let mut cursor = Cursor { root: self.0.as_deref(), path: Path::new() };
+        cursor.path.seek(cursor.root, |node_key| node_key >= key);
+        cursor
+    }
+
+    /// Gives a cursor on the first entry whose key is greater than the given key, or on the
+    /// ghost position if there is none.
+    pub fn upper_bound_cursor(&self, key: &K) -> Cursor<'_, K,V,S> {
+        let mut cursor = Cursor { root: self.0.as_deref(), path: Path::new() };
+        cursor.path.seek(cursor.root, |node_key| node_key > key);
+        cursor
+    }
+
+    /// Gives a mutable cursor on the first entry whose key is not less than the given key,
+    /// or on the ghost position if there is none.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  for key in vec![10, 20, 30] {
+    ///      tree.insert(key, key / 10);
+    ///  }
+    ///
+    ///  {
+    ///      let mut cursor = tree.lower_bound_cursor_mut(&20);
+    ///      *cursor.value_mut().unwrap() = 200;
+    ///      assert_eq!(cursor.insert_before(15, 150), Ok(()));
+    ///      assert_eq!(cursor.insert_before(5, 50), Err((5, 50)));
+    ///      assert_eq!(cursor.remove_current(), Some((20, 200)));
+    ///      assert_eq!(cursor.key(), Some(&30));
+    ///  }
+    ///  assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&10, &1), (&15, &150), (&30, &3)]);
+    ///  ```
+    pub fn lower_bound_cursor_mut(&mut self, key: &K) -> CursorMut<'_, K,V,S> {
+        let mut cursor = CursorMut { tree: self, path: Path::new(), dirty: false };
+        let root = cursor.root();
+        cursor.path.seek(root, |node_key| node_key >= key);
+        cursor
+    }
+
+    /// Gives a mutable cursor on the first entry whose key is greater than the given key, or
+    /// on the ghost position if there is none.
+    pub fn upper_bound_cursor_mut(&mut self, key: &K) -> CursorMut<'_, K,V,S> {
+        let mut cursor = CursorMut { tree: self, path: Path::new(), dirty: false };
+        let root = cursor.root();
+        cursor.path.seek(root, |node_key| node_key > key);
+        cursor
+    }
+}
+
+impl<'a, K: Ord, V, S: Summary<K,V>> Cursor<'a, K,V,S> {
+
+    /// Gives the key of the entry the cursor is on, or None at the ghost position
+    pub fn key(&self) -> Option<&'a K> {
+        self.path.current().map(|node| &node.key)
+    }
+
+    /// Gives the value of the entry the cursor is on, or None at the ghost position
+    pub fn value(&self) -> Option<&'a V> {
+        self.path.current().map(|node| &node.val)
+    }
+
+    /// Moves to the next entry, or from the last entry to the ghost position, or from the
+    /// ghost position to the first entry
+    pub fn move_next(&mut self) {
+        self.path.step(self.root, true);
+    }
+
+    /// Moves to the previous entry, or from the first entry to the ghost position, or from
+    /// the ghost position to the last entry
+    pub fn move_prev(&mut self) {
+        self.path.step(self.root, false);
+    }
+
+    /// Gives the entry `move_next` would move to, or None if it would move to the ghost
+    /// position
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        self.path.peek(self.root, true).map(|node| (&node.key, &node.val))
+    }
+
+    /// Gives the entry `move_prev` would move to, or None if it would move to the ghost
+    /// position
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        self.path.peek(self.root, false).map(|node| (&node.key, &node.val))
+    }
+}
+
+impl<'a, K: Ord, V, S: Summary<K,V>> CursorMut<'a, K,V,S> {
+
+    ///the root, reached afresh through the tree. only called when the path is about to be
+    ///rebuilt, since it invalidates the pointers already on it
+    fn root(&mut self) -> Option<NodePtr<K,V,S>> {
+        self.tree.0.as_mut().map(|root| NodePtr(NonNull::from(&mut **root)))
+    }
+
+    ///recomputes the summaries on the path, if a value on it may have changed
+    fn settle(&mut self) {
+        if self.dirty {
+            for node in self.path.nodes.iter().rev() {
+                unsafe { (*node.0.as_ptr()).update() };
+            }
+            self.dirty = false;
+        }
+    }
+
+    /// Gives the key of the entry the cursor is on, or None at the ghost position
+    pub fn key(&self) -> Option<&K> {
+        self.path.nodes.last().map(|node| &node.node().key)
+    }
+
+    /// Gives the value of the entry the cursor is on, to change it, or None at the ghost
+    /// position. The tree's summaries are brought up to date the next time the cursor is
+    /// used or dropped.
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let node = self.path.nodes.last()?;
+        self.dirty = true;
+        Some(unsafe { &mut (*node.0.as_ptr()).val })
+    }
+
+    /// Moves to the next entry, or from the last entry to the ghost position, or from the
+    /// ghost position to the first entry
+    pub fn move_next(&mut self) {
+        self.settle();
+        let root = if self.path.nodes.is_empty() { self.root() } else { None };
+        self.path.step(root, true);
+    }
+
+    /// Moves to the previous entry, or from the first entry to the ghost position, or from
+    /// the ghost position to the last entry
+    pub fn move_prev(&mut self) {
+        self.settle();
+        let root = if self.path.nodes.is_empty() { self.root() } else { None };
+        self.path.step(root, false);
+    }
+
+    /// Gives the entry `move_next` would move to, or None if it would move to the ghost
+    /// position
+    pub fn peek_next(&self) -> Option<(&K, &V)> {
+        self.peek(true)
+    }
+
+    /// Gives the entry `move_prev` would move to, or None if it would move to the ghost
+    /// position
+    pub fn peek_prev(&self) -> Option<(&K, &V)> {
+        self.peek(false)
+    }
+
+    fn peek(&self, right: bool) -> Option<(&K, &V)> {
+        let node = if self.path.nodes.is_empty() {
+            //from the ghost position the path has nothing to go on, so the tree is read directly
+            Path::new().peek(self.tree.0.as_deref(), right)
+        } else {
+            self.path.peek(None, right).map(|node| unsafe { &*node.0.as_ptr() })
+        };
+        node.map(|node| (&node.key, &node.val))
+    }
+
+    /// Inserts an entry just before the cursor's, or at the end when the cursor is on the
+    /// ghost position. The key must fall strictly between the keys on either side, or the
+    /// entry is given back as the error. The cursor stays on the same entry.
+    pub fn insert_before(&mut self, key: K, val: V) -> Result<(), (K,V)> {
+        self.insert_beside(key, val, false)
+    }
+
+    /// Inserts an entry just after the cursor's, or at the start when the cursor is on the
+    /// ghost position. The key must fall strictly between the keys on either side, or the
+    /// entry is given back as the error. The cursor stays on the same entry.
+    pub fn insert_after(&mut self, key: K, val: V) -> Result<(), (K,V)> {
+        self.insert_beside(key, val, true)
+    }
+
+    fn insert_beside(&mut self, key: K, val: V, right: bool) -> Result<(), (K,V)> {
+        self.settle();
+        let (below, above) = if right {
+            (self.key(), self.peek_next().map(|(key, _)| key))
+        } else {
+            (self.peek_prev().map(|(key, _)| key), self.key())
+        };
+        if below.is_some_and(|below| *below >= key) || above.is_some_and(|above| *above <= key) {
+            return Err((key, val));
+        }
+
+        //the new leaf goes on the inner side of the neighbouring subtree, or at the outer
+        //edge of the whole tree from the ghost position
+        let mut slot = self.path.turns.clone();
+        let mut next = match self.path.current() {
+            Some(node) => {
+                slot.push(right);
+                node.child(right)
+            },
+            None => self.root()
+        };
+        while let Some(node) = next {
+            slot.push(!right);
+            next = node.child(!right);
+        }
+
+        let mut target = self.position();
+        self.path.clear();
+        let leaf = AvlTree::node(AvlTree::from_root(None), key, val, AvlTree::from_root(None));
+        edit_along(self.tree, &slot, 0, &mut target, |tree| tree.0 = leaf.0);
+        let root = self.root();
+        self.path.follow(root, target);
+        Ok(())
+    }
+
+    /// Removes the cursor's entry and gives it back, moving the cursor on to the next entry.
+    /// Returns None at the ghost position.
+    pub fn remove_current(&mut self) -> Option<(K,V)> {
+        self.settle();
+        let node = self.path.current()?;
+        let place = self.path.turns.clone();
+        //where the next entry will be once this one is gone
+        let mut target = match (node.child(false), node.child(true)) {
+            //the next entry is moved up into this one's place
+            (Some(_), Some(_)) => Some(place.clone()),
+            //the right subtree is moved up, so the next entry is at the bottom of its left side
+            (None, Some(mut next)) => {
+                let mut turns = place.clone();
+                while let Some(left) = next.child(false) {
+                    turns.push(false);
+                    next = left;
+                }
+                Some(turns)
+            },
+            //the next entry is the nearest entry above that this one is left of
+            (_, None) => self.path.turns.iter().rposition(|&right| !right).map(|index| place[..index].to_vec())
+        };
+
+        self.path.clear();
+        let mut removed = None;
+        edit_along(self.tree, &place, 0, &mut target, |tree| {
+            let node = *tree.0.take().unwrap();
+            tree.0 = match (node.left.0, node.right.0) {
+                (None, None) => AvlTree::from_root(None),
+                (Some(left), None) => AvlTree::from_root(Some(left)),
+                (None, Some(right)) => AvlTree::from_root(Some(right)),
+                (Some(left), Some(right)) => {
+                    let mut newright = AvlTree::from_root(Some(right));
+                    let mut min = newright.take_min();
+                    min.right = newright;
+                    min.left = AvlTree::from_root(Some(left));
+                    AvlTree::from_root(Some(min))
+                }
+            }.0;
+            removed = Some((node.key, node.val));
+        });
+        let root = self.root();
+        self.path.follow(root, target);
+        removed
+    }
+
+    ///the turns down to the cursor's entry, or None at the ghost position
+    fn position(&self) -> Option<Vec<bool>> {
+        if self.path.nodes.is_empty() { None } else { Some(self.path.turns.clone()) }
+    }
+}
+
+impl<'a, K: Ord, V, S: Summary<K,V>> Drop for CursorMut<'a, K,V,S> {
+    fn drop(&mut self) {
+        self.settle();
+    }
+}
+
+///makes a change at the end of a path of turns, then rebalances every node on the path from
+///the bottom up. any rotation that moves the target's node is replayed on the target's turns
+fn edit_along<K: Ord, V, S: Summary<K,V>, F: FnOnce(&mut AvlTree<K,V,S>)>(tree: &mut AvlTree<K,V,S>, path: &[bool], depth: usize, target: &mut Option<Vec<bool>>, at: F) {
+    if depth == path.len() {
+        at(tree);
+    } else {
+        let node = tree.0.as_mut().expect("edit path runs off the tree");
+        let subtree = if path[depth] { &mut node.right } else { &mut node.left };
+        edit_along(subtree, path, depth + 1, target, at);
+    }
+    if let Some(turn) = tree.rebalance() {
+        if let Some(ref mut target) = *target {
+            if target.len() >= depth && target[..depth] == path[..depth] {
+                let below = target.split_off(depth);
+                target.extend(replay(below, turn));
+            }
+        }
+    }
+}
+
+///gives the turns to a node below a rotated one after the rotation, given the turns before
+fn replay(turns: Vec<bool>, turn: Turn) -> Vec<bool> {
+    match turn {
+        Turn::Right => lift(turns, false),
+        Turn::Left => lift(turns, true),
+        Turn::LeftRight => lift(lift_below(turns, false), false),
+        Turn::RightLeft => lift(lift_below(turns, true), true)
+    }
+}
+
+///the first half of a double rotation: lifts the inner grandchild on the given side, which
+///only moves nodes that were under that child
+fn lift_below(turns: Vec<bool>, side: bool) -> Vec<bool> {
+    if turns.first() != Some(&side) {
+        return turns;
+    }
+    let mut moved = vec![side];
+    moved.extend(lift(turns[1..].to_vec(), !side));
+    moved
+}
+
+///a single rotation lifting the child on the given side over its parent
+fn lift(turns: Vec<bool>, side: bool) -> Vec<bool> {
+    let mut moved = Vec::with_capacity(turns.len() + 1);
+    match (turns.first(), turns.get(1)) {
+        //the parent moves down to the other side
+        (None, _) => moved.push(!side),
+        //the lifted child is the new top
+        (Some(&first), None) if first == side => {},
+        //the lifted child's outer subtree moves up with it
+        (Some(&first), Some(&second)) if first == side && second == side => {
+            moved.push(side);
+            moved.extend_from_slice(&turns[2..]);
+        },
+        //its inner subtree moves across to become the parent's inner subtree
+        (Some(&first), Some(_)) if first == side => {
+            moved.push(!side);
+            moved.push(side);
+            moved.extend_from_slice(&turns[2..]);
+        },
+        //the parent's other subtree moves down with it
+        _ => {
+            moved.push(!side);
+            moved.extend_from_slice(&turns);
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+    use ordered_map::conformance::XorShift;
+    use summary::Sum;
+
+    #[test]
+    fn test_cursor_mut() {
+        let mut tree: AvlTree<u64, u64, Sum> = AvlTree::with_summary();
+        //the entries in order, and the index of the cursor's entry, None being the ghost
+        let mut model: Vec<(u64, u64)> = Vec::new();
+        let mut at: Option<usize> = None;
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        let mut cursor = tree.lower_bound_cursor_mut(&0);
+        for step in 0..20000 {
+            let len = model.len();
+            match rng.next(10) {
+                0 | 1 => {
+                    cursor.move_next();
+                    at = match at {
+                        None if len > 0 => Some(0),
+                        Some(index) if index + 1 < len => Some(index + 1),
+                        _ => None
+                    };
+                },
+                2 => {
+                    cursor.move_prev();
+                    at = match at {
+                        None if len > 0 => Some(len - 1),
+                        Some(index) if index > 0 => Some(index - 1),
+                        _ => None
+                    };
+                },
+                3 => {
+                    let removed = at.map(|index| model.remove(index));
+                    assert_eq!(cursor.remove_current(), removed);
+                    if at == Some(model.len()) {
+                        at = None;
+                    }
+                },
+                4 => {
+                    if let Some(val) = cursor.value_mut() {
+                        *val += 1;
+                        model[at.unwrap()].1 += 1;
+                    }
+                },
+                _ => {
+                    let after = rng.next(2) == 1;
+                    let (below, above) = match at {
+                        Some(index) if after => (Some(model[index].0), model.get(index + 1).map(|e| e.0)),
+                        Some(index) => (index.checked_sub(1).map(|i| model[i].0), Some(model[index].0)),
+                        None if after => (None, model.first().map(|e| e.0)),
+                        None => (model.last().map(|e| e.0), None)
+                    };
+                    //usually a key between the neighbours, sometimes one that may be out of order
+                    let (low, high) = (below.map_or(0, |below| below + 1), above.unwrap_or(1 << 48));
+                    let key = if rng.next(8) == 5 || low >= high {
+                        rng.next(1 << 48)
+                    } else {
+                        low + rng.next(high - low)
+                    };
+                    let fits = below.is_none_or(|below| below < key) && above.is_none_or(|above| key < above);
+                    let result = if after { cursor.insert_after(key, step) } else { cursor.insert_before(key, step) };
+                    if fits {
+                        assert_eq!(result, Ok(()));
+                        let index = match at {
+                            Some(index) if after => index + 1,
+                            Some(index) => index,
+                            None if after => 0,
+                            None => model.len()
+                        };
+                        model.insert(index, (key, step));
+                        if let Some(ref mut at) = at {
+                            if index <= *at {
+                                *at += 1;
+                            }
+                        }
+                    } else {
+                        assert_eq!(result, Err((key, step)));
+                    }
+                }
+            }
+            assert_eq!(cursor.key(), at.map(|index| &model[index].0), "step {}", step);
+            let next = match at { Some(index) => model.get(index + 1), None => model.first() };
+            assert_eq!(cursor.peek_next(), next.map(|e| (&e.0, &e.1)));
+            let prev = match at { Some(index) => index.checked_sub(1).map(|i| &model[i]), None => model.last() };
+            assert_eq!(cursor.peek_prev(), prev.map(|e| (&e.0, &e.1)));
+        }
+        drop(cursor);
+        assert!(model.len() > 1000);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), model);
+        assert_eq!(tree.aggregate(None, None), model.iter().map(|e| e.1).sum::<u64>());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut tree = AvlTree::new();
+        for key in 0..100 {
+            tree.insert(key * 2, ());
+        }
+        for key in 0..200 {
+            let lower = tree.lower_bound_cursor(&key);
+            assert_eq!(lower.key().cloned(), if key <= 198 { Some(key + key % 2) } else { None });
+            let upper = tree.upper_bound_cursor(&key);
+            assert_eq!(upper.key().cloned(), if key < 198 { Some(key + 2 - key % 2) } else { None });
+        }
+        let mut cursor = tree.lower_bound_cursor(&0);
+        let mut walked = Vec::new();
+        while let Some(key) = cursor.key() {
+            walked.push(*key);
+            cursor.move_next();
+        }
+        assert_eq!(walked, (0..100).map(|key| key * 2).collect::<Vec<_>>());
+        cursor.move_prev();
+        assert_eq!(cursor.key(), Some(&198));
+    }
+}
@@ -0,0 +1,134 @@
+//! Debugging views of an AvlTree's structure.
+use alloc::string::String;
+use core::cmp::Ord;
+use core::fmt::{self, Debug, Write};
+
+use AvlTree;
+use summary::Summary;
+
+///escapes a string for use inside a quoted Graphviz label
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<K: Ord + Debug, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Gives the structure of the tree in the Graphviz DOT language. Every node is labeled
+    /// with its key and its stored height, and every edge with the side of the child. Render
+    /// it with `dot -Tsvg`.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(2, "b");
+    ///  tree.insert(1, "a");
+    ///
+    ///  let dot = tree.to_dot();
+    ///  assert!(dot.starts_with("digraph AvlTree {"));
+    ///  assert!(dot.contains("n0 [label=\"2\\nh=2\"];"));
+    ///  assert!(dot.contains("n0 -> n1 [label=\"L\"];"));
+    ///  ```
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph AvlTree {\n");
+        let mut next_id = 0;
+        self.dot_nodes(&mut out, &mut next_id);
+        out.push_str("}\n");
+        out
+    }
+
+    ///writes this subtree's nodes and edges, numbering nodes in preorder. returns this node's id
+    fn dot_nodes(&self, out: &mut String, next_id: &mut usize) -> Option<usize> {
+        self.0.as_ref().map(|node| {
+            let id = *next_id;
+            *next_id += 1;
+            let _ = writeln!(out, "    n{} [label=\"{}\\nh={}\"];", id, escape_dot(&format!("{:?}", node.key)), node.height);
+            if let Some(left) = node.left.dot_nodes(out, next_id) {
+                let _ = writeln!(out, "    n{} -> n{} [label=\"L\"];", id, left);
+            }
+            if let Some(right) = node.right.dot_nodes(out, next_id) {
+                let _ = writeln!(out, "    n{} -> n{} [label=\"R\"];", id, right);
+            }
+            id
+        })
+    }
+
+    /// Gives a quick view of the tree's structure for a terminal. The tree is drawn on its
+    /// side with the root on the left and larger keys above, one node per line, each with its
+    /// stored height in brackets.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(1, "a");
+    ///  tree.insert(2, "b");
+    ///  tree.insert(3, "c");
+    ///
+    ///  let lines: Vec<_> = tree.to_ascii().lines().map(String::from).collect();
+    ///  assert_eq!(lines, vec!["    3 [1]", "2 [2]", "    1 [1]"]);
+    ///  ```
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        self.ascii_lines(&mut out, 0);
+        out
+    }
+
+    fn ascii_lines(&self, out: &mut String, depth: usize) {
+        if let Some(ref node) = self.0 {
+            node.right.ascii_lines(out, depth + 1);
+            let _ = writeln!(out, "{:indent$}{:?} [{}]", "", node.key, node.height, indent = depth * 4);
+            node.left.ascii_lines(out, depth + 1);
+        }
+    }
+}
+
+impl<K: Ord + Debug, V: Debug, S: Summary<K,V>> Debug for AvlTree<K,V,S> {
+    /// Shows the tree as a map in sorted order. Use `to_ascii` or `to_dot` to see its shape.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+
+    #[test]
+    fn test_dot() {
+        let mut tree = AvlTree::new();
+        for num in 1..8 {
+            tree.insert(num, ());
+        }
+        let dot = tree.to_dot();
+        assert!(dot.starts_with("digraph AvlTree {\n    n0 [label=\"4\\nh=3\"];\n"));
+        assert_eq!(dot.matches("label=\"L\"").count(), 3);
+        assert_eq!(dot.matches("label=\"R\"").count(), 3);
+
+        let mut quoted = AvlTree::new();
+        quoted.insert("say \"hi\"", ());
+        assert!(quoted.to_dot().contains(r#"[label="\"say \\\"hi\\\"\"\nh=1"]"#));
+        assert_eq!(AvlTree::<i32, ()>::new().to_dot(), "digraph AvlTree {\n}\n");
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut tree = AvlTree::new();
+        for num in 1..8 {
+            tree.insert(num, ());
+        }
+        assert_eq!(tree.to_ascii(), "        7 [1]\n    6 [2]\n        5 [1]\n4 [3]\n        3 [1]\n    2 [2]\n        1 [1]\n");
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut tree = AvlTree::new();
+        tree.insert(2, "b");
+        tree.insert(1, "a");
+        assert_eq!(format!("{:?}", tree), r#"{1: "a", 2: "b"}"#);
+    }
+}
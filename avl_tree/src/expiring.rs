@@ -0,0 +1,369 @@
+//! A sorted map whose entries expire a set time after they are inserted.
+//!
+//! Entries are kept in an AvlTree by key, alongside the time each one expires, and a second
+//! AvlTree holds `(expiry, key)` for every entry, so the next entries to expire are always at
+//! its front. Expired entries are hidden from reads straight away, but stay stored until
+//! `purge_expired` takes them out.
+//!
+//! Time comes from a `Clock`, as the time since some fixed start. `ManualClock` only moves when
+//! told to, which keeps tests deterministic, and with the `std` feature `SystemClock` follows
+//! the real time.
+use core::cell::Cell;
+use core::cmp::Ord;
+use core::time::Duration;
+use alloc::vec::Vec;
+
+use {AvlTree, Iter};
+
+/// A source of the current time, given as the time since some fixed start.
+pub trait Clock {
+    /// The current time. It must never go backwards.
+    fn now(&self) -> Duration;
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// A clock that only moves when it is told to, starting at zero.
+#[derive(Debug, Default)]
+pub struct ManualClock(Cell<Duration>);
+
+impl ManualClock {
+    /// Creates a clock stopped at zero
+    pub fn new() -> Self {
+        ManualClock(Cell::new(Duration::from_secs(0)))
+    }
+
+    /// Moves the clock forwards
+    pub fn advance(&self, by: Duration) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+/// The real time, as the time since the clock was created. Needs the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock(::std::time::Instant);
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    /// Creates a clock starting now
+    pub fn new() -> Self {
+        SystemClock(::std::time::Instant::now())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// A sorted map where every entry is given a time to live, after which it is no longer seen.
+///
+/// #Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use avltree_map::expiring::{Clock, ExpiringMap, ManualClock};
+///
+/// let clock = ManualClock::new();
+/// let mut sessions = ExpiringMap::new(&clock);
+/// sessions.insert_with_ttl("alice", 1, Duration::from_secs(30));
+/// sessions.insert_with_ttl("bob", 2, Duration::from_secs(60));
+///
+/// clock.advance(Duration::from_secs(45));
+/// assert_eq!(sessions.get(&"alice"), None);
+/// assert_eq!(sessions.get(&"bob"), Some(&2));
+///
+/// assert_eq!(sessions.purge_expired(clock.now()), vec![("alice", 1)]);
+/// assert_eq!(sessions.next_expiry(), Some(Duration::from_secs(60)));
+/// ```
+pub struct ExpiringMap<K: Ord + Clone, V, C: Clock> {
+    ///every stored entry with the time it expires
+    entries: AvlTree<K, (V, Duration)>,
+    ///the same entries, by the time they expire
+    expiries: AvlTree<(Duration, K), ()>,
+    len: usize,
+    clock: C
+}
+
+/// An iterator over the entries of an `ExpiringMap` that had not expired when it was made,
+/// sorted by key.
+pub struct ExpiringIter<'a, K: 'a, V: 'a> {
+    entries: Iter<'a, K, (V, Duration)>,
+    now: Duration
+}
+
+impl<K: Ord + Clone, V, C: Clock> ExpiringMap<K,V,C> {
+
+    /// Creates a new empty map that tells the time with the given clock
+    pub fn new(clock: C) -> Self {
+        ExpiringMap { entries: AvlTree::new(), expiries: AvlTree::new(), len: 0, clock }
+    }
+
+    /// Gives the clock
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The number of entries stored, including any that have expired but not been purged
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if no entries are stored, expired or not
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Inserts a key,value pair that expires once ttl has passed. Returns the value the key
+    /// had if it had not expired yet. Either way, the key's old expiry is replaced. A ttl too
+    /// long to add to the current time, such as `Duration::MAX`, means the entry never expires.
+    pub fn insert_with_ttl(&mut self, key: K, val: V, ttl: Duration) -> Option<V> {
+        let now = self.clock.now();
+        let expires = now.checked_add(ttl).unwrap_or(Duration::MAX);
+        let old = match self.entries.insert(key.clone(), (val, expires)) {
+            None => {
+                self.len += 1;
+                None
+            },
+            Some((old, old_expires)) => {
+                self.expiries.remove(&(old_expires, key.clone()));
+                if old_expires > now { Some(old) } else { None }
+            }
+        };
+        self.expiries.insert((expires, key), ());
+        old
+    }
+
+    /// Gives the key's value, or None if it is not present or has expired
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        match self.entries.get(key) {
+            Some(&(ref val, expires)) if expires > now => Some(val),
+            _ => None
+        }
+    }
+
+    /// Checks if the key is present and has not expired
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Gives the time the key's entry expires, or None if it is not present or has expired
+    pub fn expires_at(&self, key: &K) -> Option<Duration> {
+        let now = self.clock.now();
+        match self.entries.get(key) {
+            Some(&(_, expires)) if expires > now => Some(expires),
+            _ => None
+        }
+    }
+
+    /// Removes a key, giving back its value if it had not expired. An expired entry is
+    /// removed too, but its value is not given back.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let now = self.clock.now();
+        let (val, expires) = self.entries.remove(key)?;
+        self.len -= 1;
+        self.expiries.remove(&(expires, key.clone()));
+        if expires > now { Some(val) } else { None }
+    }
+
+    /// The earliest time a stored entry expires, which may already have passed if it has not
+    /// been purged. Useful for knowing when to next call `purge_expired`.
+    pub fn next_expiry(&self) -> Option<Duration> {
+        self.expiries.first().map(|node| node.key.0)
+    }
+
+    /// Takes out every entry that has expired as of the given time, giving them back in the
+    /// order they expired. The expired entries are always the ones at the front of the expiry
+    /// tree, so they are split off it all at once in O(log n) and taken apart in O(k) for k
+    /// expired entries. Their keys are then sorted, in O(k log k), and taken out of the tree
+    /// by key in one pass that splits it around each of them and joins the rest back
+    /// together, in O(k log(n/k + 1)).
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use avltree_map::expiring::{Clock, ExpiringMap, ManualClock};
+    ///
+    /// let mut map = ExpiringMap::new(ManualClock::new());
+    /// for i in 0..10 {
+    ///     map.insert_with_ttl(i, i * 10, Duration::from_secs(10 - i));
+    /// }
+    ///
+    /// map.clock().advance(Duration::from_secs(3));
+    /// let purged = map.purge_expired(map.clock().now());
+    /// assert_eq!(purged, vec![(9, 90), (8, 80), (7, 70)]);
+    /// assert_eq!(map.len(), 7);
+    /// ```
+    pub fn purge_expired(&mut self, now: Duration) -> Vec<(K,V)> {
+        let expiries = AvlTree::from_root(self.expiries.0.take());
+        let (expired, live) = expiries.split_by(&|&(expires, _): &(Duration, K)| expires > now);
+        self.expiries.0 = live.0;
+
+        let mut expired_keys = Vec::new();
+        expired.take_apart(&mut expired_keys);
+        //each key is paired with its place in expiry order, which is where its entry goes
+        let mut keys: Vec<_> = expired_keys.into_iter().enumerate().map(|(place, ((_, key), ()))| (key, place)).collect();
+        keys.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let k = keys.len();
+        let keys = AvlTree::from_sorted(&mut keys.into_iter(), k);
+
+        let mut purged = Vec::new();
+        purged.resize_with(k, || None);
+        let entries = AvlTree::from_root(self.entries.0.take());
+        self.entries.0 = take_keys(entries, keys, &mut purged).0;
+        self.len -= k;
+        purged.into_iter().map(|entry| {
+            let (key, (val, _)) = entry.expect("expiring entry missing");
+            (key, val)
+        }).collect()
+    }
+
+    /// Gives an iterator over the entries that have not expired, sorted by key
+    pub fn iter(&self) -> ExpiringIter<'_, K,V> {
+        ExpiringIter { entries: self.entries.iter(), now: self.clock.now() }
+    }
+}
+
+///takes the entries for the given keys out of a tree, putting each in taken at the place its
+///key maps to. the tree is split around the middle key and both sides are done on their own,
+///so k keys come out of n entries in O(k log(n/k + 1))
+fn take_keys<K: Ord, V>(entries: AvlTree<K,V>, keys: AvlTree<K, usize>, taken: &mut [Option<(K,V)>]) -> AvlTree<K,V> {
+    if entries.is_empty() {
+        return entries;
+    }
+    match keys.expose() {
+        None => entries,
+        Some((keys_left, key, place, keys_right)) => {
+            let (left, found, right) = entries.split(&key);
+            taken[place] = found;
+            let left = take_keys(left, keys_left, taken);
+            let right = take_keys(right, keys_right, taken);
+            AvlTree::join2(left, right)
+        }
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> Iterator for ExpiringIter<'a, K,V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let now = self.now;
+        self.entries.by_ref().find(|&(_, &(_, expires))| expires > now).map(|(key, (val, _))| (key, val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use expiring::{Clock, ExpiringMap, ManualClock};
+    use ordered_map::conformance::XorShift;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_differential() {
+        let clock = ManualClock::new();
+        let mut map = ExpiringMap::new(&clock);
+        //every stored key with its value and expiry, in seconds
+        let mut model: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..5000 {
+            let key = rng.next(200);
+            let now = clock.now().as_secs();
+            let live = |entry: Option<&(u64, u64)>| entry.filter(|&&(_, expires)| expires > now).map(|&(val, _)| val);
+            match rng.next(20) {
+                0..=3 => clock.advance(Duration::from_secs(rng.next(5))),
+                4 => {
+                    let mut expected: Vec<_> = model.iter()
+                        .filter(|&(_, &(_, expires))| expires <= now)
+                        .map(|(&key, &(val, expires))| (expires, key, val))
+                        .collect();
+                    expected.sort();
+                    model.retain(|_, &mut (_, expires)| expires > now);
+                    let expected: Vec<_> = expected.into_iter().map(|(_, key, val)| (key, val)).collect();
+                    assert_eq!(map.purge_expired(clock.now()), expected);
+                },
+                5..=7 => assert_eq!(map.remove(&key), live(model.remove(&key).as_ref())),
+                _ => {
+                    let ttl = rng.next(30);
+                    let old = model.insert(key, (step, now + ttl));
+                    assert_eq!(map.insert_with_ttl(key, step, Duration::from_secs(ttl)), live(old.as_ref()));
+                }
+            }
+            assert_eq!(map.len(), model.len());
+            let now = clock.now().as_secs();
+            assert_eq!(map.get(&key).cloned(), model.get(&key).filter(|&&(_, expires)| expires > now).map(|&(val, _)| val));
+            assert_eq!(map.next_expiry().map(|expiry| expiry.as_secs()), model.values().map(|&(_, expires)| expires).min());
+        }
+        let now = clock.now().as_secs();
+        assert_eq!(map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(),
+                   model.iter().filter(|&(_, &(_, expires))| expires > now).map(|(&k, &(v, _))| (k, v)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ttl_bounds() {
+        let clock = ManualClock::new();
+        let mut map = ExpiringMap::new(&clock);
+        clock.advance(Duration::from_secs(5));
+
+        //a ttl too long to add to the time never expires rather than overflowing
+        assert_eq!(map.insert_with_ttl(1, "forever", Duration::MAX), None);
+        assert_eq!(map.expires_at(&1), Some(Duration::MAX));
+        assert_eq!(map.insert_with_ttl(2, "forever", Duration::MAX - Duration::from_secs(1)), None);
+        assert_eq!(map.expires_at(&2), Some(Duration::MAX));
+
+        //a zero ttl has expired as soon as it is inserted, but is stored until purged
+        assert_eq!(map.insert_with_ttl(3, "gone", Duration::from_secs(0)), None);
+        assert_eq!(map.get(&3), None);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.next_expiry(), Some(clock.now()));
+        assert_eq!(map.insert_with_ttl(3, "again", Duration::from_secs(0)), None);
+
+        clock.advance(Duration::from_secs(1_000_000_000));
+        assert_eq!(map.purge_expired(clock.now()), vec![(3, "again")]);
+        assert_eq!(map.get(&1), Some(&"forever"));
+        assert_eq!(map.get(&2), Some(&"forever"));
+        assert_eq!(map.purge_expired(Duration::MAX - Duration::from_secs(1)), vec![]);
+        assert_eq!(map.purge_expired(Duration::MAX), vec![(1, "forever"), (2, "forever")]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_purge_many() {
+        let clock = ManualClock::new();
+        let mut map = ExpiringMap::new(&clock);
+        //expiries run against key order, so the purged keys are spread through the tree
+        for key in 0..1000u64 {
+            map.insert_with_ttl(key, key, Duration::from_secs((key * 7919) % 1000 + 1));
+        }
+        clock.advance(Duration::from_secs(500));
+        let purged = map.purge_expired(clock.now());
+        assert_eq!(purged.len(), 500);
+        assert!(purged.windows(2).all(|pair| (pair[0].0 * 7919) % 1000 < (pair[1].0 * 7919) % 1000));
+        assert_eq!(map.len(), 500);
+        assert_eq!(map.entries.validate(), Ok(()));
+        assert_eq!(map.iter().count(), 500);
+        assert!(purged.iter().all(|&(key, _)| map.expires_at(&key).is_none()));
+    }
+}
@@ -0,0 +1,319 @@
+//! An interval tree built on the AvlTree.
+//!
+//! Intervals are half open, like `Range`, and are kept in an AvlTree ordered by start point and
+//! then end point. Every subtree keeps the largest end point in it as its summary, which lets
+//! queries skip any subtree that ends before the range being searched for.
+use core::cmp::{Ord, max};
+use alloc::collections::VecDeque;
+use core::ops::Range;
+
+use AvlTree;
+use summary::Summary;
+
+/// The summary used by the IntervalTree: the largest end point in a subtree.
+struct MaxEnd;
+
+impl<T: Ord + Clone, V> Summary<(T,T), V> for MaxEnd {
+    type Value = Option<T>;
+
+    fn empty() -> Option<T> {
+        None
+    }
+
+    fn summarize(key: &(T,T), _: &V) -> Option<T> {
+        Some(key.1.clone())
+    }
+
+    fn combine(left: &Option<T>, right: &Option<T>) -> Option<T> {
+        max(left.clone(), right.clone())
+    }
+}
+
+/// A map from intervals to values that can find all of the intervals overlapping a range or
+/// containing a point. End points must be `Clone` so that every subtree can keep its largest
+/// end point.
+pub struct IntervalTree<T: Ord + Clone, V> (AvlTree<(T,T), V, MaxEnd>);
+
+/// A struct used to iterate over intervals of the IntervalTree, sorted by start point and then
+/// end point.
+pub struct Iter<'a, T: 'a, V: 'a> {
+    queue: VecDeque<(Range<&'a T>, &'a V)>
+}
+
+impl<'a, T: 'a + Ord + Clone, V: 'a> Iter<'a, T, V> {
+
+    ///creates an iterator over every interval in the tree
+    fn all(tree: &'a IntervalTree<T,V>) -> Self {
+        Iter {
+            queue: tree.0.iter().map(|(key, val)| (&key.0..&key.1, val)).collect()
+        }
+    }
+
+    ///creates an iterator containing all intervals that overlap start..end, or start..=end if
+    ///closed is true
+    fn overlapping(tree: &'a IntervalTree<T,V>, start: &T, end: &T, closed: bool) -> Self {
+        let mut queue = VecDeque::new();
+        visit_overlapping(&tree.0, start, end, closed, &mut |a, b, val| queue.push_back((a..b, val)));
+        Iter { queue }
+    }
+}
+
+///calls f on every interval that overlaps start..end, or start..=end if closed is true, in
+///sorted order
+fn visit_overlapping<'a, T, V, F>(tree: &'a AvlTree<(T,T), V, MaxEnd>, start: &T, end: &T, closed: bool, f: &mut F)
+    where T: Ord + Clone, F: FnMut(&'a T, &'a T, &'a V)
+{
+    if let Some(ref node) = tree.0 {
+        //nothing in this subtree ends after the start of the query
+        match node.summary {
+            Some(ref max_end) if *max_end > *start => (),
+            _ => return
+        }
+        visit_overlapping(&node.left, start, end, closed, f);
+
+        //if this interval starts after the query, so does everything to the right of it
+        let (ref a, ref b) = node.key;
+        let starts_in_time = if closed { *a <= *end } else { *a < *end };
+        if starts_in_time {
+            if *start < *b && *a < *b {
+                f(a, b, &node.val);
+            }
+            visit_overlapping(&node.right, start, end, closed, f);
+        }
+    }
+}
+
+impl<'a, T: 'a, V: 'a> Iterator for Iter<'a, T, V> {
+    type Item = (Range<&'a T>, &'a V);
+
+    fn next(&mut self) -> Option<(Range<&'a T>, &'a V)> {
+        self.queue.pop_front()
+    }
+}
+
+impl<T: Ord + Clone, V> IntervalTree<T,V> {
+
+    /// Creates a new empty IntervalTree
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    ///
+    /// tree.insert(1..5, "a");
+    /// ```
+    pub fn new() -> Self {
+        IntervalTree(AvlTree::with_summary())
+    }
+
+    /// checks if the tree is empty.
+    ///
+    ///  #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::interval::IntervalTree;
+    ///
+    ///  let mut tree = IntervalTree::new();
+    ///  assert!(tree.is_empty());
+    ///
+    ///  tree.insert(1..5, "a");
+    ///  assert!(!tree.is_empty());
+    ///  ```
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Inserts an interval and its value into the tree. Returns None if the same interval was
+    /// not present in the tree already. If it was, then its value is replaced and the old value
+    /// is returned. Empty intervals are stored, but never overlap anything.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    ///
+    /// assert_eq!(tree.insert(1..5, "a"), None);
+    /// assert_eq!(tree.insert(1..6, "b"), None);
+    /// assert_eq!(tree.insert(1..5, "c"), Some("a"));
+    /// ```
+    pub fn insert(&mut self, range: Range<T>, val: V) -> Option<V> {
+        self.0.insert((range.start, range.end), val)
+    }
+
+    /// Removes an interval from the tree. Returns the value of the interval if it was present,
+    /// or None if it was not.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    /// tree.insert(1..5, "a");
+    ///
+    /// assert_eq!(tree.remove(&(1..4)), None);
+    /// assert_eq!(tree.remove(&(1..5)), Some("a"));
+    /// assert!(tree.is_empty());
+    /// ```
+    pub fn remove(&mut self, range: &Range<T>) -> Option<V> {
+        self.0.remove(&(range.start.clone(), range.end.clone()))
+    }
+
+    /// Gives an iterator over all of the intervals in the tree, sorted by start point and then
+    /// end point.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    /// tree.insert(3..4, "b");
+    /// tree.insert(1..5, "a");
+    ///
+    /// assert_eq!(tree.iter().next().unwrap(), (&1..&5, &"a"));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter::all(self)
+    }
+
+    /// Gives an iterator over the intervals that share at least one point with the given range,
+    /// sorted by start point and then end point. Subtrees that can not hold an overlapping
+    /// interval are skipped, so this takes O(log n) time when there are no overlaps. The k
+    /// intervals found are reached along paths from at most O(log n) subtrees, which share
+    /// their upper levels, so finding them takes O(log n + k log(n/k + 1)) altogether. That is
+    /// O(log n + k) when the intervals found are a large share of the tree, but not when a few
+    /// are spread out through it: that bound would need the largest end point stored in the
+    /// node that has it, as a priority search tree does, rather than summed up over a subtree.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    /// tree.insert(1..5, "a");
+    /// tree.insert(4..8, "b");
+    /// tree.insert(8..9, "c");
+    ///
+    /// let found: Vec<_> = tree.overlapping(5..8).map(|(_, v)| *v).collect();
+    /// assert_eq!(found, vec!["b"]);
+    /// ```
+    pub fn overlapping(&self, range: Range<T>) -> Iter<'_, T, V> {
+        if range.start >= range.end {
+            return Iter { queue: VecDeque::new() };
+        }
+        Iter::overlapping(self, &range.start, &range.end, false)
+    }
+
+    /// Gives an iterator over the intervals that contain the given point, sorted by start point
+    /// and then end point.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    /// tree.insert(1..5, "a");
+    /// tree.insert(4..8, "b");
+    ///
+    /// let found: Vec<_> = tree.containing(&4).map(|(_, v)| *v).collect();
+    /// assert_eq!(found, vec!["a", "b"]);
+    /// assert_eq!(tree.containing(&8).next(), None);
+    /// ```
+    pub fn containing(&self, point: &T) -> Iter<'_, T, V> {
+        Iter::overlapping(self, point, point, true)
+    }
+
+    /// Counts the intervals that contain the given point. The tree is walked as `containing`
+    /// walks it, but nothing is collected.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::interval::IntervalTree;
+    ///
+    /// let mut tree = IntervalTree::new();
+    /// tree.insert(1..5, "a");
+    /// tree.insert(4..8, "b");
+    ///
+    /// assert_eq!(tree.stabbing_count(&0), 0);
+    /// assert_eq!(tree.stabbing_count(&3), 1);
+    /// assert_eq!(tree.stabbing_count(&4), 2);
+    /// ```
+    pub fn stabbing_count(&self, point: &T) -> usize {
+        let mut count = 0;
+        visit_overlapping(&self.0, point, point, true, &mut |_, _, _| count += 1);
+        count
+    }
+}
+
+impl<T: Ord + Clone, V> Default for IntervalTree<T,V> {
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use interval::IntervalTree;
+    use ordered_map::conformance::XorShift;
+
+    ///pseudo-random intervals, the same on every run
+    fn intervals() -> Vec<(u32, u32)> {
+        let mut rng = XorShift::new(12345);
+        (0..500).map(|_| {
+            let start = rng.next(1000) as u32;
+            (start, start + rng.next(50) as u32)
+        }).collect()
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let mut tree = IntervalTree::new();
+        let mut all = intervals();
+        for (i, &(a, b)) in all.iter().enumerate() {
+            tree.insert(a..b, i);
+        }
+        //later duplicates replaced earlier ones
+        all.sort();
+        all.dedup();
+
+        for &(c, d) in [(0, 1000), (10, 20), (500, 501), (999, 2000), (30, 30)].iter() {
+            let found: Vec<(u32, u32)> = tree.overlapping(c..d).map(|(r, _)| (*r.start, *r.end)).collect();
+            let expected: Vec<(u32, u32)> = all.iter().cloned()
+                .filter(|&(a, b)| a < b && c < d && a < d && c < b)
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        for p in 0..1050 {
+            let expected = all.iter().filter(|&&(a, b)| a <= p && p < b).count();
+            assert_eq!(tree.stabbing_count(&p), expected);
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = IntervalTree::new();
+        let mut all = intervals();
+        all.sort();
+        all.dedup();
+        for &(a, b) in all.iter() {
+            tree.insert(a..b, ());
+        }
+        //removing intervals rotates the tree, which must keep the largest end points right
+        for &(a, b) in all.iter().filter(|&&(a, _)| a % 2 == 0) {
+            tree.remove(&(a..b));
+        }
+        for p in 0..1050 {
+            let expected = all.iter().filter(|&&(a, b)| a % 2 == 1 && a <= p && p < b).count();
+            assert_eq!(tree.stabbing_count(&p), expected);
+        }
+    }
+}
@@ -1,19 +1,229 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 //! A self balancing binary tree
-use std::cmp::{Ord, Ordering, max};
-use std::mem::replace;
-use std::collections::VecDeque;
+//!
+//! The `std` feature is on by default. Without it the crate needs only `core` and `alloc`, and
+//! leaves out the modules that need threads or I/O: `concurrent` and `snapshot`.
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[macro_use]
+extern crate alloc;
+#[cfg_attr(test, macro_use)]
+extern crate ordered_map;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "std")]
+extern crate crossbeam_epoch;
 
+use core::cmp::{Ord, Ordering, max};
+use core::convert::Infallible;
+use core::mem::{MaybeUninit, replace, take};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use ordered_map::OrderedMap;
+pub use ordered_map::AllocError;
+use ordered_map::try_box;
 
-struct AvlNode<K: Ord, V> {
+///counts an event with the `metrics` feature, and compiles to nothing without it
+#[cfg(feature = "metrics")]
+macro_rules! record {
+    ($event:ident($($arg:expr),*)) => { ::metrics::$event($($arg),*) }
+}
+#[cfg(not(feature = "metrics"))]
+macro_rules! record {
+    ($($ignored:tt)*) => {}
+}
+
+///runs an operation and adds the work it counted to the counters kept in the tree with the
+///`metrics` feature, and just runs it without
+#[cfg(feature = "metrics")]
+macro_rules! measured {
+    ($tree:expr, $op:expr) => {{
+        let start = ::metrics::running();
+        let result = $op;
+        $tree.1.add_since(start);
+        result
+    }}
+}
+#[cfg(not(feature = "metrics"))]
+macro_rules! measured {
+    ($tree:expr, $op:expr) => { $op }
+}
+
+#[cfg(feature = "std")]
+pub mod concurrent;
+pub mod cursor;
+mod dump;
+pub mod expiring;
+pub mod interval;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod multi;
+pub mod observed;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod persistent;
+pub mod priority_queue;
+pub mod seq;
+pub mod slab;
+#[cfg(feature = "std")]
+pub mod snapshot;
+pub mod stats;
+pub mod summary;
+pub mod transaction;
+pub mod versioned;
+#[cfg(feature = "serde")]
+mod serde_impls;
+
+use summary::Summary;
+
+///what an insert gives back: the key's old value, if it had one, and the node now holding the
+///key. a node keeps its address while it is in the tree, since rebalancing only moves the boxes
+///the nodes are in
+type Inserted<K, V, S> = (Option<V>, *const AvlNode<K,V,S>);
+
+struct AvlNode<K: Ord, V, S: Summary<K,V>> {
     key: K,
     val: V,
     height: i32,
-    left: AvlTree<K,V>,
-    right: AvlTree<K,V>
+    summary: S::Value,
+    left: AvlTree<K,V,S>,
+    right: AvlTree<K,V,S>
+}
+
+///the rotations rebalancing can make, named by the direction the rebalanced node moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Turn {
+    Left,
+    Right,
+    LeftRight,
+    RightLeft
+}
+
+/// A map based on a binary tree that self balances using the AVL algorithm.
+///
+/// Every subtree can keep a [`Summary`](summary/trait.Summary.html) of its entries, which is
+/// used to aggregate ranges of keys with `aggregate`. The default summary, `()`, keeps nothing.
+///
+/// With the `serde` feature, the tree is serialized as a map in sorted order. Deserializing
+/// builds a balanced tree from all of the entries at once. The entries may come in any order,
+/// and if a key appears more than once the last value for it is kept, just as if the entries
+/// had been inserted one at a time.
+///
+/// With the `metrics` feature, the tree also counts the work its own operations do, read with
+/// `counters`.
+pub struct AvlTree<K: Ord, V, S: Summary<K,V> = ()> (Option<Box<AvlNode<K,V,S>>>, Meter);
+
+//the counters a tree keeps with the `metrics` feature, which take no space without it
+#[cfg(feature = "metrics")]
+use metrics::Meter;
+#[cfg(not(feature = "metrics"))]
+#[derive(Default)]
+struct Meter;
+
+/// Memory for new nodes of an AvlTree, set aside ahead of time with `try_reserve` so that
+/// inserts made with `AvlTree::insert_reserved` can not fail. A tree allocates each node on
+/// its own, and every subtree is itself an `AvlTree`, so the room is held here rather than in
+/// the tree. It can be used with any tree of the same types.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::{AvlTree, NodeReserve};
+///
+/// let mut tree = AvlTree::new();
+/// let mut reserve = NodeReserve::new();
+/// reserve.try_reserve(10).expect("out of memory");
+///
+/// for i in 0..10 {
+///     assert_eq!(tree.insert_reserved(i, i, &mut reserve), Ok(None));
+/// }
+/// assert!(reserve.is_empty());
+/// ```
+pub struct NodeReserve<K: Ord, V, S: Summary<K,V> = ()>(Vec<Box<MaybeUninit<AvlNode<K,V,S>>>>);
+
+impl<K: Ord, V, S: Summary<K,V>> NodeReserve<K,V,S> {
+    /// Creates an empty reserve
+    pub fn new() -> Self {
+        NodeReserve(Vec::new())
+    }
+
+    /// Sets aside the memory for `additional` more nodes, giving back an error instead of
+    /// aborting if it can not be allocated, in which case some of it may have been set aside
+    /// all the same.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        self.0.try_reserve(additional)?;
+        for _ in 0..additional {
+            let node = try_box(MaybeUninit::uninit()).map_err(|(err, _)| err)?;
+            self.0.push(node);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of nodes set aside
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Checks if no nodes are set aside
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
-/// A map based on a binary tree that self balances using the AVL algorithm
-pub struct AvlTree<K: Ord, V> (Option<Box<AvlNode<K,V>>>);
+impl<K: Ord, V, S: Summary<K,V>> Default for NodeReserve<K,V,S> {
+    fn default() -> Self {
+        NodeReserve::new()
+    }
+}
+
+/// The ways an AvlTree can be found broken by `AvlTree::validate`. Nodes are named by their
+/// index in sorted order, so that keys do not need to be printable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The key of the node at this index is not greater than the key sorted before it
+    OutOfOrder {
+        /// the node's index in sorted order
+        index: usize
+    },
+    /// The node at this index stores a height that does not match its subtrees
+    WrongHeight {
+        /// the node's index in sorted order
+        index: usize,
+        /// the height stored in the node
+        stored: i32,
+        /// the height computed from the node's subtrees
+        actual: i32
+    },
+    /// The subtrees of the node at this index differ in height by more than one
+    Unbalanced {
+        /// the node's index in sorted order
+        index: usize,
+        /// the right subtree's height minus the left subtree's height
+        balance: i32
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::OutOfOrder { index } =>
+                write!(f, "node {} is not greater than the node before it", index),
+            ValidationError::WrongHeight { index, stored, actual } =>
+                write!(f, "node {} stores height {} but has height {}", index, stored, actual),
+            ValidationError::Unbalanced { index, balance } =>
+                write!(f, "node {} has a balance of {}", index, balance)
+        }
+    }
+}
+
+impl Error for ValidationError {}
 
 /// A struct used to iterate over values of the AvlTree
 pub struct Iter<'a, K: 'a, V: 'a> {
@@ -25,24 +235,50 @@ impl<'a, K: 'a + Ord, V: 'a> Iter<'a,K, V> {
 
     ///creates an iterator containing all elements between start and end, inclusive,
     ///Unbounded if the None
-    fn new(tree: &'a AvlTree<K,V>, start: Option<&K>, end: Option<&K>, forwards: bool) -> Self {
+    fn new<S: Summary<K,V>>(tree: &'a AvlTree<K,V,S>, start: Option<&K>, end: Option<&K>, forwards: bool) -> Self {
+        let below = |key: &K| start.is_some_and(|start| {
+            record!(compared());
+            *key < *start
+        });
+        let above = |key: &K| end.is_some_and(|end| {
+            record!(compared());
+            *key > *end
+        });
+        Self::by(tree, forwards, |key| {
+            if below(key) {
+                Ordering::Less
+            } else if above(key) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+    }
+
+    ///creates an iterator containing the elements of a range, where f orders every key against
+    ///the range. only the subtrees that can hold keys in the range are visited
+    fn by<S: Summary<K,V>, F: Fn(&K) -> Ordering>(tree: &'a AvlTree<K,V,S>, forwards: bool, f: F) -> Self {
         let mut iter = Iter { 
             queue: VecDeque::new(),
-            forwards: forwards
+            forwards
         };
-        iter.init(tree, start, end);
+        iter.init(tree, &f);
         iter
     }
 
-    fn init(&mut self, tree: &'a AvlTree<K,V>, start: Option<&K>, end: Option<&K>) {
-        tree.0.as_ref().map(|node| {
-            self.init(&node.left, start, end);
-            if (start.is_none() || *start.unwrap() <= node.key) && (end.is_none() || *end.unwrap() >= node.key) {
+    fn init<S: Summary<K,V>, F: Fn(&K) -> Ordering>(&mut self, tree: &'a AvlTree<K,V,S>, f: &F) {
+        if let Some(ref node) = tree.0 {
+            let order = f(&node.key);
+            if order != Ordering::Less {
+                self.init(&node.left, f);
+            }
+            if order == Ordering::Equal {
                 self.queue.push_back((&node.key,&node.val));
-            };
-            self.init(&node.right, start, end);
-            0
-        });
+            }
+            if order != Ordering::Greater {
+                self.init(&node.right, f);
+            }
+        }
     }
 }
 impl<'a, K: 'a + Ord , V: 'a> Iterator for Iter<'a,K, V> {
@@ -56,11 +292,122 @@ impl<'a, K: 'a + Ord , V: 'a> Iterator for Iter<'a,K, V> {
         }
 
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.queue.len(), Some(self.queue.len()))
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.forwards {
+            self.queue.pop_back()
+        } else {
+            self.queue.pop_front()
+        }
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> ExactSizeIterator for Iter<'a, K, V> {}
+
+///orders a key against the range of keys that start with prefix, going by their bytes
+fn prefix_order(key: &[u8], prefix: &[u8]) -> Ordering {
+    if key.starts_with(prefix) {
+        Ordering::Equal
+    } else {
+        key.cmp(prefix)
+    }
 }
 
 
 impl<K: Ord, V> AvlTree<K,V> {
 
+    /// Creates a new empty AvlTree
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::AvlTree;
+    ///
+    /// let mut tree = AvlTree::new();
+    ///
+    /// tree.insert(1,"a");
+    /// ```
+    pub fn new() -> Self {
+        AvlTree::from_root(None)
+    }
+
+    ///a mutable reference to the key's value, for the maps built on a tree. only for trees
+    ///with no summary, which changing a value in place would leave stale
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut tree = self;
+        while let Some(ref mut node) = tree.0 {
+            tree = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&mut node.val),
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right
+            };
+        }
+        None
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Creates a new empty AvlTree that keeps the summary `S` for all of its subtrees
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::AvlTree;
+    /// use avltree_map::summary::Count;
+    ///
+    /// let mut tree: AvlTree<i32, &str, Count> = AvlTree::with_summary();
+    ///
+    /// tree.insert(1,"a");
+    /// assert_eq!(tree.aggregate(None, None), 1);
+    /// ```
+    pub fn with_summary() -> Self {
+        AvlTree::from_root(None)
+    }
+
+    ///wraps a root, or nothing, as a tree with counters of its own
+    fn from_root(root: Option<Box<AvlNode<K,V,S>>>) -> Self {
+        AvlTree(root, Default::default())
+    }
+
+    /// Gives the work this tree's own operations have counted since it was created or
+    /// `reset_counters` was last called. Key comparisons are counted by inserting, finding and
+    /// removing keys and by iterating over ranges, and rotations by every insert and remove.
+    /// Only with the `metrics` feature.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::AvlTree;
+    ///
+    /// let mut tree = AvlTree::new();
+    /// for key in 0..3 {
+    ///     tree.insert(key, "a");
+    /// }
+    /// assert_eq!(tree.counters().comparisons, 3);
+    /// assert_eq!(tree.counters().left_rotations, 1);
+    ///
+    /// tree.reset_counters();
+    /// assert_eq!(tree.range_iter(Some(&2), None).count(), 1);
+    /// assert_eq!(tree.counters().comparisons, 2);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn counters(&self) -> metrics::Counters {
+        self.1.get()
+    }
+
+    /// Sets this tree's counts back to zero. Only with the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn reset_counters(&mut self) {
+        self.1.reset();
+    }
+
     /// Returns the height of the tree.
     /// The height is defined as The number of Nodes
     /// on the longest path from the root to any leaf.
@@ -101,25 +448,10 @@ impl<K: Ord, V> AvlTree<K,V> {
         }
     }
 
-    ///updates this subtree's heights. returns the height of the tree after updating
-    fn update_height(&mut self) -> i32{
-        match self.0 {
-            None => 0,
-            Some(ref mut node) => {
-                node.height = max(node.left.update_height(),node.right.update_height()) + 1;
-                node.height
-            }
-
-        }
-    }
-    ///updates this node's height.
+    ///updates this node's height and summary.
     fn update_one_height(&mut self) {
-        match self.0 {
-            None => (),
-            Some(ref mut node) => {
-                node.height = max(node.left.height(),node.right.height()) + 1;
-            }
-
+        if let Some(ref mut node) = self.0 {
+            node.update();
         }
     }
 
@@ -130,21 +462,24 @@ impl<K: Ord, V> AvlTree<K,V> {
         let mut parent_node = grandparent_node.as_mut().unwrap().left.0.take();
         //move parent's right to grandparent's left
         grandparent_node.as_mut().unwrap().left.0 = parent_node.as_mut().unwrap().right.0.take();
+        //grandparent's subtrees are final, so its height can be updated before moving it
+        grandparent_node.as_mut().unwrap().update();
         //move grandparent to parent's right
         parent_node.as_mut().unwrap().right.0 = grandparent_node;
         //move parent into self
         self.0 = parent_node;
-        //update heights based on new positions
-        self.update_height();
+        //only the two rotated nodes changed height, everything below them is untouched
+        self.update_one_height();
     }
 
     fn left_rot(&mut self) {
         let mut grandparent_node = self.0.take();
         let mut parent_node = grandparent_node.as_mut().unwrap().right.0.take();
         grandparent_node.as_mut().unwrap().right.0 = parent_node.as_mut().unwrap().left.0.take();
+        grandparent_node.as_mut().unwrap().update();
         parent_node.as_mut().unwrap().left.0 = grandparent_node;
         self.0 = parent_node;
-        self.update_height();
+        self.update_one_height();
     }
 
     fn right_left_rot(&mut self) {
@@ -158,39 +493,54 @@ impl<K: Ord, V> AvlTree<K,V> {
 
     }
 
-    fn rebalance(&mut self) {
+    ///rebalances this node, giving back the rotation made if there was one
+    fn rebalance(&mut self) -> Option<Turn> {
 
         //check balance
         let balance = self.check_balance();
         
         //too left leaning
-        if balance == -2 {
+        let turn = if balance == -2 {
             //if left subtree is left leaning or balanced, then right rotate, else
             //left-right rotate
             if self.0.as_mut().unwrap().left.check_balance() <= 0 {
                 self.right_rot();    
+                record!(rotated(::metrics::Rotation::Right, balance, self.height()));
+                Some(Turn::Right)
             }
             else {
                 self.left_right_rot();
+                record!(rotated(::metrics::Rotation::LeftRight, balance, self.height()));
+                Some(Turn::LeftRight)
             }
         }
         //too right leaning
         else if balance == 2 {
             if self.0.as_mut().unwrap().right.check_balance() >= 0 {
                 self.left_rot();    
+                record!(rotated(::metrics::Rotation::Left, balance, self.height()));
+                Some(Turn::Left)
             }
             else {
                 self.right_left_rot();
+                record!(rotated(::metrics::Rotation::RightLeft, balance, self.height()));
+                Some(Turn::RightLeft)
             }
 
-        };
+        }
         //else balanced, nothing to do
+        else {
+            None
+        };
 
         //update heights, not using update_height method to not do unneccesary recursions
         self.update_one_height();
+        turn
     }
 
-    /// Creates a new empty AvlTree
+    /// Inserts a key,value pair into the tree. Returns None if the key was
+    /// not present in the tree already. If the key was present, then the key is updated
+    /// with the new value and the old value is returned.
     ///
     /// #Examples
     ///
@@ -199,16 +549,35 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     /// let mut tree = AvlTree::new();
     ///
-    /// tree.insert(1,"a");
-    /// ```
-    pub fn new() -> Self {
-        AvlTree(None as Option<Box<AvlNode<K,V>>>)
+    /// assert_eq!(tree.insert(37, "a"), None);
+    /// assert_eq!(tree.is_empty(), false);
+    ///
+    /// tree.insert(37, "b");
+    /// assert_eq!(tree.insert(37, "c"), Some("b"));
+    /// assert_eq!(tree.get(&37), Some(&"c"));
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        self.insert_entry(key, val).0
     }
 
+    ///inserts like `insert`, also giving back the key and value now in the tree, for the
+    ///wrappers that report what they changed
+    pub(crate) fn insert_entry(&mut self, key: K, val: V) -> (Option<V>, &K, &V) {
+        let (old, node) = match measured!(self, self.insert_with(key, val, |node| Ok::<_, Infallible>(Box::new(node)))) {
+            Ok(result) => result,
+            Err(never) => match never {}
+        };
+        //the node is still in the tree, and the tree is borrowed for as long as it is used
+        let node = unsafe { &*node };
+        (old, &node.key, &node.val)
+    }
 
-    /// Inserts a key,value pair into the tree. Returns None if the key was
-    /// not present in the tree already. If the key was present, then the key is updated
-    /// with the new value and the old value is returned.
+    /// Inserts a key,value pair like `insert`, but gives back an error instead of aborting if
+    /// the memory for a new node can not be allocated, in which case the tree is left as it
+    /// was and the key and value are handed back with the error. Replacing the value of a key
+    /// that is already present never allocates.
+    ///
+    /// Where inserts must not fail at all, the room can be set aside up front with a
+    /// `NodeReserve` and `insert_reserved`.
     ///
     /// #Examples
     ///
@@ -217,44 +586,81 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     /// let mut tree = AvlTree::new();
     ///
-    /// assert_eq!(tree.insert(37, "a"), None);
-    /// assert_eq!(tree.is_empty(), false);
+    /// assert_eq!(tree.try_insert(37, "a"), Ok(None));
+    /// assert_eq!(tree.try_insert(37, "b"), Ok(Some("a")));
+    /// assert_eq!(tree.get(&37), Some(&"b"));
+    /// ```
+    pub fn try_insert(&mut self, key: K, val: V) -> Result<Option<V>, (AllocError, K, V)> {
+        measured!(self, self.insert_with(key, val, |node| {
+            try_box(node).map_err(|(err, node)| (err, node.key, node.val))
+        })).map(|(old, _)| old)
+    }
+
+    /// Inserts a key,value pair like `try_insert`, but takes the memory for a new node from the
+    /// reserve, so that it can only fail if the reserve is empty.
     ///
-    /// tree.insert(37, "b");
-    /// assert_eq!(tree.insert(37, "c"), Some("b"));
-    /// assert_eq!(tree.get(&37), Some(&"c"));
-    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::{AllocError, AvlTree, NodeReserve};
+    ///
+    /// let mut tree = AvlTree::new();
+    /// let mut reserve = NodeReserve::new();
+    /// reserve.try_reserve(1).expect("out of memory");
+    ///
+    /// assert_eq!(tree.insert_reserved(37, "a", &mut reserve), Ok(None));
+    /// assert_eq!(reserve.len(), 0);
+    /// //replacing a value needs no new node
+    /// assert_eq!(tree.insert_reserved(37, "b", &mut reserve), Ok(Some("a")));
+    /// ```
+    pub fn insert_reserved(&mut self, key: K, val: V, reserve: &mut NodeReserve<K,V,S>) -> Result<Option<V>, (AllocError, K, V)> {
+        measured!(self, self.insert_with(key, val, |node| match reserve.0.pop() {
+            Some(slot) => Ok(Box::write(slot, node)),
+            None => try_box(node).map_err(|(err, node)| (err, node.key, node.val))
+        })).map(|(old, _)| old)
+    }
+
+    ///inserts a key,value pair, boxing a new node with make, and gives back the old value
+    ///along with the node that now holds the key. If make fails, it fails before anything has
+    ///changed, so the error is passed straight back up without rebalancing.
+    fn insert_with<E, F>(&mut self, key: K, val: V, make: F) -> Result<Inserted<K,V,S>, E>
+        where F: FnOnce(AvlNode<K,V,S>) -> Result<Box<AvlNode<K,V,S>>, E>
+    {
         let result = match self.0 {
             //if there is no data here, insert the key-value pair here and return None
             None => {
-                self.0 = Some(Box::new(AvlNode {
-                    key: key,
-                    val: val,
+                let node = make(AvlNode {
+                    summary: S::summarize(&key, &val),
+                    key,
+                    val,
                     height: 1,
-                    left: AvlTree::new(),
-                    right: AvlTree::new()
-                }));
-                None
+                    left: AvlTree::from_root(None),
+                    right: AvlTree::from_root(None)
+                })?;
+                let placed: *const AvlNode<K,V,S> = &*node;
+                self.0 = Some(node);
+                (None, placed)
             }
             Some(ref mut root) => {
+                record!(compared());
                 //if key exists, swap out values, return old value
                 if key == root.key {
-                    Some(replace(&mut root.val,val))
+                    (Some(replace(&mut root.val,val)), &**root as *const AvlNode<K,V,S>)
                 }
                 //go left
                 else if key < root.key {
-                    root.left.insert(key,val)
+                    root.left.insert_with(key,val,make)?
                 }
                 //go right
                 else {
-                    root.right.insert(key,val)
+                    root.right.insert_with(key,val,make)?
                 }
             }
         };
         
         self.rebalance();
 
-        result
+        Ok(result)
     }
    
 
@@ -294,17 +700,19 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  ```
     pub fn get(&self, key: &K) -> Option<&V> {
+        measured!(self, self.find(key).map(|node| &node.val))
+    }
+
+    ///finds the node holding key
+    fn find(&self, key: &K) -> Option<&AvlNode<K,V,S>> {
         match self.0 {
             None => None,
             Some(ref node) => {
-                if *key == node.key {
-                    Some(&node.val)
-                }
-                else if *key < node.key {
-                    node.left.get(key)
-                }
-                else {
-                    node.right.get(key)
+                record!(compared());
+                match key.cmp(&node.key) {
+                    Ordering::Equal => Some(node),
+                    Ordering::Less => node.left.find(key),
+                    Ordering::Greater => node.right.find(key)
                 }
             }
         }
@@ -330,43 +738,46 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  ```
     pub fn remove(&mut self, key: &K) -> Option<V> {
-        let (result, replacement) = match self.0.take() {
-            None => (None, AvlTree(None) as AvlTree<K,V>),
-            Some(mut node) => {
-                match key.cmp(&node.key) {
-                    Ordering::Less => (node.left.remove(key), AvlTree(Some(node))),
-                    Ordering::Greater => (node.right.remove(key), AvlTree(Some(node))),
-                    Ordering::Equal => {
-                        let node = *node;
-                        let replacement = match (node.left.0, node.right.0) {
-                            //no subtrees, replacement is nothing
-                            (None,None) => AvlTree(None) as AvlTree<K,V>,
-                            //one subtree, replacement is the one subtree
-                            (Some(left),None) => AvlTree(Some(left)),
-                            (None,Some(right)) => AvlTree(Some(right)),
-                            //two subtrees, must move some thigns around to find a suitable
-                            //replacement
-                            (Some(left),Some(right)) => {
-                                    let mut newright = AvlTree(Some(right));
-                                    let mut min = newright.take_min();
-                                    min.right = newright;
-                                    min.left = AvlTree(Some(left));
-                                    AvlTree(Some(min))
-                            }
-                        };
-                        self.update_height();
-                        (Some(node.val),replacement)
+        measured!(self, self.remove_from(key))
+    }
+
+    ///removes key from the tree, keeping this tree's counters however the root changes. nodes
+    ///are only taken out once the key is found, so a comparison that panics leaves the tree
+    ///whole
+    fn remove_from(&mut self, key: &K) -> Option<V> {
+        let node = self.0.as_mut()?;
+        record!(compared());
+        let result = match key.cmp(&node.key) {
+            Ordering::Less => node.left.remove_from(key),
+            Ordering::Greater => node.right.remove_from(key),
+            Ordering::Equal => {
+                let node = *self.0.take().unwrap();
+                let replacement = match (node.left.0, node.right.0) {
+                    //no subtrees, replacement is nothing
+                    (None,None) => AvlTree::from_root(None),
+                    //one subtree, replacement is the one subtree
+                    (Some(left),None) => AvlTree::from_root(Some(left)),
+                    (None,Some(right)) => AvlTree::from_root(Some(right)),
+                    //two subtrees, must move some thigns around to find a suitable
+                    //replacement
+                    (Some(left),Some(right)) => {
+                            let mut newright = AvlTree::from_root(Some(right));
+                            let mut min = newright.take_min();
+                            min.right = newright;
+                            min.left = AvlTree::from_root(Some(left));
+                            AvlTree::from_root(Some(min))
                     }
-                }
+                };
+                self.0 = replacement.0;
+                Some(node.val)
             }
         };
-        *self = replacement;
 
         self.rebalance();
         result
     }
 
-    fn take_min(&mut self) -> Box<AvlNode<K,V>> {
+    fn take_min(&mut self) -> Box<AvlNode<K,V,S>> {
         let result = if self.0.as_ref().unwrap().left.is_empty() {
             let mut res = self.0.take().unwrap();
             self.0 = res.right.0.take();
@@ -375,10 +786,42 @@ impl<K: Ord, V> AvlTree<K,V> {
             self.0.as_mut().unwrap().left.take_min()
         };
 
-        self.update_one_height();
+        //taking a node out of the left side can leave this node right heavy
+        self.rebalance();
+        result
+    }
+
+    fn take_max(&mut self) -> Box<AvlNode<K,V,S>> {
+        let result = if self.0.as_ref().unwrap().right.is_empty() {
+            let mut res = self.0.take().unwrap();
+            self.0 = res.left.0.take();
+            res
+        } else {
+            self.0.as_mut().unwrap().right.take_max()
+        };
+
+        self.rebalance();
         result
     }
 
+    ///gives the node with the smallest key, if the tree is not empty
+    fn first(&self) -> Option<&AvlNode<K,V,S>> {
+        let mut node = self.0.as_ref()?;
+        while let Some(ref left) = node.left.0 {
+            node = left;
+        }
+        Some(node)
+    }
+
+    ///gives the node with the largest key, if the tree is not empty
+    fn last(&self) -> Option<&AvlNode<K,V,S>> {
+        let mut node = self.0.as_ref()?;
+        while let Some(ref right) = node.right.0 {
+            node = right;
+        }
+        Some(node)
+    }
+
     /// Takes a referenece to something of type Key and
     /// checks if the key is present. 
     ///
@@ -395,20 +838,7 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///  assert!(!tree.contains_key(&2));
     ///
     pub fn contains_key(&self, key: &K) -> bool {
-        match self.0 {
-            None => false,
-            Some(ref node) => {
-                if *key == node.key {
-                    true
-                }
-                else if *key < node.key {
-                    node.left.contains_key(key)
-                }
-                else {
-                    node.right.contains_key(key)
-                }
-            }
-        }
+        measured!(self, self.find(key).is_some())
     }
 
     /// Gives an iterator over the key-value pairs in the tree, sorted by key.
@@ -424,7 +854,7 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  assert_eq!(tree.iter().next().unwrap(), (&1, &"a"));
     ///
-    pub fn iter(&self) -> Iter<K,V> {
+    pub fn iter(&self) -> Iter<'_, K,V> {
        Iter::new(self, None, None, true)
     }
 
@@ -441,7 +871,7 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  assert_eq!(tree.reverse_iter().next().unwrap(), (&37, &"b"));
     ///
-    pub fn reverse_iter(&self) -> Iter<K,V> {
+    pub fn reverse_iter(&self) -> Iter<'_, K,V> {
        Iter::new(self, None, None, false)
     }
 
@@ -463,8 +893,8 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  assert_eq!(tree.range_iter(Some(&2), None).next().unwrap(), (&2, &"a"));
     ///
-    pub fn range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<K,V> {
-       Iter::new(self, start, end, true)
+    pub fn range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+       measured!(self, Iter::new(self, start, end, true))
     }
 
     /// Gives an iterator over the key-value pairs in the tree that fall within the given start and
@@ -484,15 +914,460 @@ impl<K: Ord, V> AvlTree<K,V> {
     ///
     ///  assert_eq!(tree.reverse_range_iter(None, Some(&3)).next().unwrap(), (&3, &"a"));
     ///
-    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<K,V> {
-       Iter::new(self, start, end, false)
+    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+       measured!(self, Iter::new(self, start, end, false))
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree whose keys start with the given
+    /// prefix, in sorted order. It can also be run backwards with `rev`. The keys that start
+    /// with a prefix form one range of the tree, so this runs in O(log n + k) for k matches.
+    ///
+    /// The prefix is matched against the bytes of each key, so the keys must sort the same way
+    /// their bytes do, as `String`, `&str` and `Vec<u8>` keys do.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(String::from("user/123/name"), "a");
+    ///  tree.insert(String::from("user/123/settings"), "b");
+    ///  tree.insert(String::from("user/1234/name"), "c");
+    ///  tree.insert(String::from("users"), "d");
+    ///
+    ///  let found: Vec<_> = tree.prefix_iter("user/123/").map(|(_, val)| *val).collect();
+    ///  assert_eq!(found, vec!["a", "b"]);
+    ///  assert_eq!(tree.prefix_iter("user/").rev().next(), Some((&String::from("user/1234/name"), &"c")));
+    ///
+    pub fn prefix_iter<P: AsRef<[u8]> + ?Sized>(&self, prefix: &P) -> Iter<'_, K,V> where K: AsRef<[u8]> {
+        let prefix = prefix.as_ref();
+        measured!(self, Iter::by(self, true, |key: &K| {
+            record!(compared());
+            prefix_order(key.as_ref(), prefix)
+        }))
+    }
+
+    /// Removes every entry whose key starts with the given prefix, giving them back as a tree
+    /// of their own. The tree is split around the matching range and put back together
+    /// without it, so this runs in O(log n) however many entries are removed. As with
+    /// `prefix_iter`, the keys must sort the same way their bytes do.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert("user/1/name", 1);
+    ///  tree.insert("user/2/name", 2);
+    ///  tree.insert("user/2/settings", 3);
+    ///  tree.insert("user/3/name", 4);
+    ///
+    ///  let removed = tree.remove_prefix("user/2/");
+    ///  assert_eq!(removed.iter().map(|(_, val)| *val).collect::<Vec<_>>(), vec![2, 3]);
+    ///  assert_eq!(tree.iter().map(|(_, val)| *val).collect::<Vec<_>>(), vec![1, 4]);
+    ///
+    pub fn remove_prefix<P: AsRef<[u8]> + ?Sized>(&mut self, prefix: &P) -> Self where K: AsRef<[u8]> {
+        let prefix = prefix.as_ref();
+        let tree = AvlTree::from_root(self.0.take());
+        let (below, rest) = tree.split_by(&|key: &K| prefix_order(key.as_ref(), prefix) != Ordering::Less);
+        let (within, above) = rest.split_by(&|key: &K| prefix_order(key.as_ref(), prefix) == Ordering::Greater);
+        self.0 = Self::join2(below, above).0;
+        within
+    }
+
+    /// Combines the summaries of all key-value pairs in the tree that fall within the given
+    /// start and end points (inclusive). If None is given, then that side is unbounded.
+    /// Runs in O(log n), using the summaries stored for every subtree.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///  use avltree_map::summary::Sum;
+    ///
+    ///  let mut tree: AvlTree<i32, i32, Sum> = AvlTree::with_summary();
+    ///  tree.insert(1,10);
+    ///  tree.insert(2,20);
+    ///  tree.insert(3,30);
+    ///  tree.insert(4,40);
+    ///  tree.insert(5,50);
+    ///
+    ///  assert_eq!(tree.aggregate(Some(&2), Some(&4)), 90);
+    ///  assert_eq!(tree.aggregate(None, Some(&2)), 30);
+    ///  assert_eq!(tree.aggregate(Some(&6), None), 0);
+    ///
+    pub fn aggregate(&self, start: Option<&K>, end: Option<&K>) -> S::Value {
+        match self.0 {
+            None => S::empty(),
+            Some(ref node) => {
+                //this node and its whole left subtree are out of range
+                if start.is_some_and(|start| node.key < *start) {
+                    node.right.aggregate(start, end)
+                }
+                //this node and its whole right subtree are out of range
+                else if end.is_some_and(|end| node.key > *end) {
+                    node.left.aggregate(start, end)
+                }
+                //the range is split by this node, so each side is only bounded on one end
+                else {
+                    let left = match start {
+                        None => node.left.summary(),
+                        Some(_) => node.left.aggregate(start, None)
+                    };
+                    let right = match end {
+                        None => node.right.summary(),
+                        Some(_) => node.right.aggregate(None, end)
+                    };
+                    S::combine(&S::combine(&left, &S::summarize(&node.key, &node.val)), &right)
+                }
+            }
+        }
+    }
+
+    /// Keeps only the entries for which f returns true, visiting them in sorted order. f may
+    /// also change the values it keeps. The kept entries are rebuilt into a balanced tree all
+    /// at once, so this runs in O(n) however many entries are removed.
+    ///
+    /// If f panics, the tree is rebuilt before the panic carries on, keeping the entries f
+    /// accepted, the one it was called on and every one it had not reached yet.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  for i in 0..10 {
+    ///      tree.insert(i, i);
+    ///  }
+    ///  tree.retain(|key, val| {
+    ///      *val *= 10;
+    ///      key % 3 == 0
+    ///  });
+    ///
+    ///  assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&0, &0), (&3, &30), (&6, &60), (&9, &90)]);
+    ///  ```
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        let mut entries = Vec::new();
+        AvlTree::from_root(self.0.take()).take_apart(&mut entries);
+        //Vec::retain_mut leaves every entry it has not finished with in place if f panics, and
+        //the guard builds the tree back out of whatever is left however retain_mut returns
+        let mut rebuild = Rebuild { tree: self, entries };
+        rebuild.entries.retain_mut(|(key, val)| f(key, val));
+    }
+
+    ///takes the tree apart into its entries, in order
+    fn take_apart(self, entries: &mut Vec<(K,V)>) {
+        if let Some(node) = self.0 {
+            let AvlNode { key, val, left, right, .. } = *node;
+            left.take_apart(entries);
+            entries.push((key, val));
+            right.take_apart(entries);
+        }
+    }
+
+    ///builds a perfectly balanced tree out of the next n entries of an iterator, which must
+    ///be sorted by key with no duplicates
+    fn from_sorted<I: Iterator<Item = (K,V)>>(entries: &mut I, n: usize) -> Self {
+        if n == 0 {
+            return AvlTree::from_root(None);
+        }
+        //the left side gets the extra entry when n - 1 is odd, so heights differ by at most one
+        let left = Self::from_sorted(entries, n / 2);
+        let (key, val) = entries.next().expect("fewer entries than promised");
+        let right = Self::from_sorted(entries, n - n / 2 - 1);
+        let mut node = AvlNode {
+            summary: S::summarize(&key, &val),
+            key,
+            val,
+            height: 1,
+            left,
+            right
+        };
+        node.update();
+        AvlTree::from_root(Some(Box::new(node)))
+    }
+
+    ///puts a node over two subtrees that are already balanced against each other
+    fn node(left: Self, key: K, val: V, right: Self) -> Self {
+        let mut node = AvlNode {
+            summary: S::summarize(&key, &val),
+            key,
+            val,
+            height: 1,
+            left,
+            right
+        };
+        node.update();
+        AvlTree::from_root(Some(Box::new(node)))
+    }
+
+    ///joins two trees with a key between them: every key in left is less than the given key,
+    ///and every key in right is greater. O(difference in heights). keys are never compared,
+    ///so this also concatenates the position ordered trees of an AvlSeq
+    fn join(left: Self, key: K, val: V, right: Self) -> Self {
+        let (left_height, right_height) = (left.height(), right.height());
+        let mut tree = if left_height > right_height + 1 {
+            //hang the new node off the right side of left, where the heights match
+            let mut tree = left;
+            {
+                let node = tree.0.as_mut().unwrap();
+                let inner = replace(&mut node.right, AvlTree::from_root(None));
+                node.right = Self::join(inner, key, val, right);
+            }
+            tree
+        } else if right_height > left_height + 1 {
+            let mut tree = right;
+            {
+                let node = tree.0.as_mut().unwrap();
+                let inner = replace(&mut node.left, AvlTree::from_root(None));
+                node.left = Self::join(left, key, val, inner);
+            }
+            tree
+        } else {
+            return Self::node(left, key, val, right);
+        };
+        tree.rebalance();
+        tree
+    }
+
+    ///joins two trees where every key in left is less than every key in right
+    fn join2(left: Self, mut right: Self) -> Self {
+        if right.is_empty() {
+            return left;
+        }
+        let min = right.take_min();
+        let min = *min;
+        Self::join(left, min.key, min.val, right)
+    }
+
+    ///splits the tree into the keys f is false for and the keys it is true for, where every
+    ///key f is true for must come after every key it is false for. O(log n)
+    fn split_by<F: Fn(&K) -> bool>(self, f: &F) -> (Self, Self) {
+        match self.0 {
+            None => (AvlTree::from_root(None), AvlTree::from_root(None)),
+            Some(node) => {
+                let AvlNode { key, val, left, right, .. } = *node;
+                if f(&key) {
+                    let (before, after) = left.split_by(f);
+                    (before, Self::join(after, key, val, right))
+                } else {
+                    let (before, after) = right.split_by(f);
+                    (Self::join(left, key, val, before), after)
+                }
+            }
+        }
+    }
+
+    ///splits the tree into the entries below the key, the entry for the key if there is one,
+    ///and the entries above it
+    fn split(self, key: &K) -> (Self, Option<(K,V)>, Self) {
+        match self.0 {
+            None => (AvlTree::from_root(None), None, AvlTree::from_root(None)),
+            Some(node) => {
+                let node = *node;
+                match key.cmp(&node.key) {
+                    Ordering::Less => {
+                        let (below, found, above) = node.left.split(key);
+                        (below, found, Self::join(above, node.key, node.val, node.right))
+                    },
+                    Ordering::Greater => {
+                        let (below, found, above) = node.right.split(key);
+                        (Self::join(node.left, node.key, node.val, below), found, above)
+                    },
+                    Ordering::Equal => (node.left, Some((node.key, node.val)), node.right)
+                }
+            }
+        }
+    }
+
+    ///takes the root off a tree, leaving its two subtrees
+    fn expose(self) -> Option<(Self, K, V, Self)> {
+        self.0.map(|node| {
+            let node = *node;
+            (node.left, node.key, node.val, node.right)
+        })
+    }
+
+    ///gives the summary of this whole subtree
+    fn summary(&self) -> S::Value {
+        match self.0 {
+            None => S::empty(),
+            Some(ref node) => node.summary.clone()
+        }
+    }
+
+    /// Checks that the tree is a valid AVL tree: keys are in strictly increasing order, every
+    /// node stores its correct height, and no node's subtrees differ in height by more than one.
+    /// Returns the first problem found in sorted order. This walks the whole tree, so it is
+    /// meant for tests and debugging.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  for i in 0..100 {
+    ///      tree.insert(i, i);
+    ///  }
+    ///
+    ///  assert_eq!(tree.validate(), Ok(()));
+    ///
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut index = 0;
+        let mut prev = None;
+        self.validate_from(&mut index, &mut prev).map(|_| ())
+    }
+
+    ///validates this subtree, given the number of nodes sorted before it and the key just
+    ///before it. returns the subtree's actual height
+    fn validate_from<'a>(&'a self, index: &mut usize, prev: &mut Option<&'a K>) -> Result<i32, ValidationError> {
+        match self.0 {
+            None => Ok(0),
+            Some(ref node) => {
+                let left = node.left.validate_from(index, prev)?;
+
+                let own = *index;
+                if prev.is_some_and(|prev| *prev >= node.key) {
+                    return Err(ValidationError::OutOfOrder { index: own });
+                }
+                *prev = Some(&node.key);
+                *index += 1;
+
+                let right = node.right.validate_from(index, prev)?;
+
+                let actual = max(left, right) + 1;
+                if node.height != actual {
+                    return Err(ValidationError::WrongHeight { index: own, stored: node.height, actual });
+                }
+                if (right - left).abs() > 1 {
+                    return Err(ValidationError::Unbalanced { index: own, balance: right - left });
+                }
+                Ok(actual)
+            }
+        }
     }
 
 }
 
+impl<K: Ord, V, S: Summary<K,V>> AvlNode<K,V,S> {
+    ///recomputes this node's height and summary from its subtrees
+    fn update(&mut self) {
+        self.height = max(self.left.height(),self.right.height()) + 1;
+        let own = S::summarize(&self.key, &self.val);
+        let with_left = match self.left.0 {
+            None => own,
+            Some(ref left) => S::combine(&left.summary, &own)
+        };
+        self.summary = match self.right.0 {
+            None => with_left,
+            Some(ref right) => S::combine(&with_left, &right.summary)
+        };
+    }
+}
+
+///builds a tree back out of sorted entries when dropped, so that it is put back even when
+///whatever was done to the entries panicked
+struct Rebuild<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a> {
+    tree: &'a mut AvlTree<K,V,S>,
+    entries: Vec<(K,V)>
+}
+
+impl<'a, K: Ord, V, S: Summary<K,V>> Drop for Rebuild<'a, K,V,S> {
+    fn drop(&mut self) {
+        let entries = take(&mut self.entries);
+        let n = entries.len();
+        self.tree.0 = AvlTree::from_sorted(&mut entries.into_iter(), n).0;
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> Default for AvlTree<K,V,S> {
+    fn default() -> Self {
+        AvlTree::from_root(None)
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> OrderedMap<K,V> for AvlTree<K,V,S> {
+    type Iter<'a> = Iter<'a,K,V> where K: 'a, V: 'a, S: 'a;
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        AvlTree::insert(self, key, val)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        AvlTree::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        AvlTree::remove(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        AvlTree::contains_key(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        AvlTree::is_empty(self)
+    }
+
+    fn iter(&self) -> Iter<'_, K,V> {
+        AvlTree::iter(self)
+    }
+
+    fn reverse_iter(&self) -> Iter<'_, K,V> {
+        AvlTree::reverse_iter(self)
+    }
+
+    fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        AvlTree::range_iter(self, start, end)
+    }
+
+    fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        AvlTree::reverse_range_iter(self, start, end)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use AvlTree;
+    use {AllocError, AvlTree, NodeReserve};
+    use ValidationError;
+    use summary::{Count, Sum, Max};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::ptr;
+    use ordered_map::conformance::XorShift;
+
+    ///an allocator that fails every allocation made on a thread while that thread has
+    ///FAILING set, so allocation failures can be tested without disturbing other tests
+    struct Flaky;
+
+    thread_local!(static FAILING: Cell<bool> = const { Cell::new(false) });
+
+    unsafe impl GlobalAlloc for Flaky {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if FAILING.try_with(Cell::get).unwrap_or(false) { ptr::null_mut() } else { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Flaky = Flaky;
+
+    ///runs f with every allocation on this thread failing
+    pub fn failing_allocations<T, F: FnOnce() -> T>(f: F) -> T {
+        FAILING.with(|failing| failing.set(true));
+        let result = f();
+        FAILING.with(|failing| failing.set(false));
+        result
+    }
 
 
     #[test]
@@ -515,6 +1390,49 @@ mod test {
     }
 
 
+    #[test]
+    fn test_try_insert() {
+        let mut tree: AvlTree<i32, i32, Sum> = AvlTree::with_summary();
+        for num in 0..100 {
+            tree.insert(num * 2, num);
+        }
+        let before: Vec<_> = tree.iter().map(|(&k, &v)| (k, v)).collect();
+
+        assert_eq!(failing_allocations(|| tree.try_insert(51, 0)), Err((AllocError, 51, 0)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(), before);
+        assert_eq!(tree.aggregate(None, None), (0..100).sum::<i32>());
+
+        //replacing a value needs no new node
+        assert_eq!(failing_allocations(|| tree.try_insert(50, 7)), Ok(Some(25)));
+        assert_eq!(tree.try_insert(51, 0), Ok(None));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.aggregate(None, None), (0..100).sum::<i32>() - 25 + 7);
+    }
+
+    #[test]
+    fn test_insert_reserved() {
+        let mut tree = AvlTree::new();
+        let mut reserve = NodeReserve::new();
+        assert_eq!(reserve.try_reserve(50), Ok(()));
+
+        //new keys take nodes from the reserve, and replaced values need none
+        let results = failing_allocations(|| {
+            let mut results = [Ok(None); 4];
+            for key in 0..50 {
+                results[0] = results[0].and(tree.insert_reserved(key, key, &mut reserve));
+            }
+            results[1] = tree.insert_reserved(10, 0, &mut reserve);
+            results[2] = tree.insert_reserved(50, 50, &mut reserve);
+            results[3] = tree.insert_reserved(10, 1, &mut reserve);
+            results
+        });
+        assert_eq!(results, [Ok(None), Ok(Some(10)), Err((AllocError, 50, 50)), Ok(Some(0))]);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().count(), 50);
+        assert!(reserve.is_empty());
+    }
+
     #[test]
     fn test_remove() {
         let mut tree = AvlTree::new();
@@ -586,4 +1504,164 @@ mod test {
         assert!(c == 0);
 
     }
+
+    #[test]
+    fn test_aggregate() {
+        let mut sums: AvlTree<i32, i32, Sum> = AvlTree::with_summary();
+        let mut counts: AvlTree<i32, i32, Count> = AvlTree::with_summary();
+        let mut maxes: AvlTree<i32, i32, Max> = AvlTree::with_summary();
+        for num in 1..1000 {
+            sums.insert(num, num);
+            counts.insert(num, num);
+            maxes.insert(num, num % 100);
+        }
+        //removing exercises the two subtree case and rotations on the way back up
+        for num in (1..1000).filter(|n| n % 3 == 0) {
+            sums.remove(&num);
+            counts.remove(&num);
+            maxes.remove(&num);
+        }
+
+        for &(start, end) in [(1, 999), (10, 20), (500, 400), (0, 5000), (99, 99), (300, 310)].iter() {
+            let kept = (start..end + 1).filter(|n| n % 3 != 0 && *n >= 1 && *n < 1000);
+            assert_eq!(sums.aggregate(Some(&start), Some(&end)), kept.clone().sum::<i32>());
+            assert_eq!(counts.aggregate(Some(&start), Some(&end)), kept.clone().count());
+            assert_eq!(maxes.aggregate(Some(&start), Some(&end)), kept.map(|n| n % 100).max());
+        }
+        assert_eq!(sums.aggregate(None, Some(&10)), 1 + 2 + 4 + 5 + 7 + 8 + 10);
+        assert_eq!(counts.aggregate(Some(&991), None), 6);
+
+        //replacing a value updates the stored summaries
+        sums.insert(1, 1001);
+        assert_eq!(sums.aggregate(None, Some(&2)), 1003);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tree = AvlTree::new();
+        assert_eq!(tree.validate(), Ok(()));
+        for num in 0..10 {
+            tree.insert(num, num);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        //break each invariant by hand
+        tree.0.as_mut().unwrap().height += 1;
+        assert_eq!(tree.validate(), Err(ValidationError::WrongHeight { index: 3, stored: 5, actual: 4 }));
+        tree.0.as_mut().unwrap().height -= 1;
+
+        tree.0.as_mut().unwrap().key = 100;
+        assert_eq!(tree.validate(), Err(ValidationError::OutOfOrder { index: 4 }));
+        tree.0.as_mut().unwrap().key = 3;
+
+        let right = tree.0.as_mut().unwrap().right.0.take();
+        assert_eq!(tree.validate(), Err(ValidationError::WrongHeight { index: 3, stored: 4, actual: 3 }));
+        tree.0.as_mut().unwrap().height = 3;
+        assert_eq!(tree.validate(), Err(ValidationError::Unbalanced { index: 3, balance: -2 }));
+        tree.0.as_mut().unwrap().right.0 = right;
+        tree.0.as_mut().unwrap().height = 4;
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    ///runs a long random mix of operations on an AvlTree and a BTreeMap, checking after every
+    ///step that they agree and that the tree is still valid
+    fn differential(seed: u64, steps: usize, key_space: u64) {
+        let mut rng = XorShift::new(seed);
+        let mut tree = AvlTree::new();
+        let mut model = BTreeMap::new();
+
+        for step in 0..steps {
+            let key = rng.next(key_space);
+            match rng.next(10) {
+                0..=4 => assert_eq!(tree.insert(key, step), model.insert(key, step)),
+                5..=7 => assert_eq!(tree.remove(&key), model.remove(&key)),
+                8 => assert_eq!(tree.get(&key), model.get(&key)),
+                _ => {
+                    let end = key + rng.next(key_space / 4 + 1);
+                    let found: Vec<_> = tree.range_iter(Some(&key), Some(&end)).collect();
+                    let expected: Vec<_> = model.range(key..end + 1).collect();
+                    assert_eq!(found, expected);
+                }
+            }
+            if let Err(err) = tree.validate() {
+                panic!("seed {} step {}: {}", seed, step, err);
+            }
+        }
+
+        assert_eq!(tree.iter().collect::<Vec<_>>(), model.iter().collect::<Vec<_>>());
+        assert_eq!(tree.reverse_iter().collect::<Vec<_>>(), model.iter().rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_differential() {
+        //a small key space makes removes of present keys, and so two subtree removes, common
+        for seed in 1..20 {
+            differential(seed, 2000, 64);
+        }
+        differential(0x9E3779B97F4A7C15, 20000, 4096);
+    }
+
+    ///the values under the keys that start with prefix, in order
+    fn prefixed(tree: &AvlTree<Vec<u8>, usize, Count>, prefix: &[u8]) -> Vec<usize> {
+        tree.prefix_iter(prefix).map(|(_, &val)| val).collect()
+    }
+
+    #[test]
+    fn test_prefix() {
+        //keys at the edges of the byte range, where a prefix's range of keys ends
+        let keys: Vec<Vec<u8>> = vec![vec![], vec![0], vec![1], vec![1, 0], vec![1, 0xFF],
+                                      vec![1, 0xFF, 0xFF], vec![2], vec![0xFF], vec![0xFF, 0xFF]];
+        let mut tree: AvlTree<Vec<u8>, usize, Count> = AvlTree::with_summary();
+        for (val, key) in keys.into_iter().enumerate() {
+            tree.insert(key, val);
+        }
+        assert_eq!(prefixed(&tree, &[]), (0..9).collect::<Vec<_>>());
+        assert_eq!(prefixed(&tree, &[1]), vec![2, 3, 4, 5]);
+        assert_eq!(prefixed(&tree, &[1, 0xFF]), vec![4, 5]);
+        assert_eq!(prefixed(&tree, &[0xFF]), vec![7, 8]);
+        assert_eq!(prefixed(&tree, &[1, 1]), Vec::<usize>::new());
+        assert_eq!(prefixed(&tree, &[0xFF, 0xFF, 0xFF]), Vec::<usize>::new());
+        assert_eq!(tree.prefix_iter(&[1][..]).rev().map(|(_, &val)| val).collect::<Vec<_>>(), vec![5, 4, 3, 2]);
+
+        let removed = tree.remove_prefix(&[1, 0xFF][..]);
+        assert_eq!(removed.iter().map(|(_, &val)| val).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(removed.validate(), Ok(()));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.aggregate(None, None), 7);
+
+        //a prefix nothing starts with removes nothing, and the empty prefix removes everything
+        assert!(tree.remove_prefix(&[1, 1][..]).is_empty());
+        assert_eq!(tree.aggregate(None, None), 7);
+        assert_eq!(tree.remove_prefix(&[][..]).aggregate(None, None), 7);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_retain_panic() {
+        let mut tree: AvlTree<i32, i32, Count> = AvlTree::with_summary();
+        for key in 0..100 {
+            tree.insert(key, key);
+        }
+        let result = catch_unwind(AssertUnwindSafe(|| tree.retain(|&key, val| {
+            if key == 50 {
+                panic!("retain");
+            }
+            *val += 1;
+            key % 2 == 0
+        })));
+        assert!(result.is_err());
+        assert_eq!(tree.validate(), Ok(()));
+        //the entries before the panic were filtered and changed, the rest are untouched
+        let expected: Vec<_> = (0..50).filter(|key| key % 2 == 0).map(|key| (key, key + 1))
+            .chain((50..100).map(|key| (key, key)))
+            .collect();
+        assert_eq!(tree.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(), expected);
+        assert_eq!(tree.aggregate(None, None), expected.len());
+    }
+
+    mod conformance {
+        use AvlTree;
+
+        ordered_map_conformance!(AvlTree<i32, i32>);
+    }
 }
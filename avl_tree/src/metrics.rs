@@ -0,0 +1,246 @@
+//! Counters for the work AvlTree operations do, with the `metrics` feature.
+//!
+//! Every `AvlTree` counts the key comparisons and rotations its own operations make, read with
+//! `AvlTree::counters` and cleared with `AvlTree::reset_counters`, and every rebalancing
+//! rotation can be reported to an `Observer` as it happens. The observer belongs to the
+//! current thread, and hears about every tree the thread changes. Without the feature none of
+//! this is compiled in, and the trees do no extra work.
+//!
+//! #Examples
+//!
+//! ```
+//! use avltree_map::AvlTree;
+//!
+//! let mut small = AvlTree::new();
+//! let mut large = AvlTree::new();
+//! for key in 0..1000 {
+//!     large.insert(key, "a");
+//! }
+//! large.reset_counters();
+//!
+//! //a missing key is compared against one node on every level
+//! small.insert(1, "a");
+//! assert_eq!(large.get(&1000), None);
+//! assert_eq!(small.counters().comparisons, 0);
+//! assert_eq!(large.counters().comparisons, large.height() as u64);
+//! ```
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The number of each kind of event counted for one tree since it was created or its counters
+/// were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Nodes a key was compared against while inserting, finding or removing it
+    pub comparisons: u64,
+    /// Single rotations lifting a right child
+    pub left_rotations: u64,
+    /// Single rotations lifting a left child
+    pub right_rotations: u64,
+    /// Double rotations, which are not also counted as the two single rotations they are
+    /// made of
+    pub double_rotations: u64
+}
+
+/// The rotations rebalancing can make, named by the direction the node being rebalanced
+/// moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// The right child is lifted over the node
+    Left,
+    /// The left child is lifted over the node
+    Right,
+    /// The left child's right child is lifted over both
+    LeftRight,
+    /// The right child's left child is lifted over both
+    RightLeft
+}
+
+/// One rebalancing step, as reported to an `Observer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebalanceStep {
+    /// The rotation made
+    pub rotation: Rotation,
+    /// The node's balance before the rotation: its right subtree's height minus its left's
+    pub balance: i32,
+    /// The height of the subtree after the rotation
+    pub height: i32
+}
+
+/// Receives a trace of rebalancing as it happens on this thread. Install one with
+/// `set_observer`.
+pub trait Observer {
+    /// Called after every rotation
+    fn rebalanced(&mut self, step: &RebalanceStep);
+}
+
+thread_local! {
+    //everything counted on this thread, which each operation takes its share of into its tree
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+    static OBSERVER: RefCell<Option<Box<dyn Observer>>> = RefCell::new(None);
+}
+
+///the counters kept in a tree. they are atomic so that a tree shared between threads can
+///still count the lookups each of them makes through `&self`
+#[derive(Default)]
+pub(crate) struct Meter {
+    comparisons: AtomicU64,
+    left_rotations: AtomicU64,
+    right_rotations: AtomicU64,
+    double_rotations: AtomicU64
+}
+
+impl Meter {
+    pub(crate) fn get(&self) -> Counters {
+        Counters {
+            comparisons: self.comparisons.load(Relaxed),
+            left_rotations: self.left_rotations.load(Relaxed),
+            right_rotations: self.right_rotations.load(Relaxed),
+            double_rotations: self.double_rotations.load(Relaxed)
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.comparisons.store(0, Relaxed);
+        self.left_rotations.store(0, Relaxed);
+        self.right_rotations.store(0, Relaxed);
+        self.double_rotations.store(0, Relaxed);
+    }
+
+    ///adds everything counted on this thread since start, as given by running
+    pub(crate) fn add_since(&self, start: Counters) {
+        let now = running();
+        self.comparisons.fetch_add(now.comparisons.wrapping_sub(start.comparisons), Relaxed);
+        self.left_rotations.fetch_add(now.left_rotations.wrapping_sub(start.left_rotations), Relaxed);
+        self.right_rotations.fetch_add(now.right_rotations.wrapping_sub(start.right_rotations), Relaxed);
+        self.double_rotations.fetch_add(now.double_rotations.wrapping_sub(start.double_rotations), Relaxed);
+    }
+}
+
+///gives everything counted on this thread so far
+pub(crate) fn running() -> Counters {
+    COUNTERS.with(Cell::get)
+}
+
+/// Installs an observer for rebalancing on this thread, or removes it with None, giving back
+/// the one it replaces.
+///
+/// #Examples
+///
+/// ```
+/// use std::rc::Rc;
+/// use std::cell::RefCell;
+/// use avltree_map::AvlTree;
+/// use avltree_map::metrics::{self, Observer, RebalanceStep, Rotation};
+///
+/// struct Trace(Rc<RefCell<Vec<RebalanceStep>>>);
+///
+/// impl Observer for Trace {
+///     fn rebalanced(&mut self, step: &RebalanceStep) {
+///         self.0.borrow_mut().push(*step);
+///     }
+/// }
+///
+/// let steps = Rc::new(RefCell::new(Vec::new()));
+/// metrics::set_observer(Some(Box::new(Trace(steps.clone()))));
+///
+/// let mut tree = AvlTree::new();
+/// tree.insert(3, "c");
+/// tree.insert(1, "a");
+/// tree.insert(2, "b");
+/// metrics::set_observer(None);
+///
+/// assert_eq!(steps.borrow()[0].rotation, Rotation::LeftRight);
+/// assert_eq!(steps.borrow()[0].height, 2);
+/// ```
+pub fn set_observer(observer: Option<Box<dyn Observer>>) -> Option<Box<dyn Observer>> {
+    OBSERVER.with(|current| current.replace(observer))
+}
+
+///counts one comparison
+pub(crate) fn compared() {
+    COUNTERS.with(|counters| {
+        let mut counts = counters.get();
+        counts.comparisons += 1;
+        counters.set(counts);
+    });
+}
+
+///counts a rotation and reports it to the observer
+pub(crate) fn rotated(rotation: Rotation, balance: i32, height: i32) {
+    COUNTERS.with(|counters| {
+        let mut counts = counters.get();
+        match rotation {
+            Rotation::Left => counts.left_rotations += 1,
+            Rotation::Right => counts.right_rotations += 1,
+            Rotation::LeftRight | Rotation::RightLeft => counts.double_rotations += 1
+        }
+        counters.set(counts);
+    });
+    OBSERVER.with(|observer| {
+        //an observer that touches a tree itself does not hear about its own rotations
+        if let Ok(mut observer) = observer.try_borrow_mut() {
+            if let Some(ref mut observer) = *observer {
+                observer.rebalanced(&RebalanceStep { rotation, balance, height });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+
+    #[test]
+    fn test_counters() {
+        let mut tree = AvlTree::new();
+        for key in 0..1000 {
+            tree.insert(key, key);
+        }
+        tree.reset_counters();
+        assert_eq!(tree.get(&1000), None);
+        let counters = tree.counters();
+        assert_eq!(counters.comparisons, tree.height() as u64);
+        assert_eq!(counters.left_rotations + counters.right_rotations + counters.double_rotations, 0);
+
+        //sequential inserts only ever lean right
+        tree.reset_counters();
+        for key in 1000..2000 {
+            tree.insert(key, key);
+        }
+        let counters = tree.counters();
+        assert!(counters.left_rotations > 900);
+        assert_eq!(counters.right_rotations + counters.double_rotations, 0);
+
+        tree.reset_counters();
+        for key in 0..2000 {
+            tree.remove(&key);
+        }
+        assert!(tree.counters().comparisons > 2000);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_counters_per_tree() {
+        let mut first = AvlTree::new();
+        let mut second = AvlTree::new();
+        for key in 0..100 {
+            first.insert(key, key);
+            second.insert(key, key);
+        }
+        second.insert(100, 100);
+        second.remove(&100);
+        assert!(second.counters().comparisons > first.counters().comparisons);
+
+        //a range compares the keys on both sides of it, and nothing with no bounds
+        first.reset_counters();
+        assert_eq!(first.iter().count(), 100);
+        assert_eq!(first.counters().comparisons, 0);
+        assert_eq!(first.range_iter(Some(&10), Some(&20)).count(), 11);
+        assert!(first.counters().comparisons >= 11);
+        let counted = first.counters().comparisons;
+        assert_eq!(first.reverse_range_iter(Some(&10), Some(&20)).count(), 11);
+        assert_eq!(first.counters().comparisons, 2 * counted);
+    }
+}
@@ -0,0 +1,452 @@
+//! Sorted collections that allow a key to appear more than once.
+//!
+//! `AvlMultiMap` keeps every value inserted under a key, in the order they were inserted, and
+//! `AvlMultiSet` keeps a count for every element. Both are AvlTrees with one node per distinct
+//! key, which keep the number of values under each subtree as a summary. That lets `rank` and
+//! `select` count every duplicate while still running in O(log n).
+use core::cmp::{Ord, Ordering};
+use alloc::collections::VecDeque;
+use alloc::collections::vec_deque;
+use alloc::vec::Vec;
+
+use {AvlNode, AvlTree, Iter};
+use summary::Summary;
+
+///counts the values under every subtree: the values kept for each key of a multimap, or the
+///count of each element of a multiset
+struct Multiplicity;
+
+impl<K, V> Summary<K, VecDeque<V>> for Multiplicity {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn summarize(_: &K, vals: &VecDeque<V>) -> usize {
+        vals.len()
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+impl<K> Summary<K, usize> for Multiplicity {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn summarize(_: &K, count: &usize) -> usize {
+        *count
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+///the number of values kept under keys less than the given key
+fn rank<K: Ord, V>(tree: &AvlTree<K,V,Multiplicity>, key: &K) -> usize
+    where Multiplicity: Summary<K,V,Value = usize> {
+    let mut tree = tree;
+    let mut rank = 0;
+    while let Some(ref node) = tree.0 {
+        if *key <= node.key {
+            tree = &node.left;
+        } else {
+            rank += node.left.summary() + Multiplicity::summarize(&node.key, &node.val);
+            tree = &node.right;
+        }
+    }
+    rank
+}
+
+///changes the value for a key in place, giving back what f gives, or None if the key is not
+///present. the shape of the tree does not change, so only the summaries on the way down need
+///fixing, on the way back up
+fn modify<K: Ord, V, R, F: FnOnce(&mut V) -> R>(tree: &mut AvlTree<K,V,Multiplicity>, key: &K, f: F) -> Option<R>
+    where Multiplicity: Summary<K,V,Value = usize> {
+    let node = tree.0.as_mut()?;
+    let result = match key.cmp(&node.key) {
+        Ordering::Less => modify(&mut node.left, key, f),
+        Ordering::Greater => modify(&mut node.right, key, f),
+        Ordering::Equal => Some(f(&mut node.val))
+    };
+    node.update();
+    result
+}
+
+///the node holding the value at the index in sorted order, and the index of the value among
+///that node's own
+fn select<K: Ord, V>(tree: &AvlTree<K,V,Multiplicity>, mut index: usize) -> Option<(&AvlNode<K,V,Multiplicity>, usize)>
+    where Multiplicity: Summary<K,V,Value = usize> {
+    let mut tree = tree;
+    while let Some(ref node) = tree.0 {
+        let left = node.left.summary();
+        let own = Multiplicity::summarize(&node.key, &node.val);
+        if index < left {
+            tree = &node.left;
+        } else if index < left + own {
+            return Some((node, index - left));
+        } else {
+            index -= left + own;
+            tree = &node.right;
+        }
+    }
+    None
+}
+
+/// A sorted map that keeps every value inserted for a key, in the order they were inserted.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::multi::AvlMultiMap;
+///
+/// let mut map = AvlMultiMap::new();
+/// map.insert("b", 1);
+/// map.insert("a", 2);
+/// map.insert("b", 3);
+///
+/// assert!(map.get(&"b").eq(&[1, 3]));
+/// assert_eq!(map.count(&"b"), 2);
+/// assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&"a", &2), (&"b", &1), (&"b", &3)]);
+///
+/// assert_eq!(map.remove_one(&"b"), Some(1));
+/// assert_eq!(map.len(), 2);
+/// ```
+pub struct AvlMultiMap<K: Ord, V>(AvlTree<K, VecDeque<V>, Multiplicity>);
+
+/// An iterator over the values of an `AvlMultiMap`, sorted by key, with the values for each key
+/// in the order they were inserted.
+pub struct MultiMapIter<'a, K: 'a, V: 'a> {
+    entries: Iter<'a, K, VecDeque<V>>,
+    current: Option<(&'a K, vec_deque::Iter<'a, V>)>
+}
+
+impl<K: Ord, V> AvlMultiMap<K,V> {
+
+    /// Creates a new empty map
+    pub fn new() -> Self {
+        AvlMultiMap(AvlTree::from_root(None))
+    }
+
+    /// The number of values in the map, counting every value for a key
+    pub fn len(&self) -> usize {
+        self.0.summary()
+    }
+
+    /// Checks if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds a value for the key, after any it already has
+    pub fn insert(&mut self, key: K, val: V) {
+        if self.0.contains_key(&key) {
+            modify(&mut self.0, &key, |vals| vals.push_back(val));
+        } else {
+            self.0.insert(key, VecDeque::from(vec![val]));
+        }
+    }
+
+    /// Gives an iterator over the key's values in the order they were inserted, which is
+    /// empty if it has none
+    pub fn get(&self, key: &K) -> vec_deque::Iter<'_, V> {
+        self.0.get(key).map_or_else(Default::default, |vals| vals.iter())
+    }
+
+    /// The number of values the key has
+    pub fn count(&self, key: &K) -> usize {
+        self.get(key).len()
+    }
+
+    /// Checks if the key has any values
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Removes the key's earliest inserted value and gives it back, or None if it has none.
+    /// Runs in O(log n) however many values the key has.
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        match self.count(key) {
+            0 => None,
+            1 => self.0.remove(key).and_then(|mut vals| vals.pop_front()),
+            _ => modify(&mut self.0, key, |vals| vals.pop_front()).flatten()
+        }
+    }
+
+    /// Removes every value for the key, giving them back in the order they were inserted
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        self.0.remove(key).map(Vec::from).unwrap_or_default()
+    }
+
+    /// The number of values with keys less than the given key, whether or not it is present.
+    pub fn rank(&self, key: &K) -> usize {
+        rank(&self.0, key)
+    }
+
+    /// Gives the value at the index when every value is listed in sorted order, as `iter`
+    /// gives them, or None if the index is past the end.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::multi::AvlMultiMap;
+    ///
+    /// let mut map = AvlMultiMap::new();
+    /// for (key, val) in vec![(1, 'a'), (2, 'b'), (1, 'c'), (3, 'd')] {
+    ///     map.insert(key, val);
+    /// }
+    /// assert_eq!(map.select(1), Some((&1, &'c')));
+    /// assert_eq!(map.select(2), Some((&2, &'b')));
+    /// assert_eq!(map.rank(&3), 3);
+    /// ```
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        select(&self.0, index).map(|(node, offset)| (&node.key, &node.val[offset]))
+    }
+
+    /// Gives an iterator over every value for the keys within the given start and end points
+    /// (inclusive), sorted by key. If None is given, then that side is unbounded.
+    pub fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> MultiMapIter<'_, K,V> {
+        MultiMapIter { entries: self.0.range_iter(start, end), current: None }
+    }
+
+    /// Gives an iterator over every value, sorted by key
+    pub fn iter(&self) -> MultiMapIter<'_, K,V> {
+        self.range_iter(None, None)
+    }
+}
+
+impl<K: Ord, V> Default for AvlMultiMap<K,V> {
+    fn default() -> Self {
+        AvlMultiMap::new()
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> Iterator for MultiMapIter<'a, K,V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, ref mut vals)) = self.current {
+                if let Some(val) = vals.next() {
+                    return Some((key, val));
+                }
+            }
+            let (key, vals) = self.entries.next()?;
+            self.current = Some((key, vals.iter()));
+        }
+    }
+}
+
+/// A sorted collection that counts how many times each element was inserted.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::multi::AvlMultiSet;
+///
+/// let mut set = AvlMultiSet::new();
+/// for word in "the cat saw the other cat".split(' ') {
+///     set.insert(word);
+/// }
+///
+/// assert_eq!(set.count(&"the"), 2);
+/// assert_eq!(set.len(), 6);
+/// assert_eq!(set.iter().cloned().collect::<Vec<_>>(), vec!["cat", "cat", "other", "saw", "the", "the"]);
+/// assert_eq!(set.rank(&"saw"), 3);
+/// assert_eq!(set.select(4), Some(&"the"));
+/// ```
+pub struct AvlMultiSet<T: Ord>(AvlTree<T, usize, Multiplicity>);
+
+/// An iterator over the elements of an `AvlMultiSet` in sorted order, giving each element as
+/// many times as it was counted.
+pub struct MultiSetIter<'a, T: 'a> {
+    entries: Iter<'a, T, usize>,
+    current: Option<(&'a T, usize)>
+}
+
+impl<T: Ord> AvlMultiSet<T> {
+
+    /// Creates a new empty set
+    pub fn new() -> Self {
+        AvlMultiSet(AvlTree::from_root(None))
+    }
+
+    /// The number of elements in the set, counting every copy
+    pub fn len(&self) -> usize {
+        self.0.summary()
+    }
+
+    /// Checks if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds one copy of the element, returning how many there are now
+    pub fn insert(&mut self, val: T) -> usize {
+        if self.0.contains_key(&val) {
+            modify(&mut self.0, &val, |count| {
+                *count += 1;
+                *count
+            }).unwrap()
+        } else {
+            self.0.insert(val, 1);
+            1
+        }
+    }
+
+    /// The number of copies of the element
+    pub fn count(&self, val: &T) -> usize {
+        self.0.get(val).cloned().unwrap_or(0)
+    }
+
+    /// Checks if the set has any copies of the element
+    pub fn contains(&self, val: &T) -> bool {
+        self.0.contains_key(val)
+    }
+
+    /// Removes one copy of the element, returning false if there were none
+    pub fn remove_one(&mut self, val: &T) -> bool {
+        match self.count(val) {
+            0 => false,
+            1 => self.0.remove(val).is_some(),
+            _ => modify(&mut self.0, val, |count| *count -= 1).is_some()
+        }
+    }
+
+    /// Removes every copy of the element, returning how many there were
+    pub fn remove_all(&mut self, val: &T) -> usize {
+        self.0.remove(val).unwrap_or(0)
+    }
+
+    /// The number of elements less than the given one, counting every copy, whether or not
+    /// it is present.
+    pub fn rank(&self, val: &T) -> usize {
+        rank(&self.0, val)
+    }
+
+    /// Gives the element at the index when every copy is listed in sorted order, or None if
+    /// the index is past the end.
+    pub fn select(&self, index: usize) -> Option<&T> {
+        select(&self.0, index).map(|(node, _)| &node.key)
+    }
+
+    /// Gives an iterator over every copy of the elements within the given start and end
+    /// points (inclusive), in sorted order. If None is given, then that side is unbounded.
+    pub fn range_iter(&self, start: Option<&T>, end: Option<&T>) -> MultiSetIter<'_, T> {
+        MultiSetIter { entries: self.0.range_iter(start, end), current: None }
+    }
+
+    /// Gives an iterator over every copy of every element, in sorted order
+    pub fn iter(&self) -> MultiSetIter<'_, T> {
+        self.range_iter(None, None)
+    }
+}
+
+impl<T: Ord> Default for AvlMultiSet<T> {
+    fn default() -> Self {
+        AvlMultiSet::new()
+    }
+}
+
+impl<'a, T: 'a + Ord> Iterator for MultiSetIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some((val, ref mut left)) = self.current {
+                if *left > 0 {
+                    *left -= 1;
+                    return Some(val);
+                }
+            }
+            let (val, &count) = self.entries.next()?;
+            self.current = Some((val, count));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use multi::{AvlMultiMap, AvlMultiSet};
+    use ordered_map::conformance::XorShift;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_multimap() {
+        let mut map = AvlMultiMap::new();
+        let mut model: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..5000 {
+            let key = rng.next(100);
+            match rng.next(10) {
+                0 => assert_eq!(map.remove_all(&key), model.remove(&key).unwrap_or_default()),
+                1..=3 => {
+                    let expected = model.get_mut(&key).map(|vals| vals.remove(0));
+                    if model.get(&key).is_some_and(|vals| vals.is_empty()) {
+                        model.remove(&key);
+                    }
+                    assert_eq!(map.remove_one(&key), expected);
+                },
+                _ => {
+                    map.insert(key, step);
+                    model.entry(key).or_default().push(step);
+                }
+            }
+            assert!(map.get(&key).eq(model.get(&key).into_iter().flatten()));
+        }
+        let flat: Vec<(&u64, &u64)> = model.iter().flat_map(|(key, vals)| vals.iter().map(move |val| (key, val))).collect();
+        assert_eq!(map.iter().collect::<Vec<_>>(), flat);
+        assert_eq!(map.len(), flat.len());
+        for (index, &entry) in flat.iter().enumerate() {
+            assert_eq!(map.select(index), Some(entry));
+            assert_eq!(map.rank(entry.0), flat.iter().take_while(|&&(key, _)| key < entry.0).count());
+        }
+        assert_eq!(map.select(flat.len()), None);
+        assert_eq!(map.range_iter(Some(&20), Some(&30)).count(), flat.iter().filter(|&&(&key, _)| (20..=30).contains(&key)).count());
+        assert_eq!(map.0.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_multiset() {
+        let mut set = AvlMultiSet::new();
+        let mut model: BTreeMap<u64, usize> = BTreeMap::new();
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for _ in 0..5000 {
+            let val = rng.next(50);
+            match rng.next(10) {
+                0 => assert_eq!(set.remove_all(&val), model.remove(&val).unwrap_or(0)),
+                1..=3 => {
+                    let present = model.contains_key(&val);
+                    if present {
+                        *model.get_mut(&val).unwrap() -= 1;
+                        if model[&val] == 0 {
+                            model.remove(&val);
+                        }
+                    }
+                    assert_eq!(set.remove_one(&val), present);
+                },
+                _ => {
+                    let count = model.entry(val).or_insert(0);
+                    *count += 1;
+                    assert_eq!(set.insert(val), *count);
+                }
+            }
+            assert_eq!(set.count(&val), model.get(&val).cloned().unwrap_or(0));
+        }
+        let flat: Vec<u64> = model.iter().flat_map(|(&val, &count)| ::std::iter::repeat_n(val, count)).collect();
+        assert_eq!(set.iter().cloned().collect::<Vec<_>>(), flat);
+        assert_eq!(set.len(), flat.len());
+        for (index, val) in flat.iter().enumerate() {
+            assert_eq!(set.select(index), Some(val));
+            assert_eq!(set.rank(val), flat.iter().take_while(|&other| other < val).count());
+        }
+        assert_eq!(set.range_iter(Some(&10), Some(&19)).count(), flat.iter().filter(|&&val| (10..20).contains(&val)).count());
+        assert_eq!(set.0.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,319 @@
+//! An AvlTree that reports every change made to it.
+//!
+//! `ObservedAvlTree` owns its tree and only hands out shared references to it, so every
+//! change has to go through the wrapper's own methods, and each of those tells the observer
+//! what it changed. Something mirroring the tree, like a secondary index, can rely on seeing
+//! every change exactly once, in the order they were made.
+//!
+//! Of the tree's bulk operations, `remove_prefix` is offered, reporting each entry it takes
+//! out. `union` is not: it is fast because it never looks at most of the entries, while an
+//! observer would need to hear about every one of them, so `extend` does the same job here.
+use core::cmp::Ord;
+use core::ops::Deref;
+
+use AvlTree;
+use summary::Summary;
+
+/// A change made to an observed tree.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change<'a, K: 'a, V: 'a> {
+    /// A key that was not in the tree was added
+    Inserted {
+        /// the new key
+        key: &'a K,
+        /// its value
+        value: &'a V
+    },
+    /// A key already in the tree was given a new value
+    Replaced {
+        /// the key
+        key: &'a K,
+        /// the value it had
+        old: &'a V,
+        /// the value it has now
+        new: &'a V
+    },
+    /// A key was taken out of the tree
+    Removed {
+        /// the key
+        key: &'a K,
+        /// the value it had
+        value: &'a V
+    }
+}
+
+/// Receives the changes made to an `ObservedAvlTree`. Any `FnMut(Change<K,V>)` closure is an
+/// observer.
+pub trait ChangeObserver<K, V> {
+    /// Called once for every change, in the order the changes are made
+    fn changed(&mut self, change: Change<'_, K, V>);
+}
+
+impl<K, V, F: FnMut(Change<'_, K, V>)> ChangeObserver<K, V> for F {
+    fn changed(&mut self, change: Change<'_, K, V>) {
+        self(change)
+    }
+}
+
+/// An AvlTree that tells an observer about every insert, replacement and removal.
+///
+/// It dereferences to the tree for reading, so `get`, `iter`, `aggregate` and the rest work
+/// as usual, but it can only be changed through its own methods, which cannot be bypassed.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::observed::{Change, ObservedAvlTree};
+///
+/// let mut log = Vec::new();
+/// {
+///     let mut tree = ObservedAvlTree::new(|change: Change<i32, &str>| log.push(format!("{:?}", change)));
+///     tree.insert(1, "a");
+///     tree.insert(1, "b");
+///     tree.remove(&1);
+///     assert!(tree.is_empty());
+/// }
+/// assert_eq!(log, vec![
+///     "Inserted { key: 1, value: \"a\" }",
+///     "Replaced { key: 1, old: \"a\", new: \"b\" }",
+///     "Removed { key: 1, value: \"b\" }"
+/// ]);
+/// ```
+pub struct ObservedAvlTree<K: Ord, V, O: ChangeObserver<K,V>, S: Summary<K,V> = ()> {
+    tree: AvlTree<K,V,S>,
+    observer: O
+}
+
+impl<K: Ord, V, O: ChangeObserver<K,V>> ObservedAvlTree<K,V,O> {
+
+    /// Creates a new empty tree that reports its changes to the observer
+    pub fn new(observer: O) -> Self {
+        ObservedAvlTree { tree: AvlTree::new(), observer }
+    }
+}
+
+impl<K: Ord, V, O: ChangeObserver<K,V>, S: Summary<K,V>> ObservedAvlTree<K,V,O,S> {
+
+    /// Starts observing an existing tree. The observer is first told about every entry
+    /// already in it, in sorted order, as if each had just been inserted, so that whatever
+    /// it builds starts out matching the tree.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::AvlTree;
+    /// use avltree_map::observed::{Change, ObservedAvlTree};
+    ///
+    /// let mut tree = AvlTree::new();
+    /// tree.insert(2, "b");
+    /// tree.insert(1, "a");
+    ///
+    /// let mut keys = Vec::new();
+    /// ObservedAvlTree::from_tree(tree, |change: Change<i32, &str>| {
+    ///     if let Change::Inserted { key, .. } = change {
+    ///         keys.push(*key);
+    ///     }
+    /// });
+    /// assert_eq!(keys, vec![1, 2]);
+    /// ```
+    pub fn from_tree(tree: AvlTree<K,V,S>, mut observer: O) -> Self {
+        for (key, value) in tree.iter() {
+            observer.changed(Change::Inserted { key, value });
+        }
+        ObservedAvlTree { tree, observer }
+    }
+
+    /// Gives the observer
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Gives the observer, to read or reset whatever it has gathered. It can not reach the
+    /// tree through this.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Inserts a key,value pair into the tree, as `AvlTree::insert` does, and reports it as
+    /// `Inserted` or, if the key was already present, `Replaced`. The observer hears about
+    /// the change once it has been made.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let (old, key, value) = self.tree.insert_entry(key, val);
+        match old {
+            Some(ref old) => self.observer.changed(Change::Replaced { key, old, new: value }),
+            None => self.observer.changed(Change::Inserted { key, value })
+        }
+        old
+    }
+
+    /// Removes a key from the tree, as `AvlTree::remove` does, and reports it as `Removed` if
+    /// it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let removed = self.tree.remove(key);
+        if let Some(ref value) = removed {
+            self.observer.changed(Change::Removed { key, value });
+        }
+        removed
+    }
+
+    /// Keeps only the entries for which f returns true, as `AvlTree::retain` does, and
+    /// reports each entry it drops as `Removed`. Unlike `AvlTree::retain`, f can not change
+    /// the values, since the observer would not hear about it.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::observed::{Change, ObservedAvlTree};
+    ///
+    /// let mut removed = Vec::new();
+    /// {
+    ///     let mut tree = ObservedAvlTree::new(|change: Change<i32, i32>| {
+    ///         if let Change::Removed { key, .. } = change {
+    ///             removed.push(*key);
+    ///         }
+    ///     });
+    ///     tree.extend((0..6).map(|i| (i, i)));
+    ///     tree.retain(|key, _| key % 2 == 0);
+    /// }
+    /// assert_eq!(removed, vec![1, 3, 5]);
+    /// ```
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) {
+        let observer = &mut self.observer;
+        self.tree.retain(|key, value| {
+            let keep = f(key, value);
+            if !keep {
+                observer.changed(Change::Removed { key, value });
+            }
+            keep
+        });
+    }
+
+    /// Removes every entry whose key starts with the given prefix, as `AvlTree::remove_prefix`
+    /// does, and reports each one as `Removed` in sorted order. The removed entries are given
+    /// back as a plain tree, which is no longer observed.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::observed::{Change, ObservedAvlTree};
+    ///
+    /// let mut removed = Vec::new();
+    /// {
+    ///     let mut tree = ObservedAvlTree::new(|change: Change<&str, i32>| {
+    ///         if let Change::Removed { key, .. } = change {
+    ///             removed.push(key.to_string());
+    ///         }
+    ///     });
+    ///     tree.extend(vec![("a/1", 1), ("b/1", 2), ("b/2", 3)]);
+    ///     assert_eq!(tree.remove_prefix("b/").iter().count(), 2);
+    /// }
+    /// assert_eq!(removed, vec!["b/1", "b/2"]);
+    /// ```
+    pub fn remove_prefix<P: AsRef<[u8]> + ?Sized>(&mut self, prefix: &P) -> AvlTree<K,V,S> where K: AsRef<[u8]> {
+        let removed = self.tree.remove_prefix(prefix);
+        for (key, value) in removed.iter() {
+            self.observer.changed(Change::Removed { key, value });
+        }
+        removed
+    }
+
+    /// Inserts every entry from an iterator in turn, reporting each one as `insert` does.
+    pub fn extend<I: IntoIterator<Item = (K,V)>>(&mut self, entries: I) {
+        for (key, val) in entries {
+            self.insert(key, val);
+        }
+    }
+
+    /// Removes every entry, reporting each as `Removed` in sorted order.
+    pub fn clear(&mut self) {
+        let observer = &mut self.observer;
+        self.tree.retain(|key, value| {
+            observer.changed(Change::Removed { key, value });
+            false
+        });
+    }
+
+    /// Stops observing, giving back the tree and the observer.
+    pub fn into_parts(self) -> (AvlTree<K,V,S>, O) {
+        (self.tree, self.observer)
+    }
+}
+
+impl<K: Ord, V, O: ChangeObserver<K,V>, S: Summary<K,V>> Deref for ObservedAvlTree<K,V,O,S> {
+    type Target = AvlTree<K,V,S>;
+
+    fn deref(&self) -> &AvlTree<K,V,S> {
+        &self.tree
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use observed::{Change, ChangeObserver, ObservedAvlTree};
+    use ordered_map::conformance::XorShift;
+    use std::collections::BTreeMap;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    ///rebuilds the tree from its changes alone, checking each is consistent with the last
+    struct Mirror(BTreeMap<i32, i32>);
+
+    impl ChangeObserver<i32, i32> for Mirror {
+        fn changed(&mut self, change: Change<'_, i32, i32>) {
+            match change {
+                Change::Inserted { key, value } => assert_eq!(self.0.insert(*key, *value), None),
+                Change::Replaced { key, old, new } => assert_eq!(self.0.insert(*key, *new), Some(*old)),
+                Change::Removed { key, value } => assert_eq!(self.0.remove(key), Some(*value))
+            }
+        }
+    }
+
+    #[test]
+    fn test_mirror() {
+        let mut tree = ObservedAvlTree::new(Mirror(BTreeMap::new()));
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..5000 {
+            let key = rng.next(300) as i32;
+            match rng.next(100) {
+                0 => tree.retain(|k, _| k % 7 != key % 7),
+                1 => tree.extend((key..key + 20).map(|k| (k, step))),
+                2 if step % 5 == 0 => tree.clear(),
+                3..=40 => { tree.remove(&key); },
+                _ => { tree.insert(key, step); }
+            }
+            assert_eq!(tree.iter().collect::<Vec<_>>(), tree.observer().0.iter().collect::<Vec<_>>(), "step {}", step);
+        }
+        let (avl, mirror) = tree.into_parts();
+        assert_eq!(avl.validate(), Ok(()));
+        assert_eq!(avl.iter().count(), mirror.0.len());
+    }
+
+    #[test]
+    fn test_insert_reported_after() {
+        let mut tree = ObservedAvlTree::new(|change: Change<'_, i32, i32>| {
+            if let Change::Inserted { key, .. } = change {
+                assert!(*key < 50);
+            }
+        });
+        tree.extend((0..50).map(|key| (key, key)));
+        //the insert was made before the observer heard of it, so the tree holds it whole
+        let result = catch_unwind(AssertUnwindSafe(|| tree.insert(75, 75)));
+        assert!(result.is_err());
+        assert_eq!(tree.get(&75), Some(&75));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_retain_panic() {
+        let mut tree = ObservedAvlTree::new(Mirror(BTreeMap::new()));
+        tree.extend((0..100).map(|key| (key, key)));
+        let result = catch_unwind(AssertUnwindSafe(|| tree.retain(|&key, _| {
+            assert!(key < 50);
+            key % 3 != 0
+        })));
+        assert!(result.is_err());
+        //the observer heard about exactly the entries that were dropped before the panic
+        assert_eq!(tree.iter().collect::<Vec<_>>(), tree.observer().0.iter().collect::<Vec<_>>());
+        assert_eq!(tree.iter().count(), 100 - 17);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
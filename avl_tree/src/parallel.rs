@@ -0,0 +1,477 @@
+//! Parallel iteration and bulk operations on AvlTrees, with the `rayon` feature.
+//!
+//! Work is split along subtrees: an iterator hands each thread whole subtrees to walk in
+//! order, building a tree builds its two halves at once, and the set operations split one
+//! tree around the other's root and work on the two sides independently before joining them
+//! back together. Results always come out in sorted order.
+use core::cmp::Ord;
+use alloc::vec::Vec;
+
+use rayon;
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::slice::ParallelSliceMut;
+
+use AvlTree;
+use summary::Summary;
+
+///trees no taller than this are worked on by one thread, since splitting them costs more than
+///it saves
+const SEQUENTIAL_HEIGHT: i32 = 8;
+
+///fewer entries than this are built into a tree by one thread
+const SEQUENTIAL_BUILD: usize = 1 << SEQUENTIAL_HEIGHT;
+
+///runs both closures, in parallel if there is enough work to be worth it
+fn fork<A, B, RA, RB>(parallel: bool, a: A, b: B) -> (RA, RB)
+    where A: FnOnce() -> RA + Send, B: FnOnce() -> RB + Send, RA: Send, RB: Send
+{
+    if parallel {
+        rayon::join(a, b)
+    } else {
+        (a(), b())
+    }
+}
+
+///a run of entries that comes next in sorted order: either a whole subtree or a single entry
+enum Piece<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a> {
+    Tree(&'a AvlTree<K,V,S>),
+    Entry(&'a K, &'a V)
+}
+
+///A parallel iterator over the entries of an AvlTree, in sorted order.
+///Created by `par_iter` and `par_range`.
+pub struct ParIter<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a = ()> {
+    pieces: Vec<Piece<'a,K,V,S>>
+}
+
+impl<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a> ParIter<'a,K,V,S> {
+
+    ///covers the entries of the tree between start and end, inclusive, with whole subtrees
+    ///wherever possible. only the two paths to the ends of the range are visited
+    fn new(tree: &'a AvlTree<K,V,S>, start: Option<&K>, end: Option<&K>) -> Self {
+        let mut iter = ParIter { pieces: Vec::new() };
+        iter.cover(tree, start, end);
+        iter
+    }
+
+    fn cover(&mut self, tree: &'a AvlTree<K,V,S>, start: Option<&K>, end: Option<&K>) {
+        if let Some(ref node) = tree.0 {
+            if start.is_none() && end.is_none() {
+                self.pieces.push(Piece::Tree(tree));
+            } else if start.is_some_and(|start| node.key < *start) {
+                self.cover(&node.right, start, end);
+            } else if end.is_some_and(|end| node.key > *end) {
+                self.cover(&node.left, start, end);
+            } else {
+                //everything left of this node is below the end, and everything right of it is
+                //above the start
+                self.cover(&node.left, start, None);
+                self.pieces.push(Piece::Entry(&node.key, &node.val));
+                self.cover(&node.right, None, end);
+            }
+        }
+    }
+}
+
+impl<'a, K, V, S> ParallelIterator for ParIter<'a,K,V,S>
+    where K: Ord + Sync + 'a, V: Sync + 'a, S: Summary<K,V> + 'a, S::Value: Sync
+{
+    type Item = (&'a K, &'a V);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        bridge_unindexed(Producer { pieces: self.pieces }, consumer)
+    }
+}
+
+impl<'a, K, V, S> IntoParallelIterator for &'a AvlTree<K,V,S>
+    where K: Ord + Sync + 'a, V: Sync + 'a, S: Summary<K,V> + 'a, S::Value: Sync
+{
+    type Iter = ParIter<'a,K,V,S>;
+    type Item = (&'a K, &'a V);
+
+    fn into_par_iter(self) -> ParIter<'a,K,V,S> {
+        self.par_iter()
+    }
+}
+
+///hands out pieces of the tree to rayon, splitting a lone subtree at its root when more
+///threads want work
+struct Producer<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a> {
+    pieces: Vec<Piece<'a,K,V,S>>
+}
+
+impl<'a, K: Ord + 'a, V: 'a, S: Summary<K,V> + 'a> Producer<'a,K,V,S> {
+
+    ///feeds a subtree to the folder in sorted order, stopping early if it is full
+    fn fold_tree<F: Folder<(&'a K, &'a V)>>(tree: &'a AvlTree<K,V,S>, mut folder: F) -> F {
+        if let Some(ref node) = tree.0 {
+            folder = Self::fold_tree(&node.left, folder);
+            if folder.full() {
+                return folder;
+            }
+            folder = folder.consume((&node.key, &node.val));
+            if folder.full() {
+                return folder;
+            }
+            folder = Self::fold_tree(&node.right, folder);
+        }
+        folder
+    }
+}
+
+impl<'a, K, V, S> UnindexedProducer for Producer<'a,K,V,S>
+    where K: Ord + Sync + 'a, V: Sync + 'a, S: Summary<K,V> + 'a, S::Value: Sync
+{
+    type Item = (&'a K, &'a V);
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        if self.pieces.len() == 1 {
+            if let Piece::Tree(&AvlTree(Some(ref node), _)) = self.pieces[0] {
+                if node.left.is_empty() && node.right.is_empty() {
+                    return (self, None);
+                }
+                let right = Producer {
+                    pieces: vec![Piece::Entry(&node.key, &node.val), Piece::Tree(&node.right)]
+                };
+                return (Producer { pieces: vec![Piece::Tree(&node.left)] }, Some(right));
+            }
+        }
+        if self.pieces.len() < 2 {
+            return (self, None);
+        }
+        let mid = self.pieces.len() / 2;
+        let right = Producer { pieces: self.pieces.split_off(mid) };
+        (self, Some(right))
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, mut folder: F) -> F {
+        for piece in self.pieces {
+            folder = match piece {
+                Piece::Tree(tree) => Self::fold_tree(tree, folder),
+                Piece::Entry(key, val) => folder.consume((key, val))
+            };
+            if folder.full() {
+                break;
+            }
+        }
+        folder
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Gives a parallel iterator over the key-value pairs in the tree. Each thread walks
+    /// whole subtrees, and the pairs are still ordered by key, so `collect` gives them in
+    /// sorted order.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  extern crate avltree_map;
+    ///  extern crate rayon;
+    ///
+    ///  use avltree_map::AvlTree;
+    ///  use rayon::prelude::*;
+    ///
+    ///  # fn main() {
+    ///  let mut tree = AvlTree::new();
+    ///  for i in 0..1000 {
+    ///      tree.insert(i, i * 2);
+    ///  }
+    ///
+    ///  let sum: i64 = tree.par_iter().map(|(_, v)| *v as i64).sum();
+    ///  assert_eq!(sum, 999_000);
+    ///
+    ///  let keys: Vec<_> = tree.par_iter().map(|(k, _)| *k).collect();
+    ///  assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    ///  # }
+    ///  ```
+    pub fn par_iter(&self) -> ParIter<'_,K,V,S> {
+        ParIter::new(self, None, None)
+    }
+
+    /// Gives a parallel iterator over the key-value pairs in the tree that fall within the
+    /// given start and end points (inclusive), in sorted order. The range is covered by
+    /// O(log n) subtrees, which are then split between threads.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  extern crate avltree_map;
+    ///  extern crate rayon;
+    ///
+    ///  use avltree_map::AvlTree;
+    ///  use rayon::prelude::*;
+    ///
+    ///  # fn main() {
+    ///  let mut tree = AvlTree::new();
+    ///  for i in 0..1000 {
+    ///      tree.insert(i, ());
+    ///  }
+    ///
+    ///  let keys: Vec<_> = tree.par_range(Some(&10), Some(&14)).map(|(k, _)| *k).collect();
+    ///  assert_eq!(keys, vec![10, 11, 12, 13, 14]);
+    ///  assert_eq!(tree.par_range(Some(&990), None).count(), 10);
+    ///  # }
+    ///  ```
+    pub fn par_range(&self, start: Option<&K>, end: Option<&K>) -> ParIter<'_,K,V,S> {
+        ParIter::new(self, start, end)
+    }
+}
+
+impl<K, V, S> AvlTree<K,V,S>
+    where K: Ord + Send, V: Send, S: Summary<K,V>, S::Value: Send
+{
+
+    /// Builds a balanced tree from key-value pairs, building the two halves of every subtree
+    /// in parallel.
+    ///
+    /// This is O(n) work when the pairs are already sorted by key. Pairs that are not are
+    /// sorted first, and if a key appears more than once the last value for it is kept, just
+    /// as if the pairs had been inserted one at a time.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  extern crate avltree_map;
+    ///  extern crate rayon;
+    ///
+    ///  use avltree_map::AvlTree;
+    ///  use rayon::prelude::*;
+    ///
+    ///  # fn main() {
+    ///  let tree: AvlTree<u32, u32> = AvlTree::par_from_sorted_iter((0..10_000u32).into_par_iter().map(|i| (i, i)));
+    ///  assert_eq!(tree.get(&5000), Some(&5000));
+    ///  assert!(tree.validate().is_ok());
+    ///
+    ///  let unsorted: AvlTree<i32, &str> = AvlTree::par_from_sorted_iter(vec![(2, "b"), (1, "a"), (2, "c")]);
+    ///  assert_eq!(unsorted.iter().collect::<Vec<_>>(), vec![(&1, &"a"), (&2, &"c")]);
+    ///  # }
+    ///  ```
+    pub fn par_from_sorted_iter<I: IntoParallelIterator<Item = (K,V)>>(entries: I) -> Self {
+        let mut entries: Vec<(K,V)> = entries.into_par_iter().collect();
+        if !entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            //the sort is stable, so equal keys stay in the order they were given
+            entries.par_sort_by(|a, b| a.0.cmp(&b.0));
+            entries.dedup_by(|later, earlier| {
+                let same = later.0 == earlier.0;
+                if same {
+                    ::core::mem::swap(later, earlier);
+                }
+                same
+            });
+        }
+        let mut slots: Vec<Option<(K,V)>> = entries.into_iter().map(Some).collect();
+        Self::build(&mut slots)
+    }
+
+    ///builds a balanced tree out of sorted entries, the same shape as `from_sorted`
+    fn build(slots: &mut [Option<(K,V)>]) -> Self {
+        let n = slots.len();
+        if n < SEQUENTIAL_BUILD {
+            return Self::from_sorted(&mut slots.iter_mut().map(|slot| slot.take().unwrap()), n);
+        }
+        let (left, rest) = slots.split_at_mut(n / 2);
+        let (middle, right) = rest.split_at_mut(1);
+        let (key, val) = middle[0].take().unwrap();
+        let (left, right) = rayon::join(|| Self::build(left), || Self::build(right));
+        Self::node(left, key, val, right)
+    }
+
+    /// Gives a tree with every entry from both trees. If a key is in both, the value from
+    /// `other` is kept. The two sides of `other`'s root are merged in parallel, recursively,
+    /// which takes O(m log(n/m + 1)) work for trees of sizes m <= n.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut a = AvlTree::new();
+    ///  a.insert(1, "a");
+    ///  a.insert(2, "b");
+    ///  let mut b = AvlTree::new();
+    ///  b.insert(2, "B");
+    ///  b.insert(3, "C");
+    ///
+    ///  let union = a.union(b);
+    ///  assert_eq!(union.iter().collect::<Vec<_>>(), vec![(&1, &"a"), (&2, &"B"), (&3, &"C")]);
+    ///  ```
+    pub fn union(self, other: Self) -> Self {
+        if self.is_empty() {
+            return other;
+        }
+        let parallel = self.height() > SEQUENTIAL_HEIGHT && other.height() > SEQUENTIAL_HEIGHT;
+        match other.expose() {
+            None => self,
+            Some((other_left, key, val, other_right)) => {
+                let (left, _, right) = self.split(&key);
+                let (left, right) = fork(parallel, || left.union(other_left), || right.union(other_right));
+                Self::join(left, key, val, right)
+            }
+        }
+    }
+
+    /// Gives a tree with the entries whose keys are in both trees, keeping the values from
+    /// `self`. The two sides of `other`'s root are intersected in parallel, recursively.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut a = AvlTree::new();
+    ///  a.insert(1, "a");
+    ///  a.insert(2, "b");
+    ///  let mut b = AvlTree::new();
+    ///  b.insert(2, "B");
+    ///  b.insert(3, "C");
+    ///
+    ///  let intersection = a.intersection(b);
+    ///  assert_eq!(intersection.iter().collect::<Vec<_>>(), vec![(&2, &"b")]);
+    ///  ```
+    pub fn intersection(self, other: Self) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        let parallel = self.height() > SEQUENTIAL_HEIGHT && other.height() > SEQUENTIAL_HEIGHT;
+        match other.expose() {
+            None => AvlTree::from_root(None),
+            Some((other_left, key, _, other_right)) => {
+                let (left, found, right) = self.split(&key);
+                let (left, right) = fork(parallel, || left.intersection(other_left), || right.intersection(other_right));
+                match found {
+                    Some((key, val)) => Self::join(left, key, val, right),
+                    None => Self::join2(left, right)
+                }
+            }
+        }
+    }
+
+    /// Gives a tree with the entries of `self` whose keys are not in `other`. The two sides
+    /// of `other`'s root are subtracted in parallel, recursively.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut a = AvlTree::new();
+    ///  a.insert(1, "a");
+    ///  a.insert(2, "b");
+    ///  let mut b = AvlTree::new();
+    ///  b.insert(2, "B");
+    ///  b.insert(3, "C");
+    ///
+    ///  let difference = a.difference(b);
+    ///  assert_eq!(difference.iter().collect::<Vec<_>>(), vec![(&1, &"a")]);
+    ///  ```
+    pub fn difference(self, other: Self) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        let parallel = self.height() > SEQUENTIAL_HEIGHT && other.height() > SEQUENTIAL_HEIGHT;
+        match other.expose() {
+            None => self,
+            Some((other_left, key, _, other_right)) => {
+                let (left, _, right) = self.split(&key);
+                let (left, right) = fork(parallel, || left.difference(other_left), || right.difference(other_right));
+                Self::join2(left, right)
+            }
+        }
+    }
+}
+
+impl<K, V, S> FromParallelIterator<(K,V)> for AvlTree<K,V,S>
+    where K: Ord + Send, V: Send, S: Summary<K,V>, S::Value: Send
+{
+    /// Builds the tree with `par_from_sorted_iter`.
+    fn from_par_iter<I: IntoParallelIterator<Item = (K,V)>>(entries: I) -> Self {
+        Self::par_from_sorted_iter(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rayon::prelude::*;
+    use ordered_map::conformance::XorShift;
+    use std::collections::BTreeMap;
+    use AvlTree;
+    use summary::Sum;
+
+    ///a tree and a matching BTreeMap with pseudo-random keys below the bound
+    fn random(seed: u64, count: usize, bound: u64) -> (AvlTree<u64, u64, Sum>, BTreeMap<u64, u64>) {
+        let mut rng = XorShift::new(seed);
+        let mut tree = AvlTree::with_summary();
+        let mut model = BTreeMap::new();
+        for i in 0..count as u64 {
+            let key = rng.next(bound);
+            tree.insert(key, i);
+            model.insert(key, i);
+        }
+        (tree, model)
+    }
+
+    #[test]
+    fn test_par_iter() {
+        let (tree, model) = random(1, 5000, 100_000);
+        let found: Vec<_> = tree.par_iter().collect();
+        assert_eq!(found, model.iter().collect::<Vec<_>>());
+        assert_eq!((&tree).into_par_iter().count(), model.len());
+
+        for &(start, end) in &[(0, 100_000), (500, 600), (25_000, 75_000), (600, 500), (99_999, 200_000)] {
+            let found: Vec<_> = tree.par_range(Some(&start), Some(&end)).collect();
+            let expected: Vec<_> = if start <= end { model.range(start..=end).collect() } else { Vec::new() };
+            assert_eq!(found, expected);
+        }
+        assert_eq!(tree.par_range(None, Some(&50_000)).count(), model.range(..=50_000).count());
+        assert_eq!(tree.par_range(Some(&50_000), None).count(), model.range(50_000..).count());
+        assert_eq!(AvlTree::<i32, i32>::new().par_iter().count(), 0);
+
+        //stopping early still gives the first match in order
+        assert_eq!(tree.par_iter().find_first(|(k, _)| **k > 50_000), model.range(50_001..).next());
+    }
+
+    #[test]
+    fn test_par_from_sorted_iter() {
+        let tree: AvlTree<u32, u32, Sum> = (0..100_000u32).into_par_iter().map(|i| (i, 1)).collect();
+        assert!(tree.validate().is_ok());
+        assert_eq!(tree.aggregate(None, None), 100_000);
+        assert_eq!(tree.iter().map(|(k, _)| *k).collect::<Vec<_>>(), (0..100_000).collect::<Vec<_>>());
+
+        let reversed: AvlTree<u32, u32> = AvlTree::par_from_sorted_iter((0..5000u32).rev().map(|i| (i % 1000, i)).collect::<Vec<_>>());
+        assert!(reversed.validate().is_ok());
+        assert_eq!(reversed.iter().count(), 1000);
+        assert_eq!(reversed.get(&7), Some(&7));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        for &(left_count, right_count) in &[(0, 100), (100, 0), (5000, 5000), (20_000, 50), (50, 20_000)] {
+            let (a, a_model) = random(left_count as u64 + 3, left_count, 40_000);
+            let (b, b_model) = random(right_count as u64 + 5, right_count, 40_000);
+
+            let mut union_model = a_model.clone();
+            union_model.extend(b_model.iter().map(|(k, v)| (*k, *v)));
+            let intersection_model: Vec<_> = a_model.iter().filter(|(k, _)| b_model.contains_key(k)).collect();
+            let difference_model: Vec<_> = a_model.iter().filter(|(k, _)| !b_model.contains_key(k)).collect();
+
+            let copy = |tree: &AvlTree<u64, u64, Sum>| tree.par_iter().map(|(k, v)| (*k, *v)).collect::<AvlTree<u64, u64, Sum>>();
+
+            let union = copy(&a).union(copy(&b));
+            assert_eq!(union.validate(), Ok(()));
+            assert_eq!(union.iter().collect::<Vec<_>>(), union_model.iter().collect::<Vec<_>>());
+            assert_eq!(union.aggregate(None, None), union_model.values().sum::<u64>());
+
+            let intersection = copy(&a).intersection(copy(&b));
+            assert_eq!(intersection.validate(), Ok(()));
+            assert_eq!(intersection.iter().collect::<Vec<_>>(), intersection_model);
+
+            let difference = a.difference(b);
+            assert_eq!(difference.validate(), Ok(()));
+            assert_eq!(difference.iter().collect::<Vec<_>>(), difference_model);
+            assert_eq!(difference.aggregate(None, None), difference_model.iter().map(|(_, v)| **v).sum::<u64>());
+        }
+    }
+}
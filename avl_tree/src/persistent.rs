@@ -0,0 +1,465 @@
+//! A persistent (immutable) version of the AvlTree.
+//!
+//! Every update returns a new version of the tree that shares all of the subtrees it did not
+//! touch with the version it was made from, so only the O(log n) nodes on the path to the
+//! changed key are copied. Nodes are reference counted with `Arc`, which makes cloning a tree
+//! O(1) and lets old versions be handed to readers on other threads.
+use core::cmp::{Ord, Ordering, max};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+struct PersistentNode<K: Ord, V> {
+    key: K,
+    val: V,
+    height: i32,
+    left: PersistentAvlTree<K,V>,
+    right: PersistentAvlTree<K,V>
+}
+
+/// A persistent map based on a binary tree that self balances using the AVL algorithm.
+/// Updating the tree does not change it, but instead gives back a new version of the tree.
+pub struct PersistentAvlTree<K: Ord, V> (Option<Arc<PersistentNode<K,V>>>);
+
+/// A struct used to iterate over values of the PersistentAvlTree
+pub struct Iter<'a, K: 'a, V: 'a> {
+    queue: VecDeque<(&'a K,&'a V)>,
+    forwards: bool
+}
+
+impl<'a, K: 'a + Ord, V: 'a> Iter<'a,K, V> {
+
+    ///creates an iterator containing all elements between start and end, inclusive,
+    ///Unbounded if the None
+    fn new(tree: &'a PersistentAvlTree<K,V>, start: Option<&K>, end: Option<&K>, forwards: bool) -> Self {
+        let mut iter = Iter {
+            queue: VecDeque::new(),
+            forwards
+        };
+        iter.init(tree, start, end);
+        iter
+    }
+
+    fn init(&mut self, tree: &'a PersistentAvlTree<K,V>, start: Option<&K>, end: Option<&K>) {
+        if let Some(ref node) = tree.0 {
+            self.init(&node.left, start, end);
+            if (start.is_none() || *start.unwrap() <= node.key) && (end.is_none() || *end.unwrap() >= node.key) {
+                self.queue.push_back((&node.key,&node.val));
+            };
+            self.init(&node.right, start, end);
+        }
+    }
+}
+
+impl<'a, K: 'a + Ord , V: 'a> Iterator for Iter<'a,K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.forwards {
+            self.queue.pop_front()
+        } else {
+            self.queue.pop_back()
+        }
+    }
+}
+
+impl<K: Ord, V> PersistentAvlTree<K,V> {
+
+    /// Creates a new empty PersistentAvlTree
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::persistent::PersistentAvlTree;
+    ///
+    /// let tree = PersistentAvlTree::new();
+    ///
+    /// let tree = tree.insert(1,"a");
+    /// assert_eq!(tree.get(&1), Some(&"a"));
+    /// ```
+    pub fn new() -> Self {
+        PersistentAvlTree(None)
+    }
+
+    /// Returns the height of the tree.
+    /// The height is defined as The number of Nodes
+    /// on the longest path from the root to any leaf.
+    ///
+    /// #Examples
+    /// ```
+    /// use avltree_map::persistent::PersistentAvlTree;
+    ///
+    /// let tree = PersistentAvlTree::new();
+    /// assert_eq!(tree.height(),0);
+    ///
+    /// let tree = tree.insert(1,"a").insert(2,"b").insert(3,"c");
+    /// assert_eq!(tree.height(),2);
+    /// ```
+    pub fn height(&self) -> i32 {
+        match self.0 {
+            None => 0,
+            Some(ref node) => node.height
+        }
+    }
+
+    /// checks if the tree is empty.
+    ///
+    ///  #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = PersistentAvlTree::new();
+    ///  assert!(tree.is_empty());
+    ///
+    ///  let tree = tree.insert("hello","world");
+    ///  assert!(!tree.is_empty());
+    ///  ```
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Takes a reference to something of type Key and
+    /// returns None if the key is not present, or a reference to the
+    /// value if the key is present
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = PersistentAvlTree::new().insert(37, "b").insert(1,"a");
+    ///
+    ///  assert_eq!(tree.get(&1), Some(&"a"));
+    ///  assert_eq!(tree.get(&2), None);
+    ///  ```
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.0 {
+            None => None,
+            Some(ref node) => {
+                match key.cmp(&node.key) {
+                    Ordering::Equal => Some(&node.val),
+                    Ordering::Less => node.left.get(key),
+                    Ordering::Greater => node.right.get(key)
+                }
+            }
+        }
+    }
+
+    /// Takes a referenece to something of type Key and
+    /// checks if the key is present.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = PersistentAvlTree::new().insert(37, "b").insert(1,"a");
+    ///
+    ///  assert!(tree.contains_key(&1));
+    ///  assert!(!tree.contains_key(&2));
+    ///  ```
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree, sorted by key.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = PersistentAvlTree::new().insert(37, "b").insert(1,"a");
+    ///
+    ///  assert_eq!(tree.iter().next().unwrap(), (&1, &"a"));
+    ///  ```
+    pub fn iter(&self) -> Iter<'_, K,V> {
+        Iter::new(self, None, None, true)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree, sorted by key, in reverse order.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = PersistentAvlTree::new().insert(37, "b").insert(1,"a");
+    ///
+    ///  assert_eq!(tree.reverse_iter().next().unwrap(), (&37, &"b"));
+    ///  ```
+    pub fn reverse_iter(&self) -> Iter<'_, K,V> {
+        Iter::new(self, None, None, false)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree that fall within the given start and
+    /// end points (inclusive) in sorted order.  If None is given, then that side is unbounded.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = (1..6).fold(PersistentAvlTree::new(), |tree, i| tree.insert(i, "a"));
+    ///
+    ///  assert_eq!(tree.range_iter(Some(&2), None).next().unwrap(), (&2, &"a"));
+    ///  ```
+    pub fn range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+        Iter::new(self, start, end, true)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree that fall within the given start and
+    /// end points (inclusive) in reverse sorted order.  If None is given, then that side is unbounded.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let tree = (1..6).fold(PersistentAvlTree::new(), |tree, i| tree.insert(i, "a"));
+    ///
+    ///  assert_eq!(tree.reverse_range_iter(None, Some(&3)).next().unwrap(), (&3, &"a"));
+    ///  ```
+    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+        Iter::new(self, start, end, false)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> PersistentAvlTree<K,V> {
+
+    ///builds a node out of its parts, computing its height from its subtrees
+    fn node(key: K, val: V, left: Self, right: Self) -> Self {
+        PersistentAvlTree(Some(Arc::new(PersistentNode {
+            height: max(left.height(), right.height()) + 1,
+            key,
+            val,
+            left,
+            right
+        })))
+    }
+
+    ///builds a node out of its parts like `node`, but rotates if the subtrees heights differ by
+    ///two. The subtrees themselves must already be balanced.
+    fn balanced(key: K, val: V, left: Self, right: Self) -> Self {
+        let balance = right.height() - left.height();
+
+        //too left leaning
+        if balance == -2 {
+            let l = left.0.as_ref().unwrap();
+            //if left subtree is left leaning or balanced, then right rotate, else
+            //left-right rotate
+            if l.right.height() <= l.left.height() {
+                Self::node(l.key.clone(), l.val.clone(),
+                           l.left.clone(),
+                           Self::node(key, val, l.right.clone(), right))
+            } else {
+                let lr = l.right.0.as_ref().unwrap();
+                Self::node(lr.key.clone(), lr.val.clone(),
+                           Self::node(l.key.clone(), l.val.clone(), l.left.clone(), lr.left.clone()),
+                           Self::node(key, val, lr.right.clone(), right))
+            }
+        }
+        //too right leaning
+        else if balance == 2 {
+            let r = right.0.as_ref().unwrap();
+            if r.right.height() >= r.left.height() {
+                Self::node(r.key.clone(), r.val.clone(),
+                           Self::node(key, val, left, r.left.clone()),
+                           r.right.clone())
+            } else {
+                let rl = r.left.0.as_ref().unwrap();
+                Self::node(rl.key.clone(), rl.val.clone(),
+                           Self::node(key, val, left, rl.left.clone()),
+                           Self::node(r.key.clone(), r.val.clone(), rl.right.clone(), r.right.clone()))
+            }
+        }
+        //else balanced, nothing to do
+        else {
+            Self::node(key, val, left, right)
+        }
+    }
+
+    /// Inserts a key,value pair and returns the new version of the tree. If the key was already
+    /// present, its value is replaced in the new version. The tree this is called on is left
+    /// unchanged, and shares every subtree off of the path to the key with the new version.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::persistent::PersistentAvlTree;
+    ///
+    /// let v1 = PersistentAvlTree::new().insert(37, "a");
+    /// let v2 = v1.insert(37, "b");
+    ///
+    /// assert_eq!(v1.get(&37), Some(&"a"));
+    /// assert_eq!(v2.get(&37), Some(&"b"));
+    /// ```
+    pub fn insert(&self, key: K, val: V) -> Self {
+        match self.0 {
+            //if there is no data here, the new version is a single leaf
+            None => Self::node(key, val, PersistentAvlTree::new(), PersistentAvlTree::new()),
+            Some(ref node) => {
+                match key.cmp(&node.key) {
+                    //if key exists, copy the node with the new value
+                    Ordering::Equal => PersistentAvlTree(Some(Arc::new(PersistentNode {
+                        key,
+                        val,
+                        height: node.height,
+                        left: node.left.clone(),
+                        right: node.right.clone()
+                    }))),
+                    Ordering::Less => Self::balanced(node.key.clone(), node.val.clone(),
+                                                     node.left.insert(key, val), node.right.clone()),
+                    Ordering::Greater => Self::balanced(node.key.clone(), node.val.clone(),
+                                                        node.left.clone(), node.right.insert(key, val))
+                }
+            }
+        }
+    }
+
+    /// Takes a referenece to something of type Key and returns a new version of the tree
+    /// without that key. If the key was not present, the new version is a cheap clone of
+    /// this one.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::persistent::PersistentAvlTree;
+    ///
+    ///  let v1 = PersistentAvlTree::new().insert(37, "b").insert(1,"a");
+    ///  let v2 = v1.remove(&1);
+    ///
+    ///  assert!(v1.contains_key(&1));
+    ///  assert!(!v2.contains_key(&1));
+    ///  assert_eq!(v2.get(&37), Some(&"b"));
+    ///  ```
+    pub fn remove(&self, key: &K) -> Self {
+        if self.contains_key(key) {
+            self.remove_present(key)
+        } else {
+            self.clone()
+        }
+    }
+
+    ///removes a key that is known to be in the tree
+    fn remove_present(&self, key: &K) -> Self {
+        let node = self.0.as_ref().unwrap();
+        match key.cmp(&node.key) {
+            Ordering::Less => Self::balanced(node.key.clone(), node.val.clone(),
+                                             node.left.remove_present(key), node.right.clone()),
+            Ordering::Greater => Self::balanced(node.key.clone(), node.val.clone(),
+                                                node.left.clone(), node.right.remove_present(key)),
+            Ordering::Equal => {
+                match (node.left.is_empty(), node.right.is_empty()) {
+                    //zero or one subtree, replacement is the other subtree
+                    (true, _) => node.right.clone(),
+                    (_, true) => node.left.clone(),
+                    //two subtrees, the smallest key on the right replaces this one
+                    (false, false) => {
+                        let (min_key, min_val, right) = node.right.without_min();
+                        Self::balanced(min_key, min_val, node.left.clone(), right)
+                    }
+                }
+            }
+        }
+    }
+
+    ///gives back the smallest entry of a nonempty tree and the tree without it
+    fn without_min(&self) -> (K, V, Self) {
+        let node = self.0.as_ref().unwrap();
+        if node.left.is_empty() {
+            (node.key.clone(), node.val.clone(), node.right.clone())
+        } else {
+            let (key, val, left) = node.left.without_min();
+            (key, val, Self::balanced(node.key.clone(), node.val.clone(), left, node.right.clone()))
+        }
+    }
+}
+
+impl<K: Ord, V> Clone for PersistentAvlTree<K,V> {
+    /// Cloning only copies a reference to the root, so it is O(1).
+    fn clone(&self) -> Self {
+        PersistentAvlTree(self.0.clone())
+    }
+}
+
+impl<K: Ord, V> Default for PersistentAvlTree<K,V> {
+    fn default() -> Self {
+        PersistentAvlTree::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use persistent::PersistentAvlTree;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_insert() {
+        let mut tree = PersistentAvlTree::new();
+        for num in 1..100 {
+            tree = tree.insert(num, num);
+        }
+        //ceil log2(100) == 7, so height must be <= 7
+        assert!(tree.height() <= 7);
+
+        let old = tree.clone();
+        tree = tree.insert(5, 30);
+        assert_eq!(tree.get(&5), Some(&30));
+        assert_eq!(old.get(&5), Some(&5));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = PersistentAvlTree::new();
+        for num in 1..1000 {
+            tree = tree.insert(num, num);
+        }
+        let full = tree.clone();
+
+        for num in 100..900 {
+            tree = tree.remove(&num);
+        }
+        assert!(tree.height() <= 8);
+        assert!(!tree.contains_key(&400));
+        assert_eq!(tree.iter().count(), 199);
+
+        //the old version still has every key
+        for num in 1..1000 {
+            assert_eq!(full.get(&num), Some(&num));
+        }
+    }
+
+    #[test]
+    fn test_sharing() {
+        let mut tree = PersistentAvlTree::new();
+        for num in 0..1000 {
+            tree = tree.insert(num, num);
+        }
+        let updated = tree.insert(0, 1);
+
+        //only the path down to the leftmost leaf was copied, so the right subtree is shared
+        let old_root = tree.0.as_ref().unwrap();
+        let new_root = updated.0.as_ref().unwrap();
+        assert!(!Arc::ptr_eq(old_root, new_root));
+        assert!(Arc::ptr_eq(old_root.right.0.as_ref().unwrap(), new_root.right.0.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut tree = PersistentAvlTree::new();
+        for num in 0..100 {
+            tree = tree.insert(num, num);
+        }
+        let snapshot = tree.clone();
+        let reader = thread::spawn(move || {
+            snapshot.iter().map(|(_, v)| *v).sum::<i32>()
+        });
+        for num in 0..100 {
+            tree = tree.remove(&num);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(reader.join().unwrap(), 4950);
+    }
+}
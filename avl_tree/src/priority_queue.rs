@@ -0,0 +1,269 @@
+//! A double-ended priority queue whose entries can be reprioritized or removed after they are
+//! pushed.
+//!
+//! The entries are kept in an AvlTree ordered by `(priority, id)`, where the id is a number
+//! given to each entry when it is pushed, so that entries with equal priorities stay distinct
+//! and come out in the order they were pushed. A second AvlTree maps each id to its entry's
+//! current priority, which is all that is needed to find the entry again from its handle.
+use core::cmp::Ord;
+
+use AvlTree;
+
+/// Refers to an entry pushed onto an `AvlPriorityQueue`, for as long as it stays queued.
+/// Handles are never reused, so one for an entry that has been popped or removed simply finds
+/// nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(u64);
+
+/// A priority queue that can pop from either end and change or remove any queued entry, each
+/// in O(log n).
+///
+/// Entries with equal priorities are ordered by when they were pushed, so `pop_min` gives the
+/// earliest pushed of them and `pop_max` the latest. Changing an entry's priority keeps its
+/// place among equals.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::priority_queue::AvlPriorityQueue;
+///
+/// let mut queue = AvlPriorityQueue::new();
+/// queue.push(5, "write report");
+/// let email = queue.push(3, "answer email");
+/// queue.push(8, "plan trip");
+///
+/// queue.change_priority(email, 9);
+/// assert_eq!(queue.pop_max(), Some((9, "answer email")));
+/// assert_eq!(queue.pop_min(), Some((5, "write report")));
+/// assert_eq!(queue.len(), 1);
+/// ```
+pub struct AvlPriorityQueue<P: Ord + Clone, T> {
+    entries: AvlTree<(P, u64), T>,
+    ///the current priority of every queued entry, by id
+    priorities: AvlTree<u64, P>,
+    next_id: u64,
+    len: usize
+}
+
+impl<P: Ord + Clone, T> AvlPriorityQueue<P,T> {
+
+    /// Creates a new empty queue
+    pub fn new() -> Self {
+        AvlPriorityQueue { entries: AvlTree::new(), priorities: AvlTree::new(), next_id: 0, len: 0 }
+    }
+
+    /// The number of entries in the queue
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds an entry with the given priority, giving back a handle to it
+    pub fn push(&mut self, priority: P, val: T) -> Handle {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.priorities.insert(id, priority.clone());
+        self.entries.insert((priority, id), val);
+        self.len += 1;
+        Handle(id)
+    }
+
+    /// Gives the entry with the lowest priority, without removing it
+    pub fn peek_min(&self) -> Option<(&P, &T)> {
+        self.entries.first().map(|node| (&node.key.0, &node.val))
+    }
+
+    /// Gives the entry with the highest priority, without removing it
+    pub fn peek_max(&self) -> Option<(&P, &T)> {
+        self.entries.last().map(|node| (&node.key.0, &node.val))
+    }
+
+    /// Removes and gives back the entry with the lowest priority
+    pub fn pop_min(&mut self) -> Option<(P, T)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let node = *self.entries.take_min();
+        Some(self.forget(node.key, node.val))
+    }
+
+    /// Removes and gives back the entry with the highest priority
+    pub fn pop_max(&mut self) -> Option<(P, T)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let node = *self.entries.take_max();
+        Some(self.forget(node.key, node.val))
+    }
+
+    ///drops the id of an entry that has been taken out of entries
+    fn forget(&mut self, (priority, id): (P, u64), val: T) -> (P, T) {
+        self.priorities.remove(&id);
+        self.len -= 1;
+        (priority, val)
+    }
+
+    /// Checks if the handle's entry is still queued
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.priorities.contains_key(&handle.0)
+    }
+
+    /// Gives the priority of the handle's entry, or None if it is no longer queued
+    pub fn priority(&self, handle: Handle) -> Option<&P> {
+        self.priorities.get(&handle.0)
+    }
+
+    /// Gives the value of the handle's entry, or None if it is no longer queued
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        let priority = self.priorities.get(&handle.0)?.clone();
+        self.entries.get(&(priority, handle.0))
+    }
+
+    /// Gives the handle's entry a new priority, returning the old one, or None if the entry
+    /// is no longer queued.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::priority_queue::AvlPriorityQueue;
+    ///
+    /// let mut queue = AvlPriorityQueue::new();
+    /// let a = queue.push(10, 'a');
+    /// queue.push(5, 'b');
+    ///
+    /// assert_eq!(queue.change_priority(a, 1), Some(10));
+    /// assert_eq!(queue.peek_min(), Some((&1, &'a')));
+    ///
+    /// queue.pop_min();
+    /// assert_eq!(queue.change_priority(a, 2), None);
+    /// ```
+    pub fn change_priority(&mut self, handle: Handle, priority: P) -> Option<P> {
+        let old = self.priorities.remove(&handle.0)?;
+        let val = self.entries.remove(&(old.clone(), handle.0)).expect("queued entry missing");
+        self.priorities.insert(handle.0, priority.clone());
+        self.entries.insert((priority, handle.0), val);
+        Some(old)
+    }
+
+    /// Removes the handle's entry, giving back its priority and value, or None if it is no
+    /// longer queued.
+    pub fn remove(&mut self, handle: Handle) -> Option<(P, T)> {
+        let priority = self.priorities.remove(&handle.0)?;
+        let val = self.entries.remove(&(priority.clone(), handle.0)).expect("queued entry missing");
+        self.len -= 1;
+        Some((priority, val))
+    }
+}
+
+impl<P: Ord + Clone, T> Default for AvlPriorityQueue<P,T> {
+    fn default() -> Self {
+        AvlPriorityQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use priority_queue::{AvlPriorityQueue, Handle};
+    use ordered_map::conformance::XorShift;
+
+    #[test]
+    fn test_differential() {
+        let mut queue = AvlPriorityQueue::new();
+        //every entry pushed, in push order, with None once it has left the queue
+        let mut model: Vec<(Handle, Option<(u64, u64)>)> = Vec::new();
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..5000 {
+            let priority = rng.next(50);
+            let pick = rng.next(model.len() as u64 + 1) as usize;
+            //the queued entry that should come out at each end: lowest priority pushed first,
+            //or highest priority pushed last
+            let min = model.iter().enumerate()
+                .filter_map(|(i, &(_, entry))| entry.map(|(p, _)| (p, i)))
+                .min();
+            let max = model.iter().enumerate()
+                .filter_map(|(i, &(_, entry))| entry.map(|(p, _)| (p, i)))
+                .max();
+            match rng.next(10) {
+                0 | 1 => {
+                    let expected = min.map(|(_, i)| model[i].1.take().unwrap());
+                    assert_eq!(queue.pop_min(), expected);
+                },
+                2 => {
+                    let expected = max.map(|(_, i)| model[i].1.take().unwrap());
+                    assert_eq!(queue.pop_max(), expected);
+                },
+                3 | 4 if pick < model.len() => {
+                    let (handle, ref mut entry) = model[pick];
+                    let old = entry.as_mut().map(|entry| ::std::mem::replace(&mut entry.0, priority));
+                    assert_eq!(queue.change_priority(handle, priority), old);
+                },
+                5 if pick < model.len() => {
+                    let (handle, ref mut entry) = model[pick];
+                    assert_eq!(queue.remove(handle), entry.take());
+                },
+                _ => {
+                    let handle = queue.push(priority, step);
+                    model.push((handle, Some((priority, step))));
+                }
+            }
+            let queued = model.iter().filter(|&&(_, entry)| entry.is_some()).count();
+            assert_eq!(queue.len(), queued);
+        }
+        for &(handle, entry) in &model {
+            assert_eq!(queue.get(handle), entry.as_ref().map(|(_, val)| val));
+            assert_eq!(queue.priority(handle), entry.as_ref().map(|(p, _)| p));
+        }
+        let mut last = None;
+        while let Some((priority, _)) = queue.pop_min() {
+            assert!(last <= Some(priority));
+            last = Some(priority);
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_empty_and_single() {
+        let mut queue: AvlPriorityQueue<u32, char> = AvlPriorityQueue::new();
+        assert_eq!((queue.peek_min(), queue.peek_max()), (None, None));
+        assert_eq!((queue.pop_min(), queue.pop_max()), (None, None));
+
+        //a single entry is both ends, and popping it from either empties the queue
+        let a = queue.push(5, 'a');
+        assert_eq!(queue.peek_min(), queue.peek_max());
+        assert_eq!(queue.pop_max(), Some((5, 'a')));
+        assert!(queue.is_empty());
+        assert!(!queue.contains(a));
+        assert_eq!(queue.remove(a), None);
+        assert_eq!(queue.change_priority(a, 1), None);
+
+        //handles are not reused once the queue has been emptied
+        let b = queue.push(5, 'b');
+        assert_ne!(a, b);
+        assert_eq!(queue.get(a), None);
+        assert_eq!(queue.remove(b), Some((5, 'b')));
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_equal_priorities() {
+        let mut queue = AvlPriorityQueue::new();
+        let handles: Vec<Handle> = (0..4).map(|i| queue.push(1, i)).collect();
+        //the earliest pushed is the min and the latest the max, and a change keeps its place
+        assert_eq!(queue.change_priority(handles[0], 1), Some(1));
+        assert_eq!(queue.peek_min(), Some((&1, &0)));
+        assert_eq!(queue.peek_max(), Some((&1, &3)));
+
+        //moving entries past either end
+        queue.change_priority(handles[3], 0);
+        queue.change_priority(handles[0], 2);
+        assert_eq!(queue.pop_min(), Some((0, 3)));
+        assert_eq!(queue.pop_max(), Some((2, 0)));
+        assert_eq!(queue.pop_min(), Some((1, 1)));
+        assert_eq!(queue.pop_min(), Some((1, 2)));
+        assert_eq!(queue.pop_min(), None);
+    }
+}
@@ -0,0 +1,538 @@
+//! A list with O(log n) access, insertion and removal at any position.
+//!
+//! `AvlSeq` is an AvlTree whose keys are all `()`: nodes are ordered by where they were put
+//! rather than by comparing keys, and the `Count` summary every subtree keeps gives its size,
+//! which is enough to find any position on the way down. Rebalancing, splitting and joining
+//! are the same as for a keyed tree, since none of them look at keys.
+use core::fmt;
+use core::ops::{Bound, Index, IndexMut, RangeBounds};
+use core::iter::FromIterator;
+use alloc::vec::Vec;
+
+use {AvlNode, AvlTree};
+use summary::Count;
+
+type Tree<T> = AvlTree<(), T, Count>;
+
+/// A sequence of values that can be inserted into, removed from, split and joined anywhere in
+/// O(log n), like a rope.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::seq::AvlSeq;
+///
+/// let mut seq: AvlSeq<char> = "hllo".chars().collect();
+/// seq.insert(1, 'e');
+/// seq.push('!');
+/// assert_eq!(seq.iter().collect::<String>(), "hello!");
+///
+/// let tail = seq.split_off(2);
+/// assert_eq!(seq.iter().collect::<String>(), "he");
+/// assert_eq!(tail.slice(1..).collect::<String>(), "lo!");
+/// ```
+pub struct AvlSeq<T>(Tree<T>);
+
+/// An iterator over a run of values in an `AvlSeq`, in order.
+pub struct Iter<'a, T: 'a> {
+    ///the nodes still to be visited whose values come after everything already given, with
+    ///the next one on top. only their right subtrees are left to walk
+    stack: Vec<&'a AvlNode<(), T, Count>>,
+    remaining: usize
+}
+
+impl<T> AvlSeq<T> {
+
+    /// Creates a new empty sequence
+    pub fn new() -> Self {
+        AvlSeq(AvlTree::from_root(None))
+    }
+
+    /// The number of values in the sequence
+    pub fn len(&self) -> usize {
+        self.0.summary()
+    }
+
+    /// Checks if the sequence is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///the node at the index, if there is one
+    fn node(&self, mut index: usize) -> Option<&AvlNode<(), T, Count>> {
+        let mut tree = &self.0;
+        while let Some(ref node) = tree.0 {
+            let left = node.left.summary();
+            if index < left {
+                tree = &node.left;
+            } else if index == left {
+                return Some(node);
+            } else {
+                index -= left + 1;
+                tree = &node.right;
+            }
+        }
+        None
+    }
+
+    /// Gives the value at the index, or None if the index is past the end
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.node(index).map(|node| &node.val)
+    }
+
+    /// Gives the value at the index to change, or None if the index is past the end
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let mut tree = &mut self.0;
+        let mut index = index;
+        while let Some(ref mut node) = tree.0 {
+            let left = node.left.summary();
+            if index < left {
+                tree = &mut node.left;
+            } else if index == left {
+                return Some(&mut node.val);
+            } else {
+                index -= left + 1;
+                tree = &mut node.right;
+            }
+        }
+        None
+    }
+
+    /// Adds a value to the end of the sequence
+    pub fn push(&mut self, val: T) {
+        let len = self.len();
+        self.insert(len, val);
+    }
+
+    /// Inserts a value at the index, shifting everything after it along by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is greater than the length.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::seq::AvlSeq;
+    ///
+    /// let mut seq = AvlSeq::new();
+    /// seq.insert(0, "b");
+    /// seq.insert(0, "a");
+    /// seq.insert(2, "c");
+    /// assert_eq!(seq.iter().collect::<Vec<_>>(), vec![&"a", &"b", &"c"]);
+    /// ```
+    pub fn insert(&mut self, index: usize, val: T) {
+        assert!(index <= self.len(), "insertion index {} is past the end of a sequence of {}", index, self.len());
+        insert_at(&mut self.0, index, val);
+    }
+
+    /// Removes the value at the index and gives it back, shifting everything after it back
+    /// by one. Returns None if the index is past the end.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::seq::AvlSeq;
+    ///
+    /// let mut seq: AvlSeq<i32> = (0..5).collect();
+    /// assert_eq!(seq.remove(1), Some(1));
+    /// assert_eq!(seq.remove(4), None);
+    /// assert_eq!(seq.iter().collect::<Vec<_>>(), vec![&0, &2, &3, &4]);
+    /// ```
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(remove_at(&mut self.0, index))
+    }
+
+    /// Splits the sequence in two at the index: this keeps the values before it, and the
+    /// values from it onwards are returned. O(log n).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is greater than the length.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(index <= self.len(), "split index {} is past the end of a sequence of {}", index, self.len());
+        let tree = ::core::mem::replace(&mut self.0, AvlTree::from_root(None));
+        let (front, back) = split_at(tree, index);
+        self.0 = front;
+        AvlSeq(back)
+    }
+
+    /// Moves every value from other onto the end of this sequence, leaving other empty.
+    /// O(log n).
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::seq::AvlSeq;
+    ///
+    /// let mut seq: AvlSeq<i32> = (0..3).collect();
+    /// let mut more: AvlSeq<i32> = (3..6).collect();
+    /// seq.append(&mut more);
+    /// assert!(more.is_empty());
+    /// assert_eq!(seq.iter().cloned().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+    /// ```
+    pub fn append(&mut self, other: &mut Self) {
+        let front = ::core::mem::replace(&mut self.0, AvlTree::from_root(None));
+        let back = ::core::mem::replace(&mut other.0, AvlTree::from_root(None));
+        self.0 = AvlTree::join2(front, back);
+    }
+
+    /// Gives an iterator over the values at the positions in the range, in order. Finding the
+    /// start of the range takes O(log n), and each step after that O(1) on average.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends or ends past the end of the sequence.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::seq::AvlSeq;
+    ///
+    /// let seq: AvlSeq<i32> = (0..100).collect();
+    /// assert_eq!(seq.slice(10..13).collect::<Vec<_>>(), vec![&10, &11, &12]);
+    /// assert_eq!(seq.slice(98..).count(), 2);
+    /// ```
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Iter<'_, T> {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => Some(start),
+            Bound::Excluded(&start) => start.checked_add(1),
+            Bound::Unbounded => Some(0)
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1),
+            Bound::Excluded(&end) => Some(end),
+            Bound::Unbounded => Some(len)
+        };
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            //a bound one past usize::MAX is past the end of any sequence
+            _ => panic!("slice is past the end of a sequence of {}", len)
+        };
+        assert!(start <= end, "slice starts at {} but ends at {}", start, end);
+        assert!(end <= len, "slice end {} is past the end of a sequence of {}", end, len);
+
+        let mut stack = Vec::new();
+        let mut tree = &self.0;
+        let mut index = start;
+        //every node passed on the way down to the start whose value comes after it is left
+        //on the stack
+        while let Some(ref node) = tree.0 {
+            let left = node.left.summary();
+            if index <= left {
+                stack.push(&**node);
+                if index == left {
+                    break;
+                }
+                tree = &node.left;
+            } else {
+                index -= left + 1;
+                tree = &node.right;
+            }
+        }
+        Iter { stack, remaining: end - start }
+    }
+
+    /// Gives an iterator over every value in order
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.slice(..)
+    }
+}
+
+///inserts a value so that it ends up at the index within the subtree
+fn insert_at<T>(tree: &mut Tree<T>, index: usize, val: T) {
+    match tree.0 {
+        None => *tree = AvlTree::node(AvlTree::from_root(None), (), val, AvlTree::from_root(None)),
+        Some(ref mut node) => {
+            let left = node.left.summary();
+            if index <= left {
+                insert_at(&mut node.left, index, val);
+            } else {
+                insert_at(&mut node.right, index - left - 1, val);
+            }
+        }
+    }
+    tree.rebalance();
+}
+
+///removes the value at the index within the subtree, which must be in it
+fn remove_at<T>(tree: &mut Tree<T>, index: usize) -> T {
+    let mut node = tree.0.take().unwrap();
+    let left = node.left.summary();
+    let val = if index < left {
+        let val = remove_at(&mut node.left, index);
+        tree.0 = Some(node);
+        val
+    } else if index > left {
+        let val = remove_at(&mut node.right, index - left - 1);
+        tree.0 = Some(node);
+        val
+    } else {
+        let node = *node;
+        //the same replacement as a keyed remove: the next value in order takes its place
+        *tree = match (node.left.0, node.right.0) {
+            (None, None) => AvlTree::from_root(None),
+            (Some(left), None) => AvlTree::from_root(Some(left)),
+            (None, Some(right)) => AvlTree::from_root(Some(right)),
+            (Some(left), Some(right)) => {
+                let mut newright = AvlTree::from_root(Some(right));
+                let mut min = newright.take_min();
+                min.right = newright;
+                min.left = AvlTree::from_root(Some(left));
+                AvlTree::from_root(Some(min))
+            }
+        };
+        node.val
+    };
+    tree.rebalance();
+    val
+}
+
+///splits a subtree into its first index values and the rest
+fn split_at<T>(tree: Tree<T>, index: usize) -> (Tree<T>, Tree<T>) {
+    match tree.0 {
+        None => (AvlTree::from_root(None), AvlTree::from_root(None)),
+        Some(node) => {
+            let node = *node;
+            let left = node.left.summary();
+            if index <= left {
+                let (front, back) = split_at(node.left, index);
+                (front, AvlTree::join(back, (), node.val, node.right))
+            } else {
+                let (front, back) = split_at(node.right, index - left - 1);
+                (AvlTree::join(node.left, (), node.val, front), back)
+            }
+        }
+    }
+}
+
+impl<'a, T: 'a> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.stack.pop()?;
+        self.remaining -= 1;
+        let mut tree = &node.right;
+        while let Some(ref next) = tree.0 {
+            self.stack.push(next);
+            tree = &next.left;
+        }
+        Some(&node.val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a AvlSeq<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> Default for AvlSeq<T> {
+    fn default() -> Self {
+        AvlSeq::new()
+    }
+}
+
+impl<T> FromIterator<T> for AvlSeq<T> {
+    /// Builds a balanced sequence from all of the values at once, in O(n)
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let values: Vec<T> = values.into_iter().collect();
+        let n = values.len();
+        AvlSeq(AvlTree::from_sorted(&mut values.into_iter().map(|val| ((), val)), n))
+    }
+}
+
+impl<T> Extend<T> for AvlSeq<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        let mut more: AvlSeq<T> = values.into_iter().collect();
+        self.append(&mut more);
+    }
+}
+
+impl<T> Index<usize> for AvlSeq<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(val) => val,
+            None => panic!("index {} is past the end of a sequence of {}", index, self.len())
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for AvlSeq<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len();
+        match self.get_mut(index) {
+            Some(val) => val,
+            None => panic!("index {} is past the end of a sequence of {}", index, len)
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AvlSeq<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use seq::{AvlSeq, Tree};
+    use ordered_map::conformance::XorShift;
+    use std::cmp::max;
+    use std::ops::Bound;
+
+    ///checks heights, balance and sizes, returning the height
+    fn check<T>(tree: &Tree<T>) -> i32 {
+        match tree.0 {
+            None => 0,
+            Some(ref node) => {
+                let (left, right) = (check(&node.left), check(&node.right));
+                assert!((right - left).abs() <= 1);
+                assert_eq!(node.height, max(left, right) + 1);
+                assert_eq!(node.summary, node.left.summary() + node.right.summary() + 1);
+                node.height
+            }
+        }
+    }
+
+    fn contents(seq: &AvlSeq<u64>) -> Vec<u64> {
+        seq.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_differential() {
+        let mut seq = AvlSeq::new();
+        let mut model = Vec::new();
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..5000 {
+            let index = rng.next(model.len() as u64 + 1) as usize;
+            match rng.next(20) {
+                0 => {
+                    let mut tail = seq.split_off(index);
+                    let mut model_tail = model.split_off(index);
+                    assert_eq!(contents(&seq), model);
+                    assert_eq!(contents(&tail), model_tail);
+                    check(&tail.0);
+                    seq.append(&mut tail);
+                    model.append(&mut model_tail);
+                },
+                1 => {
+                    let end = index + rng.next((model.len() - index) as u64 + 1) as usize;
+                    assert_eq!(seq.slice(index..end).cloned().collect::<Vec<_>>(), &model[index..end]);
+                },
+                2..=8 => assert_eq!(seq.remove(index), if index < model.len() { Some(model.remove(index)) } else { None }),
+                _ => {
+                    seq.insert(index, step);
+                    model.insert(index, step);
+                }
+            }
+            assert_eq!(seq.len(), model.len());
+            check(&seq.0);
+        }
+        assert_eq!(contents(&seq), model);
+        for (index, val) in model.iter().enumerate() {
+            assert_eq!(seq[index], *val);
+        }
+    }
+
+    #[test]
+    fn test_append_uneven() {
+        let mut seq: AvlSeq<u64> = (0..1000).collect();
+        let mut small: AvlSeq<u64> = (1000..1003).collect();
+        seq.append(&mut small);
+        check(&seq.0);
+        let mut seq2: AvlSeq<u64> = (0..3).collect();
+        seq2.append(&mut seq);
+        check(&seq2.0);
+        assert_eq!(contents(&seq2), (0..3).chain(0..1003).collect::<Vec<_>>());
+
+        seq2.extend(5..7);
+        seq2[0] = 99;
+        assert_eq!(seq2.len(), 1008);
+        assert_eq!(seq2.get(0), Some(&99));
+        assert_eq!(seq2.get(1007), Some(&6));
+        assert_eq!(seq2.get(1008), None);
+    }
+
+    #[test]
+    fn test_boundaries() {
+        //an empty sequence has nothing to get or remove, and only index 0 to split at
+        let mut seq: AvlSeq<u64> = AvlSeq::new();
+        assert_eq!(seq.get(0), None);
+        assert_eq!(seq.remove(0), None);
+        assert_eq!(seq.slice(..).count(), 0);
+        assert!(seq.split_off(0).is_empty());
+
+        //inserting at the front, at the end and in between
+        seq.insert(0, 1);
+        seq.insert(1, 3);
+        seq.insert(0, 0);
+        seq.insert(2, 2);
+        assert_eq!(contents(&seq), vec![0, 1, 2, 3]);
+        assert_eq!(seq.get(3), Some(&3));
+        assert_eq!(seq.get(4), None);
+        assert_eq!(seq.get(usize::MAX), None);
+        assert_eq!(seq.remove(4), None);
+        assert_eq!(seq.slice(4..).count(), 0);
+        assert_eq!(seq.slice(..=3).count(), 4);
+
+        //splitting at either end leaves everything on one side
+        let mut tail = seq.split_off(4);
+        assert!(tail.is_empty());
+        let mut all = seq.split_off(0);
+        assert!(seq.is_empty());
+        assert_eq!(contents(&all), vec![0, 1, 2, 3]);
+        seq.append(&mut all);
+        seq.append(&mut tail);
+        check(&seq.0);
+
+        //emptied from both ends, then filled again
+        assert_eq!(seq.remove(3), Some(3));
+        assert_eq!(seq.remove(0), Some(0));
+        assert_eq!(seq.remove(1), Some(2));
+        assert_eq!(seq.remove(0), Some(1));
+        assert!(seq.is_empty());
+        check(&seq.0);
+        seq.push(7);
+        assert_eq!(contents(&seq), vec![7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn test_insert_past_end() {
+        let mut seq: AvlSeq<i32> = (0..3).collect();
+        seq.insert(4, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn test_slice_included_max() {
+        let seq: AvlSeq<i32> = (0..10).collect();
+        seq.slice(..=usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn test_slice_excluded_max() {
+        let seq: AvlSeq<i32> = (0..10).collect();
+        seq.slice((Bound::Excluded(usize::MAX), Bound::Unbounded));
+    }
+}
@@ -0,0 +1,79 @@
+//! Serialize and Deserialize for AvlTree, enabled by the `serde` feature.
+use core::cmp::{Ord, min};
+use core::fmt;
+use core::marker::PhantomData;
+use alloc::vec::Vec;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+use ordered_map::sorted_unique;
+
+use AvlTree;
+use summary::Summary;
+
+impl<K: Ord + Serialize, V: Serialize, S: Summary<K,V>> Serialize for AvlTree<K,V,S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+struct TreeVisitor<K, V, S> (PhantomData<(K, V, S)>);
+
+impl<'de, K, V, S> Visitor<'de> for TreeVisitor<K, V, S>
+    where K: Ord + Deserialize<'de>, V: Deserialize<'de>, S: Summary<K,V> {
+    type Value = AvlTree<K,V,S>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        //the size hint comes from the input, so do not trust it with a huge allocation
+        let mut entries = Vec::with_capacity(min(map.size_hint().unwrap_or(0), 4096));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        let entries = sorted_unique(entries);
+        let n = entries.len();
+        Ok(AvlTree::from_sorted(&mut entries.into_iter(), n))
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for AvlTree<K,V,S>
+    where K: Ord + Deserialize<'de>, V: Deserialize<'de>, S: Summary<K,V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TreeVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json;
+    use AvlTree;
+    use summary::Sum;
+
+    #[test]
+    fn test_round_trip() {
+        let mut tree = AvlTree::new();
+        for num in 0..1000 {
+            tree.insert(num, num * 2);
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        let back: AvlTree<i32, i32> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back.validate(), Ok(()));
+        assert_eq!(back.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unsorted_and_duplicates() {
+        let json = r#"{"c": 3, "a": 1, "b": 2, "a": 4}"#;
+        let tree: AvlTree<String, i32, Sum> = serde_json::from_str(json).unwrap();
+
+        assert_eq!(tree.validate(), Ok(()));
+        let entries: Vec<_> = tree.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(entries, vec![("a", 4), ("b", 2), ("c", 3)]);
+        //summaries are built along with the tree
+        assert_eq!(tree.aggregate(None, None), 9);
+    }
+}
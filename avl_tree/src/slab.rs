@@ -0,0 +1,815 @@
+//! An AvlTree that keeps its nodes in a slab instead of boxing each one.
+//!
+//! Nodes live in one `Vec` and point at their children by `u32` index, so building a tree
+//! makes a handful of large allocations instead of one per entry, and the nodes of a tree stay
+//! close together in memory. Removing an entry drops its key and value straight away and puts
+//! its slot on a free list, which the next insert reuses.
+//!
+//! The rotations, rebalancing, insert, remove and validation follow `AvlTree`'s step for step
+//! rather than sharing its code. `AvlTree` moves owned boxes from one subtree to another, while
+//! here every step reads and writes slots by index through the one borrow of the slab, so the
+//! two could only share code through a storage trait that hides both ways of borrowing, and
+//! the boxed tree's hot paths would pay for it. Both trees run the same conformance suite and
+//! check themselves with `validate`, which keeps them behaving alike.
+use core::cmp::{Ord, Ordering, max};
+use core::mem::replace;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use ordered_map::OrderedMap;
+
+use {AllocError, Iter, ValidationError};
+
+///the index that stands for no node, both for children and for the end of the free list
+const NIL: u32 = u32::MAX;
+
+struct SlabNode<K: Ord, V> {
+    key: K,
+    val: V,
+    height: i32,
+    left: u32,
+    right: u32
+}
+
+///A slot in the slab either holds a node or is free, in which case it links to the next
+///free slot
+enum Slot<K: Ord, V> {
+    Occupied(SlabNode<K,V>),
+    Vacant(u32)
+}
+
+/// A map based on a binary tree that self balances using the AVL algorithm, storing its nodes
+/// in a slab.
+///
+/// It behaves like [`AvlTree`](../struct.AvlTree.html), but every node is a slot in a single
+/// growable array and children are `u32` indices into it, so a tree holds at most `u32::MAX`
+/// entries. Removed slots are reused by later inserts, and `clear` drops the entries without
+/// visiting the tree.
+pub struct SlabAvlTree<K: Ord, V> {
+    slots: Vec<Slot<K,V>>,
+    root: u32,
+    free: u32,
+    len: usize
+}
+
+impl<K: Ord, V> SlabAvlTree<K,V> {
+
+    /// Creates a new empty SlabAvlTree
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    ///
+    /// tree.insert(1,"a");
+    /// ```
+    pub fn new() -> Self {
+        SlabAvlTree { slots: Vec::new(), root: NIL, free: NIL, len: 0 }
+    }
+
+    /// Creates a new empty SlabAvlTree with room for `capacity` entries before the slab
+    /// has to grow
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::with_capacity(100);
+    ///
+    /// tree.insert(1,"a");
+    /// assert!(tree.capacity() >= 100);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        SlabAvlTree { slots: Vec::with_capacity(capacity), root: NIL, free: NIL, len: 0 }
+    }
+
+    /// Returns the number of entries in the tree
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    /// tree.insert(1,"a");
+    /// tree.insert(2,"b");
+    /// tree.insert(1,"c");
+    ///
+    /// assert_eq!(tree.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of entries the slab can hold before it has to grow
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    /// Makes room for at least `additional` more entries, giving back an error instead of
+    /// aborting if the memory can not be allocated. Once it succeeds, that many inserts can
+    /// be made without allocating, and so without failing.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    /// tree.try_reserve(10).expect("out of memory");
+    ///
+    /// let capacity = tree.capacity();
+    /// for i in 0..10 {
+    ///     tree.insert(i, i);
+    /// }
+    /// assert_eq!(tree.capacity(), capacity);
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        let needed = self.len.saturating_add(additional).saturating_sub(self.slots.len());
+        Ok(self.slots.try_reserve(needed)?)
+    }
+
+    /// checks if the tree is empty.
+    ///
+    ///  #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///
+    ///  assert!(tree.is_empty());
+    ///
+    ///  tree.insert("hello","world");
+    ///
+    ///  assert!(!tree.is_empty());
+    pub fn is_empty(&self) -> bool {
+        self.root == NIL
+    }
+
+    /// Returns the height of the tree, the number of nodes on the longest path from the root
+    /// to any leaf.
+    ///
+    /// #Examples
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    ///
+    /// assert_eq!(tree.height(),0);
+    /// for key in 0..4 {
+    ///     tree.insert(key,"a");
+    /// }
+    /// assert_eq!(tree.height(),3);
+    /// ```
+    pub fn height(&self) -> i32 {
+        self.height_of(self.root)
+    }
+
+    /// Removes every entry from the tree. The entries are dropped, but the tree is not walked
+    /// and no free list is built: the slab is emptied in one go and keeps its capacity, so
+    /// this is O(1) when the keys and values need no dropping.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    /// tree.insert(1,"a");
+    /// tree.clear();
+    ///
+    /// assert!(tree.is_empty());
+    /// assert_eq!(tree.get(&1), None);
+    /// ```
+    pub fn clear(&mut self) {
+        //the tree is emptied before any entry is dropped, so a panicking drop can not leave it
+        //linking to slots that are gone
+        self.root = NIL;
+        self.free = NIL;
+        self.len = 0;
+        self.slots.clear();
+    }
+
+    ///the node in a slot that the tree links to
+    fn node(&self, index: u32) -> &SlabNode<K,V> {
+        match self.slots[index as usize] {
+            Slot::Occupied(ref node) => node,
+            Slot::Vacant(_) => unreachable!("the tree links to a free slot")
+        }
+    }
+
+    fn node_mut(&mut self, index: u32) -> &mut SlabNode<K,V> {
+        match self.slots[index as usize] {
+            Slot::Occupied(ref mut node) => node,
+            Slot::Vacant(_) => unreachable!("the tree links to a free slot")
+        }
+    }
+
+    fn height_of(&self, index: u32) -> i32 {
+        if index == NIL { 0 } else { self.node(index).height }
+    }
+
+    ///puts a new leaf in a free slot, or at the end of the slab if none are free
+    fn alloc(&mut self, key: K, val: V) -> u32 {
+        let node = SlabNode { key, val, height: 1, left: NIL, right: NIL };
+        self.len += 1;
+        if self.free == NIL {
+            assert!(self.slots.len() < NIL as usize, "SlabAvlTree is full");
+            self.slots.push(Slot::Occupied(node));
+            (self.slots.len() - 1) as u32
+        } else {
+            let index = self.free;
+            self.free = match replace(&mut self.slots[index as usize], Slot::Occupied(node)) {
+                Slot::Vacant(next) => next,
+                Slot::Occupied(_) => unreachable!("the free list links to a used slot")
+            };
+            index
+        }
+    }
+
+    ///takes the node out of its slot and puts the slot on the free list
+    fn release(&mut self, index: u32) -> SlabNode<K,V> {
+        self.len -= 1;
+        let slot = replace(&mut self.slots[index as usize], Slot::Vacant(self.free));
+        self.free = index;
+        match slot {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => unreachable!("released a free slot")
+        }
+    }
+
+    ///recomputes a node's height from its children
+    fn update(&mut self, index: u32) {
+        let height = {
+            let node = self.node(index);
+            max(self.height_of(node.left), self.height_of(node.right)) + 1
+        };
+        self.node_mut(index).height = height;
+    }
+
+    ///the right subtree's height minus the left's
+    fn balance(&self, index: u32) -> i32 {
+        let node = self.node(index);
+        self.height_of(node.right) - self.height_of(node.left)
+    }
+
+    ///lifts the left child over the node, returning the subtree's new root
+    fn right_rot(&mut self, index: u32) -> u32 {
+        let parent = self.node(index).left;
+        let moved = self.node(parent).right;
+        self.node_mut(index).left = moved;
+        self.update(index);
+        self.node_mut(parent).right = index;
+        self.update(parent);
+        parent
+    }
+
+    ///lifts the right child over the node, returning the subtree's new root
+    fn left_rot(&mut self, index: u32) -> u32 {
+        let parent = self.node(index).right;
+        let moved = self.node(parent).left;
+        self.node_mut(index).right = moved;
+        self.update(index);
+        self.node_mut(parent).left = index;
+        self.update(parent);
+        parent
+    }
+
+    ///restores the balance of the subtree at index, after one of its subtrees changed height
+    ///by at most one, returning the subtree's new root
+    fn rebalance(&mut self, index: u32) -> u32 {
+        let balance = self.balance(index);
+        if balance == -2 {
+            let left = self.node(index).left;
+            if self.balance(left) > 0 {
+                let left = self.left_rot(left);
+                self.node_mut(index).left = left;
+            }
+            self.right_rot(index)
+        } else if balance == 2 {
+            let right = self.node(index).right;
+            if self.balance(right) < 0 {
+                let right = self.right_rot(right);
+                self.node_mut(index).right = right;
+            }
+            self.left_rot(index)
+        } else {
+            self.update(index);
+            index
+        }
+    }
+
+    /// Inserts a key,value pair into the tree. Returns None if the key was
+    /// not present in the tree already. If the key was present, then the key is updated
+    /// with the new value and the old value is returned.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    ///
+    /// assert_eq!(tree.insert(37, "a"), None);
+    /// assert_eq!(tree.is_empty(), false);
+    ///
+    /// tree.insert(37, "b");
+    /// assert_eq!(tree.insert(37, "c"), Some("b"));
+    /// assert_eq!(tree.get(&37), Some(&"c"));
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let root = self.root;
+        let (root, result) = self.insert_at(root, key, val);
+        self.root = root;
+        result
+    }
+
+    /// Inserts a key,value pair like `insert`, but gives back an error instead of aborting if
+    /// the slab has to grow and the memory can not be allocated, in which case the tree is
+    /// left as it was and the key and value are handed back with the error.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    ///
+    /// assert_eq!(tree.try_insert(37, "a"), Ok(None));
+    /// assert_eq!(tree.try_insert(37, "b"), Ok(Some("a")));
+    /// ```
+    pub fn try_insert(&mut self, key: K, val: V) -> Result<Option<V>, (AllocError, K, V)> {
+        let full = self.free == NIL && self.slots.len() == self.slots.capacity();
+        if full && self.find(&key).is_none() {
+            if let Err(err) = self.slots.try_reserve(1) {
+                return Err((err.into(), key, val));
+            }
+        }
+        Ok(self.insert(key, val))
+    }
+
+    ///inserts into the subtree at index, returning the subtree's new root
+    fn insert_at(&mut self, index: u32, key: K, val: V) -> (u32, Option<V>) {
+        if index == NIL {
+            return (self.alloc(key, val), None);
+        }
+        match key.cmp(&self.node(index).key) {
+            Ordering::Equal => {
+                let old = replace(&mut self.node_mut(index).val, val);
+                (index, Some(old))
+            }
+            Ordering::Less => {
+                let left = self.node(index).left;
+                let (left, result) = self.insert_at(left, key, val);
+                self.node_mut(index).left = left;
+                (self.rebalance(index), result)
+            }
+            Ordering::Greater => {
+                let right = self.node(index).right;
+                let (right, result) = self.insert_at(right, key, val);
+                self.node_mut(index).right = right;
+                (self.rebalance(index), result)
+            }
+        }
+    }
+
+    ///finds the slot holding key
+    fn find(&self, key: &K) -> Option<u32> {
+        let mut index = self.root;
+        while index != NIL {
+            let node = self.node(index);
+            index = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(index),
+                Ordering::Less => node.left,
+                Ordering::Greater => node.right
+            };
+        }
+        None
+    }
+
+    /// Takes a reference to something of type Key and
+    /// returns None if the key is not present, or a reference to the
+    /// value if the key is present
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///  tree.insert(37, "b");
+    ///  tree.insert(1,"a");
+    ///
+    ///  assert_eq!(tree.get(&1), Some(&"a"));
+    ///  assert_eq!(tree.get(&2), None);
+    ///
+    ///  ```
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).map(|index| &self.node(index).val)
+    }
+
+    /// Takes a referenece to something of type Key and
+    /// checks if the key is present.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///  tree.insert(37, "b");
+    ///
+    ///  assert!(tree.contains_key(&37));
+    ///  assert!(!tree.contains_key(&2));
+    ///
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Takes a referenece to something of type Key and
+    /// attempts to delete the key and its associated value
+    /// from the tree. Returns None if the key was not present,
+    /// and returns the value if the key was present. The node's slot is
+    /// reused by the next insert.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///  tree.insert(37, "b");
+    ///  tree.insert(1,"a");
+    ///
+    ///  assert_eq!(tree.remove(&1), Some("a"));
+    ///  assert!(!tree.contains_key(&1));
+    ///  assert_eq!(tree.remove(&2), None);
+    ///
+    ///  ```
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let root = self.root;
+        let (root, removed) = self.remove_at(root, key);
+        self.root = root;
+        if self.root == NIL {
+            //nothing is left to point into the slab, so give back the free slots at once
+            self.clear();
+        }
+        //the removed key is only dropped now that the tree no longer links to its slot, so a
+        //panicking drop leaves the tree whole
+        removed.map(|node| node.val)
+    }
+
+    ///removes from the subtree at index, returning the subtree's new root and the removed
+    ///node, whose slot is already free
+    fn remove_at(&mut self, index: u32, key: &K) -> (u32, Option<SlabNode<K,V>>) {
+        if index == NIL {
+            return (NIL, None);
+        }
+        match key.cmp(&self.node(index).key) {
+            Ordering::Less => {
+                let left = self.node(index).left;
+                let (left, result) = self.remove_at(left, key);
+                self.node_mut(index).left = left;
+                (self.rebalance(index), result)
+            }
+            Ordering::Greater => {
+                let right = self.node(index).right;
+                let (right, result) = self.remove_at(right, key);
+                self.node_mut(index).right = right;
+                (self.rebalance(index), result)
+            }
+            Ordering::Equal => {
+                let (left, right) = {
+                    let node = self.node(index);
+                    (node.left, node.right)
+                };
+                let replacement = if left == NIL {
+                    right
+                } else if right == NIL {
+                    left
+                } else {
+                    //the smallest node on the right takes the removed node's place
+                    let (right, min) = self.take_min(right);
+                    let node = self.node_mut(min);
+                    node.left = left;
+                    node.right = right;
+                    self.rebalance(min)
+                };
+                (replacement, Some(self.release(index)))
+            }
+        }
+    }
+
+    ///unlinks the smallest node from the subtree at index, returning the subtree's new root
+    ///and the unlinked node
+    fn take_min(&mut self, index: u32) -> (u32, u32) {
+        let left = self.node(index).left;
+        if left == NIL {
+            return (self.node(index).right, index);
+        }
+        let (left, min) = self.take_min(left);
+        self.node_mut(index).left = left;
+        (self.rebalance(index), min)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree, sorted by key.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///  tree.insert(37, "b");
+    ///  tree.insert(1,"a");
+    ///
+    ///  assert_eq!(tree.iter().next().unwrap(), (&1, &"a"));
+    ///
+    pub fn iter(&self) -> Iter<'_, K,V> {
+        self.range(None, None, true)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree, sorted by key, in reverse order.
+    pub fn reverse_iter(&self) -> Iter<'_, K,V> {
+        self.range(None, None, false)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree that fall within the given start and
+    /// end points (inclusive) in sorted order.  If None is given, then that side is unbounded.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::slab::SlabAvlTree;
+    ///
+    ///  let mut tree = SlabAvlTree::new();
+    ///  for key in 1..6 {
+    ///      tree.insert(key,"a");
+    ///  }
+    ///
+    ///  assert_eq!(tree.range_iter(Some(&2), None).next().unwrap(), (&2, &"a"));
+    ///
+    pub fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        self.range(start, end, true)
+    }
+
+    /// Gives an iterator over the key-value pairs in the tree that fall within the given start and
+    /// end points (inclusive) in reverse sorted order.  If None is given, then that side is unbounded.
+    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        self.range(start, end, false)
+    }
+
+    fn range(&self, start: Option<&K>, end: Option<&K>, forwards: bool) -> Iter<'_, K,V> {
+        let mut queue = VecDeque::new();
+        self.collect(self.root, start, end, &mut queue);
+        Iter { queue, forwards }
+    }
+
+    ///queues the entries of the subtree at index between start and end, in order
+    fn collect<'a>(&'a self, index: u32, start: Option<&K>, end: Option<&K>, queue: &mut VecDeque<(&'a K, &'a V)>) {
+        if index == NIL {
+            return;
+        }
+        let node = self.node(index);
+        let after_start = start.is_none_or(|start| *start <= node.key);
+        let before_end = end.is_none_or(|end| *end >= node.key);
+        if after_start {
+            self.collect(node.left, start, end, queue);
+        }
+        if after_start && before_end {
+            queue.push_back((&node.key, &node.val));
+        }
+        if before_end {
+            self.collect(node.right, start, end, queue);
+        }
+    }
+
+    /// Checks that the keys are in order, that every node stores its correct height, and
+    /// that every node is balanced, returning the first problem found in sorted order.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::slab::SlabAvlTree;
+    ///
+    /// let mut tree = SlabAvlTree::new();
+    /// for key in 0..100 {
+    ///     tree.insert(key, key);
+    /// }
+    /// assert_eq!(tree.validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_from(self.root, &mut 0, &mut None).map(|_| ())
+    }
+
+    ///validates the subtree at index, returning its actual height
+    fn validate_from<'a>(&'a self, index: u32, next: &mut usize, prev: &mut Option<&'a K>) -> Result<i32, ValidationError> {
+        if index == NIL {
+            return Ok(0);
+        }
+        let node = self.node(index);
+        let left = self.validate_from(node.left, next, prev)?;
+
+        let own = *next;
+        if prev.is_some_and(|prev| *prev >= node.key) {
+            return Err(ValidationError::OutOfOrder { index: own });
+        }
+        *prev = Some(&node.key);
+        *next += 1;
+
+        let right = self.validate_from(node.right, next, prev)?;
+
+        let actual = max(left, right) + 1;
+        if node.height != actual {
+            return Err(ValidationError::WrongHeight { index: own, stored: node.height, actual });
+        }
+        if (right - left).abs() > 1 {
+            return Err(ValidationError::Unbalanced { index: own, balance: right - left });
+        }
+        Ok(actual)
+    }
+}
+
+impl<K: Ord, V> Default for SlabAvlTree<K,V> {
+    fn default() -> Self {
+        SlabAvlTree::new()
+    }
+}
+
+impl<K: Ord, V> OrderedMap<K,V> for SlabAvlTree<K,V> {
+    type Iter<'a> = Iter<'a,K,V> where K: 'a, V: 'a;
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        SlabAvlTree::insert(self, key, val)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        SlabAvlTree::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        SlabAvlTree::remove(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        SlabAvlTree::contains_key(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        SlabAvlTree::is_empty(self)
+    }
+
+    fn iter(&self) -> Iter<'_, K,V> {
+        SlabAvlTree::iter(self)
+    }
+
+    fn reverse_iter(&self) -> Iter<'_, K,V> {
+        SlabAvlTree::reverse_iter(self)
+    }
+
+    fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        SlabAvlTree::range_iter(self, start, end)
+    }
+
+    fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        SlabAvlTree::reverse_range_iter(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use AllocError;
+    use slab::{SlabAvlTree, NIL};
+    use std::cmp::Ordering;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use test::failing_allocations;
+
+    ///a key whose drop panics if it is armed. only the number takes part in comparisons
+    struct Bomb(i32, bool);
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            if self.1 {
+                panic!("dropped an armed key");
+            }
+        }
+    }
+
+    impl PartialEq for Bomb {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for Bomb {}
+
+    impl PartialOrd for Bomb {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Bomb {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn test_slots_reused() {
+        let mut tree = SlabAvlTree::new();
+        for key in 0..100 {
+            tree.insert(key, key);
+        }
+        let slots = tree.slots.len();
+        for key in 0..50 {
+            tree.remove(&key);
+        }
+        for key in 100..150 {
+            tree.insert(key, key);
+        }
+        //every insert after the removes went into a freed slot
+        assert_eq!(tree.slots.len(), slots);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.iter().map(|(k, _)| *k).collect::<Vec<_>>(), (50..150).collect::<Vec<_>>());
+
+        for key in 50..150 {
+            tree.remove(&key);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.slots.len(), 0);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut tree = SlabAvlTree::new();
+        tree.insert(0, 0);
+        tree.insert(1, 1);
+        //the tree is not emptied, so the slot stays on the free list
+        tree.remove(&0);
+        //the count already stored must not overflow when added to
+        assert_eq!(tree.try_reserve(usize::MAX), Err(AllocError));
+        assert_eq!(tree.try_reserve(100), Ok(()));
+        //the freed slot and the reserved room are enough, so none of these allocate
+        failing_allocations(|| for key in 2..=101 {
+            assert_eq!(tree.try_insert(key, key), Ok(None));
+        });
+        assert_eq!(tree.len(), 101);
+        assert_eq!(tree.slots.len(), 101);
+
+        let capacity = tree.capacity();
+        for key in 102..=capacity as i32 {
+            tree.insert(key, key);
+        }
+        assert_eq!(failing_allocations(|| tree.try_insert(0, 0)), Err((AllocError, 0, 0)));
+        assert_eq!(failing_allocations(|| tree.try_insert(1, 7)), Ok(Some(1)));
+        assert_eq!(tree.len(), capacity);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_panicking_drop() {
+        let mut tree = SlabAvlTree::new();
+        for key in 0..30 {
+            tree.insert(Bomb(key, key == 13 || key == 20), key);
+        }
+        //13 has two children, so removing it moves another node into its place first
+        let index = tree.find(&Bomb(13, false)).unwrap();
+        assert!(tree.node(index).left != NIL && tree.node(index).right != NIL);
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.remove(&Bomb(13, false)))).is_err());
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.len(), 29);
+        let keys: Vec<i32> = tree.iter().map(|(key, _)| key.0).collect();
+        assert_eq!(keys, (0..30).filter(|&key| key != 13).collect::<Vec<_>>());
+        tree.insert(Bomb(13, false), 13);
+        assert_eq!(tree.get(&Bomb(13, false)), Some(&13));
+
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.clear())).is_err());
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.iter().count(), 0);
+        tree.insert(Bomb(1, false), 1);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut tree = SlabAvlTree::with_capacity(10);
+        for key in 0..10 {
+            tree.insert(key, key.to_string());
+        }
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert!(tree.capacity() >= 10);
+        tree.insert(3, String::from("c"));
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&3, &String::from("c"))]);
+    }
+
+    mod conformance {
+        use slab::SlabAvlTree;
+        ordered_map_conformance!(SlabAvlTree<i32, i32>);
+    }
+}
@@ -0,0 +1,438 @@
+//! A compact, versioned binary format for saving an AvlTree and loading it back.
+//!
+//! A snapshot is laid out as follows, with all integers little endian:
+//!
+//! | field    | size      | contents                                       |
+//! |----------|-----------|------------------------------------------------|
+//! | magic    | 4 bytes   | `b"AVLS"`                                      |
+//! | version  | 4 bytes   | the format version, currently 1                |
+//! | count    | 8 bytes   | the number of entries                          |
+//! | entries  | count     | a record for each entry, in sorted order       |
+//! | checksum | 4 bytes   | CRC-32 of everything before it                 |
+//!
+//! Each record is the key then the value, each written as a 4 byte length followed by that
+//! many bytes from its [`Codec`](trait.Codec.html).
+use std::cmp::{Ord, min};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use AvlTree;
+use summary::Summary;
+
+const MAGIC: &[u8; 4] = b"AVLS";
+
+/// The version of the format written by `write_snapshot`.
+pub const VERSION: u32 = 1;
+
+/// Turns keys and values into bytes for a snapshot, and back again.
+pub trait Codec: Sized {
+    /// Appends the bytes for this value to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Reads a value back from exactly the bytes `encode` wrote for it. Returns None if the
+    /// bytes are not a valid encoding.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! int_codec {
+    ($($t:ty),*) => {$(
+        impl Codec for $t {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(bytes: &[u8]) -> Option<Self> {
+                if bytes.len() != ::std::mem::size_of::<$t>() {
+                    return None;
+                }
+                let mut buf = [0; ::std::mem::size_of::<$t>()];
+                buf.copy_from_slice(bytes);
+                Some(<$t>::from_le_bytes(buf))
+            }
+        }
+    )*}
+}
+
+int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Written as a `u64` so snapshots do not depend on the platform's pointer width.
+impl Codec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        u64::decode(bytes).and_then(|n| if n <= usize::MAX as u64 { Some(n as usize) } else { None })
+    }
+}
+
+/// Written as an `i64` so snapshots do not depend on the platform's pointer width.
+impl Codec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        i64::decode(bytes).and_then(|n| if n >= isize::MIN as i64 && n <= isize::MAX as i64 { Some(n as isize) } else { None })
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None
+        }
+    }
+}
+
+impl Codec for () {
+    fn encode(&self, _: &mut Vec<u8>) {}
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty() { Some(()) } else { None }
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl Codec for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+/// The ways reading a snapshot can fail.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The underlying reader or writer failed, or the snapshot ended early
+    Io(io::Error),
+    /// The snapshot does not start with the magic number
+    BadMagic,
+    /// The snapshot was written in a version of the format this crate can not read
+    UnsupportedVersion(u32),
+    /// A key or value at this index could not be decoded, or was too long to write
+    BadRecord(u64),
+    /// The key at this index is not greater than the key before it
+    OutOfOrder(u64),
+    /// The checksum at the end does not match the contents
+    ChecksumMismatch
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::Io(ref err) => write!(f, "snapshot io error: {}", err),
+            SnapshotError::BadMagic => write!(f, "not an AvlTree snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            SnapshotError::BadRecord(index) => write!(f, "snapshot entry {} could not be encoded or decoded", index),
+            SnapshotError::OutOfOrder(index) => write!(f, "snapshot entry {} is out of order", index),
+            SnapshotError::ChecksumMismatch => write!(f, "snapshot checksum does not match")
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SnapshotError::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+///the lookup table for CRC-32 with the IEEE polynomial
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+///updates a running CRC-32 with more bytes. start from 0
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &b| CRC_TABLE[((crc ^ u32::from(b)) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+///writes bytes while keeping a checksum of everything written
+struct ChecksumWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    crc: u32
+}
+
+impl<'a, W: Write> ChecksumWriter<'a, W> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc = crc32(self.crc, bytes);
+        self.inner.write_all(bytes)
+    }
+}
+
+///reads bytes while keeping a checksum of everything read
+struct ChecksumReader<R> {
+    inner: R,
+    crc: u32
+}
+
+impl<R: Read> ChecksumReader<R> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact(buf)?;
+        self.crc = crc32(self.crc, buf);
+        Ok(())
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    ///reads a length prefixed record into buf. the buffer grows as bytes arrive, so a corrupt
+    ///length can not make it allocate more than the input holds
+    fn read_record(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+        let len = self.read_u32()?;
+        buf.clear();
+        (&mut self.inner).take(u64::from(len)).read_to_end(buf)?;
+        if buf.len() as u64 != u64::from(len) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "snapshot record was cut off"));
+        }
+        self.crc = crc32(self.crc, buf);
+        Ok(())
+    }
+}
+
+///appends a length prefixed record for val to out
+fn encode_record<T: Codec>(val: &T, out: &mut Vec<u8>, index: u64) -> Result<(), SnapshotError> {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    val.encode(out);
+    let len = out.len() - start - 4;
+    if len > u32::MAX as usize {
+        return Err(SnapshotError::BadRecord(index));
+    }
+    out[start..start + 4].copy_from_slice(&(len as u32).to_le_bytes());
+    Ok(())
+}
+
+impl<K: Ord + Codec, V: Codec, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Writes the whole tree to a writer in the snapshot format described in the
+    /// [`snapshot`](snapshot/index.html) module. Entries are written in sorted order, one
+    /// record at a time, so wrapping the writer in a `BufWriter` is a good idea.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(1, String::from("a"));
+    ///  tree.insert(2, String::from("b"));
+    ///
+    ///  let mut bytes = Vec::new();
+    ///  tree.write_snapshot(&mut bytes).unwrap();
+    ///
+    ///  let loaded: AvlTree<i32, String> = AvlTree::read_snapshot(&bytes[..]).unwrap();
+    ///  assert_eq!(loaded.get(&2), Some(&String::from("b")));
+    ///  ```
+    pub fn write_snapshot<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+        let mut out = ChecksumWriter { inner: writer, crc: 0 };
+        let entries = self.iter();
+
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&(entries.len() as u64).to_le_bytes())?;
+
+        let mut record = Vec::new();
+        for (index, (key, val)) in entries.enumerate() {
+            record.clear();
+            encode_record(key, &mut record, index as u64)?;
+            encode_record(val, &mut record, index as u64)?;
+            out.write_all(&record)?;
+        }
+
+        let crc = out.crc;
+        out.inner.write_all(&crc.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a tree back from a snapshot written by `write_snapshot`. The whole snapshot is
+    /// checked before the tree is given back: the header, the order of the keys and the
+    /// checksum. The tree is then built from the sorted entries in O(n).
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///  use avltree_map::snapshot::SnapshotError;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(1u32, true);
+    ///
+    ///  let mut bytes = Vec::new();
+    ///  tree.write_snapshot(&mut bytes).unwrap();
+    ///
+    ///  //a bool can only be encoded as 0 or 1
+    ///  bytes[28] = 7;
+    ///  match AvlTree::<u32, bool>::read_snapshot(&bytes[..]) {
+    ///      Err(SnapshotError::BadRecord(0)) => (),
+    ///      _ => panic!("corruption was not found")
+    ///  }
+    ///  ```
+    pub fn read_snapshot<R: Read>(reader: R) -> Result<Self, SnapshotError> {
+        let mut input = ChecksumReader { inner: reader, crc: 0 };
+
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = input.read_u32()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let count = input.read_u64()?;
+
+        //the count comes from the input, so do not trust it with a huge allocation
+        let mut entries: Vec<(K,V)> = Vec::with_capacity(min(count, 4096) as usize);
+        let mut buf = Vec::new();
+        for index in 0..count {
+            input.read_record(&mut buf)?;
+            let key = K::decode(&buf).ok_or(SnapshotError::BadRecord(index))?;
+            input.read_record(&mut buf)?;
+            let val = V::decode(&buf).ok_or(SnapshotError::BadRecord(index))?;
+            if entries.last().is_some_and(|last| last.0 >= key) {
+                return Err(SnapshotError::OutOfOrder(index));
+            }
+            entries.push((key, val));
+        }
+
+        let expected = input.crc;
+        let mut crc = [0; 4];
+        input.inner.read_exact(&mut crc)?;
+        if u32::from_le_bytes(crc) != expected {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+
+        let n = entries.len();
+        Ok(AvlTree::from_sorted(&mut entries.into_iter(), n))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+    use snapshot::{SnapshotError, crc32};
+
+    fn tree() -> AvlTree<u64, String> {
+        let mut tree = AvlTree::new();
+        for num in 0..1000 {
+            tree.insert(num * 7, format!("value {}", num));
+        }
+        tree
+    }
+
+    #[test]
+    fn test_crc32() {
+        //the standard check value for CRC-32
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"12345"), b"6789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = tree();
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+
+        let loaded: AvlTree<u64, String> = AvlTree::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(loaded.validate(), Ok(()));
+        assert_eq!(loaded.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+
+        let mut empty_bytes = Vec::new();
+        AvlTree::<u64, String>::new().write_snapshot(&mut empty_bytes).unwrap();
+        assert_eq!(empty_bytes.len(), 20);
+        assert!(AvlTree::<u64, String>::read_snapshot(&empty_bytes[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corruption() {
+        let mut bytes = Vec::new();
+        tree().write_snapshot(&mut bytes).unwrap();
+        let read = |bytes: &[u8]| AvlTree::<u64, String>::read_snapshot(bytes).map(|_| ());
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        match read(&bad) { Err(SnapshotError::BadMagic) => (), other => panic!("{:?}", other) }
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        match read(&bad) { Err(SnapshotError::UnsupportedVersion(2)) => (), other => panic!("{:?}", other) }
+
+        //change a letter inside a value so every record still decodes
+        let mut bad = bytes.clone();
+        let at = bytes.len() / 2;
+        let at = at + bad[at..].windows(5).position(|w| w == b"value").unwrap();
+        bad[at] = b'w';
+        match read(&bad) { Err(SnapshotError::ChecksumMismatch) => (), other => panic!("{:?}", other) }
+
+        match read(&bytes[..bytes.len() - 1]) { Err(SnapshotError::Io(_)) => (), other => panic!("{:?}", other) }
+        match read(&bytes[..100]) { Err(SnapshotError::Io(_)) => (), other => panic!("{:?}", other) }
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut tree = AvlTree::new();
+        tree.insert(1u8, ());
+        tree.insert(2u8, ());
+        let mut bytes = Vec::new();
+        tree.write_snapshot(&mut bytes).unwrap();
+
+        //swap the two one byte keys, which sit after their length and the previous record
+        bytes.swap(20, 29);
+        match AvlTree::<u8, ()>::read_snapshot(&bytes[..]) {
+            Err(SnapshotError::OutOfOrder(1)) => (),
+            other => panic!("{:?}", other.map(|_| ()))
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Memory use and shape of an AvlTree.
+use core::cmp::{Ord, max};
+use core::mem::size_of;
+
+pub use ordered_map::heap_size::HeapSize;
+
+use {AvlNode, AvlTree};
+use summary::Summary;
+
+/// Statistics about an AvlTree, from `AvlTree::stats` or `AvlTree::stats_with_contents`.
+///
+/// Depths count nodes, so the root is at depth 1 and the deepest node is as deep as the tree
+/// is high.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    /// The number of entries
+    pub len: usize,
+    /// The bytes allocated for the nodes, which hold the keys and values themselves
+    pub node_bytes: usize,
+    /// The bytes the keys and values own on the heap, if they were counted
+    pub content_bytes: Option<usize>,
+    /// The average depth of the entries, or 0 for an empty tree
+    pub average_depth: f64,
+    /// The depth of the deepest entry, or 0 for an empty tree
+    pub max_depth: usize,
+    /// How many nodes lean left, are balanced, and lean right, where a node leans toward
+    /// its taller subtree
+    pub balance_factors: [usize; 3]
+}
+
+impl TreeStats {
+    /// All of the heap bytes counted: the nodes, and the keys' and values' own memory if it
+    /// was counted
+    pub fn heap_bytes(&self) -> usize {
+        self.node_bytes + self.content_bytes.unwrap_or(0)
+    }
+}
+
+impl<K: Ord, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Gives the number of entries, the bytes used by the nodes, and the depths and balance
+    /// factors of the nodes. Keys and values are only counted by their own size; use
+    /// `stats_with_contents` to count the memory they own as well. Runs in O(n).
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  for key in 0..3 {
+    ///      tree.insert(key, "a");
+    ///  }
+    ///
+    ///  let stats = tree.stats();
+    ///  assert_eq!(stats.len, 3);
+    ///  assert_eq!(stats.max_depth, 2);
+    ///  assert_eq!(stats.average_depth, 5.0 / 3.0);
+    ///  assert_eq!(stats.balance_factors, [0, 3, 0]);
+    ///  assert_eq!(stats.content_bytes, None);
+    ///  ```
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            len: 0,
+            node_bytes: 0,
+            content_bytes: None,
+            average_depth: 0.0,
+            max_depth: 0,
+            balance_factors: [0; 3]
+        };
+        let mut total_depth = 0;
+        self.visit(1, &mut |node, depth| {
+            stats.len += 1;
+            total_depth += depth;
+            stats.max_depth = max(stats.max_depth, depth);
+            let balance = node.right.height() - node.left.height();
+            stats.balance_factors[(balance.signum() + 1) as usize] += 1;
+        });
+        stats.node_bytes = stats.len * size_of::<AvlNode<K,V,S>>();
+        if stats.len > 0 {
+            stats.average_depth = total_depth as f64 / stats.len as f64;
+        }
+        stats
+    }
+
+    /// Gives the same statistics as `stats`, and also counts the memory the keys and values
+    /// own on the heap through `HeapSize`. Runs in O(n), plus the time to size every key and
+    /// value.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert(1, String::with_capacity(16));
+    ///  tree.insert(2, String::with_capacity(8));
+    ///
+    ///  let stats = tree.stats_with_contents();
+    ///  assert_eq!(stats.content_bytes, Some(24));
+    ///  assert_eq!(stats.heap_bytes(), stats.node_bytes + 24);
+    ///  ```
+    pub fn stats_with_contents(&self) -> TreeStats where K: HeapSize, V: HeapSize {
+        let mut content_bytes = 0;
+        self.visit(1, &mut |node, _| content_bytes += node.key.heap_size() + node.val.heap_size());
+        TreeStats { content_bytes: Some(content_bytes), ..self.stats() }
+    }
+
+    ///runs f on every node in the tree along with its depth, in order
+    fn visit<F: FnMut(&AvlNode<K,V,S>, usize)>(&self, depth: usize, f: &mut F) {
+        if let Some(ref node) = self.0 {
+            node.left.visit(depth + 1, f);
+            f(node, depth);
+            node.right.visit(depth + 1, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_stats() {
+        let empty: AvlTree<u32, u32> = AvlTree::new();
+        let stats = empty.stats();
+        assert_eq!((stats.len, stats.node_bytes, stats.max_depth), (0, 0, 0));
+        assert_eq!(stats.average_depth, 0.0);
+
+        let mut tree = AvlTree::new();
+        for key in 0..1000u32 {
+            tree.insert(key, vec![0u8; 4]);
+        }
+        let stats = tree.stats_with_contents();
+        assert_eq!(stats.len, 1000);
+        assert_eq!(stats.max_depth as i32, tree.height());
+        assert!(stats.average_depth > 1.0 && stats.average_depth < stats.max_depth as f64);
+        assert_eq!(stats.balance_factors.iter().sum::<usize>(), 1000);
+        assert!(stats.node_bytes >= 1000 * (size_of::<u32>() + size_of::<Vec<u8>>()));
+        assert_eq!(stats.content_bytes, Some(4000));
+    }
+}
@@ -0,0 +1,180 @@
+//! Summaries that an AvlTree can keep for every subtree.
+//!
+//! A summary is a monoid over the entries of the tree: every node stores the combination of
+//! the summaries of its left subtree, its own entry and its right subtree, in that order. The
+//! stored values are kept up to date as the tree is rebalanced, which lets
+//! [`AvlTree::aggregate`](../struct.AvlTree.html#method.aggregate) combine any range of keys
+//! in O(log n).
+use core::cmp::{Ord, max, min};
+use core::ops::Add;
+
+/// A monoid over the entries of a tree.
+///
+/// `combine` must be associative and `empty` must be its identity, but `combine` does not need
+/// to be commutative: the left argument always summarizes smaller keys than the right.
+pub trait Summary<K, V> {
+    /// The summarized value kept for every subtree
+    type Value: Clone;
+
+    /// The summary of a range with no entries in it
+    fn empty() -> Self::Value;
+
+    /// The summary of a single entry
+    fn summarize(key: &K, val: &V) -> Self::Value;
+
+    /// Combines the summaries of two neighbouring ranges, the left one having the smaller keys
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// The empty summary. This is what a plain `AvlTree` uses, and costs nothing to keep.
+impl<K, V> Summary<K, V> for () {
+    type Value = ();
+
+    fn empty() {}
+
+    fn summarize(_: &K, _: &V) {}
+
+    fn combine(_: &(), _: &()) {}
+}
+
+/// Counts the entries in a range.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::AvlTree;
+/// use avltree_map::summary::Count;
+///
+/// let mut tree: AvlTree<i32, &str, Count> = AvlTree::with_summary();
+/// tree.insert(1, "a");
+/// tree.insert(2, "b");
+/// tree.insert(3, "c");
+///
+/// assert_eq!(tree.aggregate(Some(&2), None), 2);
+/// ```
+pub struct Count;
+
+impl<K, V> Summary<K, V> for Count {
+    type Value = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn summarize(_: &K, _: &V) -> usize {
+        1
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+}
+
+/// Sums the values in a range. The sum of an empty range is `V::default()`.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::AvlTree;
+/// use avltree_map::summary::Sum;
+///
+/// let mut tree: AvlTree<i32, i32, Sum> = AvlTree::with_summary();
+/// for i in 1..11 {
+///     tree.insert(i, i * 10);
+/// }
+///
+/// assert_eq!(tree.aggregate(Some(&3), Some(&5)), 120);
+/// ```
+pub struct Sum;
+
+impl<K, V: Clone + Default + Add<Output = V>> Summary<K, V> for Sum {
+    type Value = V;
+
+    fn empty() -> V {
+        V::default()
+    }
+
+    fn summarize(_: &K, val: &V) -> V {
+        val.clone()
+    }
+
+    fn combine(left: &V, right: &V) -> V {
+        left.clone() + right.clone()
+    }
+}
+
+/// Finds the largest value in a range, or None if the range is empty.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::AvlTree;
+/// use avltree_map::summary::Max;
+///
+/// let mut tree: AvlTree<i32, i32, Max> = AvlTree::with_summary();
+/// tree.insert(1, 7);
+/// tree.insert(2, 3);
+/// tree.insert(3, 5);
+///
+/// assert_eq!(tree.aggregate(Some(&2), None), Some(5));
+/// assert_eq!(tree.aggregate(Some(&4), None), None);
+/// ```
+pub struct Max;
+
+impl<K, V: Clone + Ord> Summary<K, V> for Max {
+    type Value = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn summarize(_: &K, val: &V) -> Option<V> {
+        Some(val.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(max(l, r).clone()),
+            (Some(v), None) | (None, Some(v)) => Some(v.clone()),
+            (None, None) => None
+        }
+    }
+}
+
+/// Finds the smallest value in a range, or None if the range is empty.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::AvlTree;
+/// use avltree_map::summary::Min;
+///
+/// let mut tree: AvlTree<i32, i32, Min> = AvlTree::with_summary();
+/// tree.insert(1, 7);
+/// tree.insert(2, 3);
+/// tree.insert(3, 5);
+///
+/// assert_eq!(tree.aggregate(None, Some(&1)), Some(7));
+/// assert_eq!(tree.aggregate(None, None), Some(3));
+/// ```
+pub struct Min;
+
+impl<K, V: Clone + Ord> Summary<K, V> for Min {
+    type Value = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn summarize(_: &K, val: &V) -> Option<V> {
+        Some(val.clone())
+    }
+
+    fn combine(left: &Option<V>, right: &Option<V>) -> Option<V> {
+        match (left, right) {
+            (Some(l), Some(r)) => Some(min(l, r).clone()),
+            (Some(v), None) | (None, Some(v)) => Some(v.clone()),
+            (None, None) => None
+        }
+    }
+}
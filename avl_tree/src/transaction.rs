@@ -0,0 +1,293 @@
+//! All-or-nothing batches of changes to an AvlTree.
+//!
+//! A transaction makes its changes to the tree straight away and keeps an undo log of what
+//! each one replaced: the old value of a replaced key, the entry a remove took out, or the
+//! key an insert added. Rolling back plays the log backwards, so it costs O(log n) for each
+//! change undone and nothing is copied up front.
+use core::cmp::Ord;
+use core::ops::Deref;
+use alloc::vec::Vec;
+
+use AvlTree;
+use summary::Summary;
+
+///how to undo one change
+enum Undo<K, V> {
+    ///the key was inserted where there was none, so it is removed again
+    Remove(K),
+    ///the key had this value, or was removed with it, so it is put back
+    Restore(K, V)
+}
+
+/// Changes to an AvlTree that are undone unless they all succeed. Made by
+/// `AvlTree::transaction`.
+///
+/// It dereferences to the tree, so the changes made so far can be read as usual, but the tree
+/// can only be changed through `insert` and `remove`, which log how to undo themselves.
+pub struct Transaction<'a, K: 'a + Ord + Clone, V: 'a, S: 'a + Summary<K,V>> {
+    tree: &'a mut AvlTree<K,V,S>,
+    undo: Vec<Undo<K,V>>,
+    done: bool
+}
+
+impl<K: Ord + Clone, V, S: Summary<K,V>> AvlTree<K,V,S> {
+
+    /// Runs f with a transaction on the tree. If f returns `Ok` its changes are kept. If it
+    /// returns `Err`, or panics, every change it made is undone before the error is returned
+    /// or the panic carries on, so the tree holds exactly what it held before. The undo log
+    /// keeps its own clones of the keys it needs, and the values the changes replaced.
+    ///
+    /// A rollback compares keys, so if the key type's `Ord` panics while the tree is being
+    /// rolled back after a panic in f, the rollback stops there and the tree keeps whatever
+    /// changes had not yet been undone. With the `std` feature that second panic is caught
+    /// rather than aborting the process, and the first carries on as before.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.insert("a", 1);
+    ///
+    ///  let result: Result<(), &str> = tree.transaction(|tx| {
+    ///      tx.insert("a", 2);
+    ///      tx.insert("b", 3);
+    ///      tx.remove(&"a");
+    ///      Err("changed my mind")
+    ///  });
+    ///  assert_eq!(result, Err("changed my mind"));
+    ///  assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&"a", &1)]);
+    ///
+    ///  tree.transaction(|tx| {
+    ///      tx.insert("b", 3);
+    ///      Ok::<(), ()>(())
+    ///  }).unwrap();
+    ///  assert_eq!(tree.get(&"b"), Some(&3));
+    ///  ```
+    pub fn transaction<T, E, F>(&mut self, f: F) -> Result<T, E>
+        where F: FnOnce(&mut Transaction<'_, K,V,S>) -> Result<T, E> {
+        let mut tx = Transaction { tree: self, undo: Vec::new(), done: false };
+        let result = f(&mut tx);
+        if result.is_err() {
+            tx.rollback_to(0);
+        }
+        //the changes are kept, or already undone, so dropping tx must not undo anything
+        tx.done = true;
+        result
+    }
+}
+
+impl<'a, K: Ord + Clone, V, S: Summary<K,V>> Transaction<'a, K,V,S> {
+
+    /// Inserts a key,value pair into the tree, as `AvlTree::insert` does, logging how to
+    /// undo it. A replaced value goes in the log, which keeps it until the transaction ends,
+    /// so the caller gets a reference to it there.
+    pub fn insert(&mut self, key: K, val: V) -> Option<&V> {
+        let undo_key = key.clone();
+        match self.tree.insert(key, val) {
+            None => {
+                self.undo.push(Undo::Remove(undo_key));
+                None
+            },
+            Some(old) => Some(self.log_restore(undo_key, old))
+        }
+    }
+
+    /// Removes a key from the tree, as `AvlTree::remove` does, logging how to undo it. The
+    /// removed entry goes in the log, and the caller gets a reference to its value there.
+    pub fn remove(&mut self, key: &K) -> Option<&V> {
+        let removed = self.tree.remove(key)?;
+        Some(self.log_restore(key.clone(), removed))
+    }
+
+    ///logs that the key had the value, giving back the value where the log keeps it
+    fn log_restore(&mut self, key: K, val: V) -> &V {
+        self.undo.push(Undo::Restore(key, val));
+        match self.undo.last() {
+            Some(Undo::Restore(_, val)) => val,
+            _ => unreachable!("the entry was just logged")
+        }
+    }
+
+    /// Runs f as a nested transaction. If f returns `Err`, only the changes it made are
+    /// undone, and the ones made before it are kept. If it panics, the whole transaction is
+    /// undone.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use avltree_map::AvlTree;
+    ///
+    ///  let mut tree = AvlTree::new();
+    ///  tree.transaction(|tx| {
+    ///      tx.insert(1, "a");
+    ///      let nested: Result<(), ()> = tx.savepoint(|tx| {
+    ///          tx.insert(2, "b");
+    ///          Err(())
+    ///      });
+    ///      assert!(nested.is_err());
+    ///      assert_eq!(tx.get(&2), None);
+    ///      Ok::<(), ()>(())
+    ///  }).unwrap();
+    ///
+    ///  assert_eq!(tree.iter().collect::<Vec<_>>(), vec![(&1, &"a")]);
+    ///  ```
+    pub fn savepoint<T, E, F>(&mut self, f: F) -> Result<T, E>
+        where F: FnOnce(&mut Self) -> Result<T, E> {
+        let mark = self.undo.len();
+        let result = f(self);
+        if result.is_err() {
+            self.rollback_to(mark);
+        }
+        result
+    }
+
+    ///undoes changes, newest first, until the log is only mark long
+    fn rollback_to(&mut self, mark: usize) {
+        while self.undo.len() > mark {
+            match self.undo.pop().unwrap() {
+                Undo::Remove(key) => { self.tree.remove(&key); },
+                Undo::Restore(key, val) => { self.tree.insert(key, val); }
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V, S: Summary<K,V>> Deref for Transaction<'a, K,V,S> {
+    type Target = AvlTree<K,V,S>;
+
+    fn deref(&self) -> &AvlTree<K,V,S> {
+        self.tree
+    }
+}
+
+impl<'a, K: Ord + Clone, V, S: Summary<K,V>> Drop for Transaction<'a, K,V,S> {
+    ///only reached without `done` set when the closure panicked. the rollback runs while that
+    ///panic unwinds, so a comparison that panics as well would abort the process; with `std`,
+    ///that second panic is caught and the rollback stops where it was
+    fn drop(&mut self) {
+        if !self.done {
+            #[cfg(feature = "std")]
+            let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| self.rollback_to(0)));
+            #[cfg(not(feature = "std"))]
+            self.rollback_to(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use AvlTree;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn entries(tree: &AvlTree<i32, String>) -> Vec<(i32, String)> {
+        tree.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut tree = AvlTree::new();
+        for key in 0..100 {
+            tree.insert(key, key.to_string());
+        }
+        let before = entries(&tree);
+
+        let result: Result<(), i32> = tree.transaction(|tx| {
+            for key in 0..200 {
+                if key % 3 == 0 {
+                    tx.remove(&key);
+                } else {
+                    assert_eq!(tx.insert(key, String::from("new")).is_some(), key < 100);
+                }
+            }
+            Err(1)
+        });
+        assert_eq!(result, Err(1));
+        assert_eq!(entries(&tree), before);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            let _: Result<(), ()> = tree.transaction(|tx| {
+                tx.remove(&5);
+                tx.insert(500, String::from("x"));
+                panic!("halfway");
+            });
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(entries(&tree), before);
+    }
+
+    #[test]
+    fn test_nested() {
+        let mut tree = AvlTree::new();
+        let result: Result<(), ()> = tree.transaction(|tx| {
+            tx.insert(1, String::from("a"));
+            tx.savepoint(|tx| {
+                tx.insert(2, String::from("b"));
+                let inner: Result<(), ()> = tx.savepoint(|tx| {
+                    tx.insert(1, String::from("changed"));
+                    tx.remove(&2);
+                    Err(())
+                });
+                assert!(inner.is_err());
+                Ok::<(), ()>(())
+            })?;
+            assert_eq!(tx.get(&1), Some(&String::from("a")));
+            assert_eq!(tx.get(&2), Some(&String::from("b")));
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(entries(&tree), vec![(1, String::from("a")), (2, String::from("b"))]);
+    }
+
+    ///only with `std` does a rollback catch a panic of its own
+    #[cfg(feature = "std")]
+    mod rollback_panic {
+        use AvlTree;
+        use std::cell::Cell;
+        use std::cmp::Ordering;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        ///a key whose comparisons panic while FRAGILE is set
+        #[derive(Clone, PartialEq, Eq, Debug)]
+        struct Fragile(i32);
+
+        thread_local!(static FRAGILE: Cell<bool> = const { Cell::new(false) });
+
+        impl PartialOrd for Fragile {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Fragile {
+            fn cmp(&self, other: &Self) -> Ordering {
+                assert!(!FRAGILE.with(Cell::get), "comparison during rollback");
+                self.0.cmp(&other.0)
+            }
+        }
+
+        #[test]
+        fn test_panic_during_rollback() {
+            let mut tree = AvlTree::new();
+            for key in 0..10 {
+                tree.insert(Fragile(key), key);
+            }
+            let panicked = catch_unwind(AssertUnwindSafe(|| {
+                let _: Result<(), ()> = tree.transaction(|tx| {
+                    tx.insert(Fragile(20), 20);
+                    FRAGILE.with(|fragile| fragile.set(true));
+                    panic!("first");
+                });
+            }));
+            FRAGILE.with(|fragile| fragile.set(false));
+            //the first panic comes through, and the process is still running to see it
+            assert_eq!(panicked.unwrap_err().downcast_ref::<&str>(), Some(&"first"));
+            //the rollback stopped at its first comparison, leaving the insert in a whole tree
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.get(&Fragile(20)), Some(&20));
+            assert_eq!(tree.iter().count(), 11);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! A multiversion AvlTree that can be read as of any past write.
+//!
+//! Every write is given the next version number, and rather than overwriting a key's value it
+//! adds the new value, or a tombstone for a remove, to the end of the key's history. The
+//! histories are kept in an ordinary `AvlTree`, so the keys are balanced the same way. Reading
+//! as of a version finds, for each key, the last change made at or before that version.
+use core::cmp::Ord;
+use alloc::vec::Vec;
+
+use {AvlTree, Iter};
+
+/// A sorted map that remembers every version of itself, until told to forget the old ones.
+///
+/// #Examples
+///
+/// ```
+/// use avltree_map::versioned::VersionedAvlTree;
+///
+/// let mut tree = VersionedAvlTree::new();
+/// let first = tree.insert(1, "a");
+/// tree.insert(1, "b");
+/// tree.remove(&1);
+///
+/// assert_eq!(tree.get(&1), None);
+/// assert_eq!(tree.snapshot(first).unwrap().get(&1), Some(&"a"));
+/// assert_eq!(tree.snapshot(first + 1).unwrap().get(&1), Some(&"b"));
+/// ```
+pub struct VersionedAvlTree<K: Ord, V> {
+    ///every key's changes, oldest first, each tagged with the version that made it. None is
+    ///a remove
+    history: AvlTree<K, Vec<(u64, Option<V>)>>,
+    version: u64,
+    ///the oldest version that can still be read
+    oldest: u64
+}
+
+/// A read-only view of a `VersionedAvlTree` as it was at one version.
+pub struct Snapshot<'a, K: 'a + Ord, V: 'a> {
+    history: &'a AvlTree<K, Vec<(u64, Option<V>)>>,
+    version: u64
+}
+
+/// An iterator over the entries of a `Snapshot`, sorted by key.
+pub struct SnapshotIter<'a, K: 'a, V: 'a> {
+    entries: Iter<'a, K, Vec<(u64, Option<V>)>>,
+    version: u64
+}
+
+///the value a history gives as of a version, if the key was present then
+fn as_of<V>(history: &[(u64, Option<V>)], version: u64) -> Option<&V> {
+    //the history is sorted by version, so this is the first change after the version
+    let after = history.partition_point(|&(changed, _)| changed <= version);
+    after.checked_sub(1).and_then(|last| history[last].1.as_ref())
+}
+
+impl<K: Ord, V> VersionedAvlTree<K,V> {
+
+    /// Creates a new empty tree at version 0
+    pub fn new() -> Self {
+        VersionedAvlTree { history: AvlTree::new(), version: 0, oldest: 0 }
+    }
+
+    /// The version of the latest write, or 0 if nothing has been written
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The oldest version that can still be read, which `gc` moves forward
+    pub fn oldest_version(&self) -> u64 {
+        self.oldest
+    }
+
+    ///adds a change to the key's history under the next version
+    fn write(&mut self, key: K, val: Option<V>) -> u64 {
+        self.version += 1;
+        let version = self.version;
+        match self.history.get_mut(&key) {
+            Some(history) => history.push((version, val)),
+            None => {
+                self.history.insert(key, vec![(version, val)]);
+            }
+        }
+        version
+    }
+
+    /// Sets the key's value, returning the new version the write made.
+    pub fn insert(&mut self, key: K, val: V) -> u64 {
+        self.write(key, Some(val))
+    }
+
+    /// Removes the key, returning the new version the write made. Older versions still see
+    /// the key. Removing a key that is not present still makes a version.
+    pub fn remove(&mut self, key: &K) -> u64 where K: Clone {
+        self.write(key.clone(), None)
+    }
+
+    /// Gives the key's value as of the latest version
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.history.get(key).and_then(|history| history.last()).and_then(|(_, val)| val.as_ref())
+    }
+
+    /// Gives a view of the tree as it was right after the write that made the given version,
+    /// or None if that version has been collected by `gc` or has not been written yet.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::versioned::VersionedAvlTree;
+    ///
+    /// let mut tree = VersionedAvlTree::new();
+    /// for key in 0..5 {
+    ///     tree.insert(key, key * 10);
+    /// }
+    /// let view = tree.snapshot(3).unwrap();
+    /// assert_eq!(view.range(Some(&1), None).collect::<Vec<_>>(), vec![(&1, &10), (&2, &20)]);
+    /// assert!(tree.snapshot(6).is_none());
+    /// ```
+    pub fn snapshot(&self, version: u64) -> Option<Snapshot<'_, K,V>> {
+        if version < self.oldest || version > self.version {
+            return None;
+        }
+        Some(Snapshot { history: &self.history, version })
+    }
+
+    /// Forgets the versions older than the given one, keeping only what is needed to read
+    /// it and every version after it. Keys removed as of that version are dropped entirely.
+    /// Runs in O(n) for n keys, plus the length of the histories.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use avltree_map::versioned::VersionedAvlTree;
+    ///
+    /// let mut tree = VersionedAvlTree::new();
+    /// tree.insert(1, "a");
+    /// tree.insert(1, "b");
+    /// let kept = tree.insert(2, "c");
+    /// tree.insert(1, "d");
+    ///
+    /// tree.gc(kept);
+    /// assert!(tree.snapshot(kept - 1).is_none());
+    /// assert_eq!(tree.snapshot(kept).unwrap().get(&1), Some(&"b"));
+    /// assert_eq!(tree.get(&1), Some(&"d"));
+    /// ```
+    pub fn gc(&mut self, older_than: u64) {
+        let older_than = older_than.min(self.version);
+        if older_than <= self.oldest {
+            return;
+        }
+        self.oldest = older_than;
+        self.history.retain(|_, history| {
+            //the last change at or before the version is what it sees, so only older ones go
+            let visible = history.partition_point(|&(changed, _)| changed <= older_than);
+            if visible > 0 {
+                history.drain(..visible - 1);
+            }
+            //a key with nothing but a remove left is absent from every version still readable
+            !(history.len() == 1 && history[0].1.is_none())
+        });
+    }
+}
+
+impl<K: Ord, V> Default for VersionedAvlTree<K,V> {
+    fn default() -> Self {
+        VersionedAvlTree::new()
+    }
+}
+
+impl<'a, K: Ord, V> Snapshot<'a, K,V> {
+
+    /// The version this is a view of
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Gives the key's value as of this version
+    pub fn get(&self, key: &K) -> Option<&'a V> {
+        self.history.get(key).and_then(|history| as_of(history, self.version))
+    }
+
+    /// Checks if the key was present as of this version
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Gives an iterator over the entries present as of this version that fall within the
+    /// given start and end points (inclusive) in sorted order. If None is given, then that
+    /// side is unbounded.
+    pub fn range(&self, start: Option<&K>, end: Option<&K>) -> SnapshotIter<'a, K,V> {
+        SnapshotIter { entries: self.history.range_iter(start, end), version: self.version }
+    }
+
+    /// Gives an iterator over every entry present as of this version, sorted by key.
+    pub fn iter(&self) -> SnapshotIter<'a, K,V> {
+        self.range(None, None)
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> Iterator for SnapshotIter<'a, K,V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let version = self.version;
+        self.entries.by_ref().find_map(|(key, history)| as_of(history, version).map(|val| (key, val)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use versioned::VersionedAvlTree;
+    use ordered_map::conformance::XorShift;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_snapshots() {
+        let mut tree = VersionedAvlTree::new();
+        //the whole map as of every version
+        let mut models = vec![BTreeMap::new()];
+        let mut rng = XorShift::new(0x2545F4914F6CDD1D);
+        for step in 0..3000 {
+            let key = rng.next(100) as i32;
+            let mut model = models.last().unwrap().clone();
+            let version = if rng.next(4) == 0 {
+                model.remove(&key);
+                tree.remove(&key)
+            } else {
+                model.insert(key, step);
+                tree.insert(key, step)
+            };
+            assert_eq!(version as usize, models.len());
+            models.push(model);
+
+            if step % 500 == 499 {
+                tree.gc(version - 200);
+            }
+        }
+        let oldest = tree.oldest_version();
+        assert_eq!(oldest, 2800);
+        assert!(tree.snapshot(oldest - 1).is_none());
+        for version in oldest..=tree.version() {
+            let snapshot = tree.snapshot(version).unwrap();
+            let model = &models[version as usize];
+            assert_eq!(snapshot.iter().collect::<Vec<_>>(), model.iter().collect::<Vec<_>>());
+            assert_eq!(snapshot.range(Some(&20), Some(&40)).collect::<Vec<_>>(),
+                       model.range(20..=40).collect::<Vec<_>>());
+        }
+        for key in 0..100 {
+            assert_eq!(tree.get(&key), models.last().unwrap().get(&key));
+        }
+    }
+
+    #[test]
+    fn test_gc_exact_version() {
+        let mut tree = VersionedAvlTree::new();
+        tree.insert(1, "a");
+        tree.insert(2, "b");
+        tree.insert(1, "c");
+        let kept = tree.remove(&2);
+        tree.insert(3, "d");
+
+        //gc at a version that changed a key keeps that change and only that change
+        tree.gc(kept);
+        assert_eq!(tree.oldest_version(), kept);
+        assert!(tree.snapshot(kept - 1).is_none());
+        let snapshot = tree.snapshot(kept).unwrap();
+        assert_eq!(snapshot.iter().collect::<Vec<_>>(), vec![(&1, &"c")]);
+        assert_eq!(tree.history.get(&1).map(Vec::len), Some(1));
+        //the key removed at exactly that version is gone from every readable version
+        assert!(!tree.history.contains_key(&2));
+        assert_eq!(tree.history.get(&3).map(Vec::len), Some(1));
+
+        //collecting at or before the oldest version again changes nothing
+        tree.gc(kept);
+        tree.gc(1);
+        assert_eq!(tree.oldest_version(), kept);
+
+        //and at the latest version only the present is left
+        let latest = tree.version();
+        tree.gc(latest + 10);
+        assert_eq!(tree.oldest_version(), latest);
+        assert_eq!(tree.snapshot(latest).unwrap().iter().collect::<Vec<_>>(), vec![(&1, &"c"), (&3, &"d")]);
+        assert!(tree.snapshot(latest - 1).is_none());
+    }
+}
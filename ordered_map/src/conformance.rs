@@ -0,0 +1,203 @@
+//! A conformance suite for `OrderedMap` implementations.
+//!
+//! Every check is a public function that panics if the map misbehaves, generic over any
+//! `OrderedMap<i32, i32>` that can be made empty with `Default`. The
+//! [`ordered_map_conformance!`](../macro.ordered_map_conformance.html) macro turns the whole
+//! suite into tests:
+//!
+//! ```
+//! #[macro_use]
+//! extern crate ordered_map;
+//!
+//! # use std::collections::BTreeMap;
+//! #[cfg(test)]
+//! mod conformance {
+//!     ordered_map_conformance!(::std::collections::BTreeMap<i32, i32>);
+//! }
+//! # fn main() {
+//! #     ordered_map::conformance::differential::<BTreeMap<i32, i32>>(1, 100);
+//! # }
+//! ```
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use OrderedMap;
+
+/// A small xorshift generator, so that randomized tests are repeatable and need no crates.
+/// The conformance suite uses it, and so do the randomized tests of the maps themselves. It is
+/// test support for this workspace, not part of the public API.
+#[doc(hidden)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Creates a generator from a seed. A seed of zero, which xorshift can not leave, is
+    /// swapped for a fixed nonzero one.
+    pub fn new(seed: u64) -> Self {
+        XorShift(if seed == 0 { 0x2545F4914F6CDD1D } else { seed })
+    }
+
+    /// Gives the next number, below bound
+    pub fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+///the keys of an iterator, for comparing against expected orders
+fn keys<'a, I: Iterator<Item = (&'a i32, &'a i32)>>(iter: I) -> Vec<i32> {
+    iter.map(|(k, _)| *k).collect()
+}
+
+/// A new map is empty, finds nothing and iterates over nothing.
+pub fn empty<M: OrderedMap<i32, i32> + Default>() {
+    let mut map = M::default();
+    assert!(map.is_empty());
+    assert_eq!(map.get(&1), None);
+    assert!(!map.contains_key(&1));
+    assert_eq!(map.remove(&1), None);
+    assert_eq!(map.iter().next(), None);
+    assert_eq!(map.reverse_iter().next(), None);
+    assert_eq!(map.range_iter(Some(&0), Some(&10)).next(), None);
+}
+
+/// Inserting gives back the old value for a key, and the newest value is the one kept.
+pub fn insert_and_get<M: OrderedMap<i32, i32> + Default>() {
+    let mut map = M::default();
+    assert_eq!(map.insert(36, 1), None);
+    assert!(!map.is_empty());
+    assert_eq!(map.insert(36, 2), Some(1));
+    assert_eq!(map.insert(36, 3), Some(2));
+    assert_eq!(map.get(&36), Some(&3));
+
+    for num in 0..100 {
+        assert_eq!(map.insert(num * 2, num), if num * 2 == 36 { Some(3) } else { None });
+    }
+    for num in 0..200 {
+        assert_eq!(map.contains_key(&num), num % 2 == 0);
+        assert_eq!(map.get(&num).cloned(), if num % 2 == 0 { Some(num / 2) } else { None });
+    }
+}
+
+/// Removing gives back the value once, leaves the other keys alone, and can empty the map.
+pub fn remove<M: OrderedMap<i32, i32> + Default>() {
+    let mut map = M::default();
+    for num in 0..100 {
+        map.insert(num, -num);
+    }
+    for num in (0..100).filter(|n| n % 3 == 0) {
+        assert_eq!(map.remove(&num), Some(-num));
+        assert_eq!(map.remove(&num), None);
+    }
+    for num in 0..100 {
+        assert_eq!(map.contains_key(&num), num % 3 != 0);
+    }
+    for num in 0..100 {
+        map.remove(&num);
+    }
+    assert!(map.is_empty());
+    assert_eq!(map.iter().next(), None);
+}
+
+/// Iteration visits every entry once, sorted by key, whatever order they were inserted in.
+pub fn iteration_order<M: OrderedMap<i32, i32> + Default>() {
+    let mut map = M::default();
+    for num in (0..50).rev().chain(50..100) {
+        map.insert((num * 37) % 100, num);
+    }
+    assert_eq!(keys(map.iter()), (0..100).collect::<Vec<_>>());
+    assert_eq!(keys(map.reverse_iter()), (0..100).rev().collect::<Vec<_>>());
+    for (key, val) in map.iter() {
+        assert_eq!((*val * 37) % 100, *key);
+    }
+}
+
+/// Range bounds are inclusive, may be missing from the map, may be left off, and give
+/// nothing when the start is after the end.
+pub fn ranges<M: OrderedMap<i32, i32> + Default>() {
+    let mut map = M::default();
+    for num in 0..50 {
+        map.insert(num * 2, num);
+    }
+    assert_eq!(keys(map.range_iter(Some(&10), Some(&16))), vec![10, 12, 14, 16]);
+    assert_eq!(keys(map.range_iter(Some(&9), Some(&15))), vec![10, 12, 14]);
+    assert_eq!(keys(map.range_iter(None, Some(&4))), vec![0, 2, 4]);
+    assert_eq!(keys(map.range_iter(Some(&95), None)), vec![96, 98]);
+    assert_eq!(keys(map.range_iter(None, None)), keys(map.iter()));
+    assert_eq!(keys(map.range_iter(Some(&20), Some(&20))), vec![20]);
+    assert_eq!(keys(map.range_iter(Some(&21), Some(&21))), vec![]);
+    assert_eq!(keys(map.range_iter(Some(&30), Some(&20))), vec![]);
+    assert_eq!(keys(map.range_iter(Some(&200), None)), vec![]);
+
+    assert_eq!(keys(map.reverse_range_iter(Some(&9), Some(&15))), vec![14, 12, 10]);
+    assert_eq!(keys(map.reverse_range_iter(None, Some(&4))), vec![4, 2, 0]);
+    assert_eq!(keys(map.reverse_range_iter(Some(&95), None)), vec![98, 96]);
+    assert_eq!(keys(map.reverse_range_iter(Some(&30), Some(&20))), vec![]);
+}
+
+/// Runs a long, repeatable, random mix of operations on the map and on a `BTreeMap`, checking
+/// after every step that they agree.
+pub fn differential<M: OrderedMap<i32, i32> + Default>(seed: u64, steps: usize) {
+    let mut rng = XorShift::new(seed);
+    let mut next = |bound: i32| rng.next(bound as u64) as i32;
+
+    let mut map = M::default();
+    let mut model = BTreeMap::new();
+    for step in 0..steps {
+        let key = next(200);
+        match next(10) {
+            0..=4 => assert_eq!(map.insert(key, step as i32), model.insert(key, step as i32), "step {}", step),
+            5..=7 => assert_eq!(map.remove(&key), model.remove(&key), "step {}", step),
+            8 => assert_eq!(map.get(&key), model.get(&key), "step {}", step),
+            _ => {
+                let end = key + next(50);
+                let found: Vec<_> = map.range_iter(Some(&key), Some(&end)).collect();
+                let expected: Vec<_> = model.range(key..=end).collect();
+                assert_eq!(found, expected, "step {}", step);
+            }
+        }
+        assert_eq!(map.is_empty(), model.is_empty(), "step {}", step);
+    }
+    assert_eq!(map.iter().collect::<Vec<_>>(), model.iter().collect::<Vec<_>>());
+    assert_eq!(map.reverse_iter().collect::<Vec<_>>(), model.iter().rev().collect::<Vec<_>>());
+}
+
+/// Expands to one test for each check in the conformance suite, run on the given map type,
+/// which must implement `OrderedMap<i32, i32>` and `Default`. Use it inside a test module.
+#[macro_export]
+macro_rules! ordered_map_conformance {
+    ($map:ty) => {
+        #[test]
+        fn conformance_empty() {
+            $crate::conformance::empty::<$map>();
+        }
+
+        #[test]
+        fn conformance_insert_and_get() {
+            $crate::conformance::insert_and_get::<$map>();
+        }
+
+        #[test]
+        fn conformance_remove() {
+            $crate::conformance::remove::<$map>();
+        }
+
+        #[test]
+        fn conformance_iteration_order() {
+            $crate::conformance::iteration_order::<$map>();
+        }
+
+        #[test]
+        fn conformance_ranges() {
+            $crate::conformance::ranges::<$map>();
+        }
+
+        #[test]
+        fn conformance_differential() {
+            for seed in 1..10 {
+                $crate::conformance::differential::<$map>(seed, 2000);
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Counting the heap memory that keys and values own.
+//!
+//! The maps can count the bytes of their own nodes, but a `String` key or a `Vec` value owns
+//! memory the map cannot see. Types that implement `HeapSize` report it, so the maps' stats
+//! can include it.
+use core::mem::size_of;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A value that can report how many bytes it owns on the heap.
+///
+/// Only memory the value owns is counted, not the value itself, which is already counted
+/// wherever it is stored. Shared memory, such as the target of a reference, is not counted.
+///
+/// #Examples
+///
+/// ```
+/// use ordered_map::heap_size::HeapSize;
+///
+/// assert_eq!(5u32.heap_size(), 0);
+/// assert_eq!(String::with_capacity(10).heap_size(), 10);
+/// assert_eq!(vec![String::with_capacity(3)].heap_size(), std::mem::size_of::<String>() + 3);
+/// ```
+pub trait HeapSize {
+    /// The number of bytes this value owns on the heap
+    fn heap_size(&self) -> usize;
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    }
+}
+
+no_heap!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char, ());
+
+impl<T: ?Sized> HeapSize for &T {
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        size_of::<T>() + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize, C: HeapSize> HeapSize for (A, B, C) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size() + self.2.heap_size()
+    }
+}
@@ -0,0 +1,217 @@
+#![deny(missing_docs)]
+#![no_std]
+//! A trait shared by the sorted maps in this workspace, so that code can be written once for
+//! any of them, along with a conformance suite that any implementation can run to check that
+//! it behaves like the others.
+//!
+//! Only `core` and `alloc` are needed, so it can be used without the standard library.
+#[macro_use]
+extern crate alloc;
+
+use core::alloc::Layout;
+use core::cmp::Ord;
+use core::error::Error;
+use core::fmt;
+use alloc::alloc::alloc;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, TryReserveError};
+use alloc::collections::btree_map;
+use alloc::vec::Vec;
+use core::ops::Bound;
+use core::ptr;
+
+#[macro_use]
+pub mod conformance;
+pub mod heap_size;
+
+/// A map that keeps its keys in sorted order.
+///
+/// Ranges are given as an optional start and end, both inclusive, where None leaves that side
+/// unbounded. A range whose start is after its end is empty.
+pub trait OrderedMap<K: Ord, V> {
+    /// The iterator over the entries of the map, which yields them sorted by key or in reverse
+    type Iter<'a>: Iterator<Item = (&'a K, &'a V)> where Self: 'a, K: 'a, V: 'a;
+
+    /// Inserts a key,value pair into the map. Returns None if the key was not present in the
+    /// map already. If the key was present, then the key is updated with the new value and the
+    /// old value is returned.
+    fn insert(&mut self, key: K, val: V) -> Option<V>;
+
+    /// Returns None if the key is not present, or a reference to the value if it is
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Removes a key from the map, returning its value if the key was present
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Checks if the key is present
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Checks if the map is empty
+    fn is_empty(&self) -> bool;
+
+    /// Gives an iterator over the key-value pairs in the map, sorted by key
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Gives an iterator over the key-value pairs in the map, sorted by key, in reverse order
+    fn reverse_iter(&self) -> Self::Iter<'_>;
+
+    /// Gives an iterator over the key-value pairs in the map that fall within the given start
+    /// and end points (inclusive) in sorted order
+    fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> Self::Iter<'_>;
+
+    /// Gives an iterator over the key-value pairs in the map that fall within the given start
+    /// and end points (inclusive) in reverse sorted order
+    fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> Self::Iter<'_>;
+}
+
+/// The error given back by the fallible insert methods of the maps in this workspace when
+/// memory for a new entry could not be allocated. The map is left as it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "memory allocation failed")
+    }
+}
+
+impl Error for AllocError {}
+
+impl From<TryReserveError> for AllocError {
+    fn from(_: TryReserveError) -> Self {
+        AllocError
+    }
+}
+
+/// Boxes a value like `Box::new`, but gives back an error along with the value instead of
+/// aborting if the memory can not be allocated.
+///
+/// #Examples
+///
+/// ```
+/// use ordered_map::try_box;
+///
+/// assert_eq!(try_box(5).map(|b| *b), Ok(5));
+/// ```
+pub fn try_box<T>(val: T) -> Result<Box<T>, (AllocError, T)> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return Ok(Box::new(val));
+    }
+    //the memory comes from the global allocator with T's layout, which is what Box expects
+    //to free, and it is written before the box is made
+    unsafe {
+        let raw = alloc(layout) as *mut T;
+        if raw.is_null() {
+            return Err((AllocError, val));
+        }
+        ptr::write(raw, val);
+        Ok(Box::from_raw(raw))
+    }
+}
+
+/// Sorts entries by key, keeping only the last value given for each key, as inserting them
+/// one at a time would. Entries that are already sorted with no duplicates are given back as
+/// they are, so maps can build themselves from the result in one pass.
+///
+/// #Examples
+///
+/// ```
+/// use ordered_map::sorted_unique;
+///
+/// assert_eq!(sorted_unique(vec![(2, 'a'), (1, 'b'), (2, 'c')]), vec![(1, 'b'), (2, 'c')]);
+/// ```
+pub fn sorted_unique<K: Ord, V>(mut entries: Vec<(K,V)>) -> Vec<(K,V)> {
+    if entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+        return entries;
+    }
+    //the sort is stable, so equal keys stay in the order they were given
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut unique: Vec<(K,V)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match unique.last_mut() {
+            Some(last) if last.0 == entry.0 => *last = entry,
+            _ => unique.push(entry)
+        }
+    }
+    unique
+}
+
+/// The iterator used by the `OrderedMap` implementation for `BTreeMap`.
+pub struct BTreeIter<'a, K: 'a, V: 'a> {
+    range: Option<btree_map::Range<'a, K, V>>,
+    forwards: bool
+}
+
+impl<'a, K: 'a + Ord, V: 'a> BTreeIter<'a, K, V> {
+    fn new(map: &'a BTreeMap<K, V>, start: Option<&K>, end: Option<&K>, forwards: bool) -> Self {
+        //BTreeMap::range panics on backwards ranges, which are empty here
+        let backwards = match (start, end) {
+            (Some(start), Some(end)) => start > end,
+            _ => false
+        };
+        let bound = |key| match key {
+            Some(key) => Bound::Included(key),
+            None => Bound::Unbounded
+        };
+        BTreeIter {
+            range: if backwards { None } else { Some(map.range::<K, _>((bound(start), bound(end)))) },
+            forwards
+        }
+    }
+}
+
+impl<'a, K: 'a, V: 'a> Iterator for BTreeIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let forwards = self.forwards;
+        self.range.as_mut().and_then(|range| if forwards { range.next() } else { range.next_back() })
+    }
+}
+
+/// The standard library's sorted map, which the conformance suite checks the others against.
+impl<K: Ord, V> OrderedMap<K, V> for BTreeMap<K, V> {
+    type Iter<'a> = BTreeIter<'a, K, V> where K: 'a, V: 'a;
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        BTreeMap::insert(self, key, val)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BTreeMap::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        BTreeMap::is_empty(self)
+    }
+
+    fn iter(&self) -> BTreeIter<'_, K, V> {
+        BTreeIter::new(self, None, None, true)
+    }
+
+    fn reverse_iter(&self) -> BTreeIter<'_, K, V> {
+        BTreeIter::new(self, None, None, false)
+    }
+
+    fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> BTreeIter<'_, K, V> {
+        BTreeIter::new(self, start, end, true)
+    }
+
+    fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> BTreeIter<'_, K, V> {
+        BTreeIter::new(self, start, end, false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::collections::BTreeMap;
+
+    ordered_map_conformance!(BTreeMap<i32, i32>);
+}
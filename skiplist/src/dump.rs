@@ -0,0 +1,212 @@
+//! Debugging views of a SkipList's structure.
+use core::cmp::Ord;
+use core::fmt::{self, Debug, Write};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use {SkipList, SkiplistNode};
+
+///the space between neighbouring nodes in `to_ascii`, which fits a link of width one
+const GAP: usize = 6;
+
+///escapes a string for use inside a Graphviz record label
+fn escape_dot(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        if "\\\"{}|<> ".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl<K: Ord + Debug, V> SkipList<K,V> {
+
+    ///every node in sorted order
+    fn nodes(&self) -> Vec<&SkiplistNode<K,V>> {
+        let mut nodes = Vec::new();
+        let mut current = self.next_at(&None, 0);
+        while let Some(node) = current {
+            let node = self.node_ref(&node);
+            nodes.push(node);
+            current = node.forward.borrow()[0].next.clone();
+        }
+        nodes
+    }
+
+    ///the widths of the links out of the head, then out of each node, at one level. None for
+    ///positions whose tower does not reach that level
+    fn widths_at(&self, nodes: &[&SkiplistNode<K,V>], level: usize) -> Vec<Option<i32>> {
+        let mut widths = vec![Some(self.0[level].width)];
+        widths.extend(nodes.iter().map(|node| node.forward.borrow().get(level).map(|link| link.width)));
+        widths
+    }
+
+    /// Gives the structure of the list in the Graphviz DOT language. Every node is drawn as a
+    /// tower with one box per level and its key at the bottom, and every link is labeled with
+    /// its width. Render it with `dot -Tsvg`.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use skiplist::SkipList;
+    ///
+    ///  let mut list = SkipList::new();
+    ///  list.insert(1, "a");
+    ///
+    ///  let dot = list.to_dot();
+    ///  assert!(dot.starts_with("digraph SkipList {"));
+    ///  assert!(dot.contains("head:l0 -> n0:l0 [label=\"1\"];"));
+    ///  assert!(dot.contains("n0:l0 -> nil [label=\"1\"];"));
+    ///  ```
+    pub fn to_dot(&self) -> String {
+        let nodes = self.nodes();
+        let mut out = String::from("digraph SkipList {\n    rankdir=LR;\n    node [shape=record];\n");
+
+        let tower = |height: usize, label: &str| {
+            let mut fields: Vec<String> = (0..height).rev().map(|level| format!("<l{}> ", level)).collect();
+            fields.push(escape_dot(label));
+            fields.join("|")
+        };
+        let _ = writeln!(out, "    head [label=\"{}\"];", tower(self.0.len(), "head"));
+        for (i, node) in nodes.iter().enumerate() {
+            let _ = writeln!(out, "    n{} [label=\"{}\"];", i, tower(node.forward.borrow().len(), &format!("{:?}", node.key)));
+        }
+        out.push_str("    nil [label=\"nil\"];\n");
+
+        let name = |index: usize| if index == 0 { String::from("head") } else { format!("n{}", index - 1) };
+        for level in 0..self.0.len() {
+            for (index, width) in self.widths_at(&nodes, level).into_iter().enumerate() {
+                if let Some(width) = width {
+                    let next = index + width as usize;
+                    let target = if next > nodes.len() { String::from("nil") } else { format!("{}:l{}", name(next), level) };
+                    let _ = writeln!(out, "    {}:l{} -> {} [label=\"{}\"];", name(index), level, target, width);
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Gives a quick view of the list's structure for a terminal. Each level is drawn on its
+    /// own line, highest first, with every link labeled with its width. Nodes line up in
+    /// columns, so each node's tower can be read straight down.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use skiplist::SkipList;
+    ///
+    ///  let mut list = SkipList::new();
+    ///  list.insert(1, "a");
+    ///  list.insert(2, "b");
+    ///
+    ///  //the towers are random, but every node is on the bottom level
+    ///  let ascii = list.to_ascii();
+    ///  assert_eq!(ascii.lines().last(), Some("L0  head -1-> 1 -1-> 2 -1-> nil"));
+    ///  ```
+    pub fn to_ascii(&self) -> String {
+        let nodes = self.nodes();
+        let mut labels = vec![String::from("head")];
+        labels.extend(nodes.iter().map(|node| format!("{:?}", node.key)));
+        labels.push(String::from("nil"));
+
+        let mut columns = Vec::with_capacity(labels.len());
+        let mut total = 0;
+        for label in &labels {
+            columns.push(total);
+            total += label.chars().count() + GAP;
+        }
+
+        let mut out = String::new();
+        for level in (0..self.0.len()).rev() {
+            let mut row = vec![' '; total];
+            let widths = self.widths_at(&nodes, level);
+            for (index, label) in labels.iter().enumerate() {
+                let on_level = index == labels.len() - 1 || widths[index].is_some();
+                if !on_level {
+                    continue;
+                }
+                let len = label.chars().count();
+                for (i, c) in label.chars().enumerate() {
+                    row[columns[index] + i] = c;
+                }
+                if let Some(width) = widths.get(index).and_then(|width| *width) {
+                    //draw the link up to just before the next node on this level
+                    let start = columns[index] + len + 1;
+                    let end = columns[index + width as usize] - 1;
+                    for c in &mut row[start..end] {
+                        *c = '-';
+                    }
+                    row[end - 1] = '>';
+                    let text = width.to_string();
+                    let at = start + (end - start - 1 - text.len()) / 2;
+                    for (i, c) in text.chars().enumerate() {
+                        row[at + i] = c;
+                    }
+                }
+            }
+            let line: String = row.into_iter().collect();
+            let _ = writeln!(out, "L{:<2} {}", level, line.trim_end());
+        }
+        out
+    }
+}
+
+impl<K: Ord + Debug, V: Debug> Debug for SkipList<K,V> {
+    /// Shows the list as a map in sorted order. Use `to_ascii` or `to_dot` to see its shape.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use SkipList;
+
+    #[test]
+    fn test_dot() {
+        let mut list = SkipList::new();
+        for num in 0..50 {
+            list.insert(num, ());
+        }
+        let dot = list.to_dot();
+
+        //one edge per level of every tower, including the head's
+        let links = list.0.len() + list.nodes().iter().map(|n| n.forward.borrow().len()).sum::<usize>();
+        assert_eq!(dot.matches(" -> ").count(), links);
+        //every level ends at nil
+        assert_eq!(dot.matches(" -> nil ").count(), list.0.len());
+        //the key sits under the bottom level of the tower
+        assert!(dot.contains("<l0> |49\"];\n"));
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut list = SkipList::new();
+        for num in 0..20 {
+            list.insert(num * 5, ());
+        }
+        let ascii = list.to_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), list.0.len());
+        assert!(lines[lines.len() - 1].starts_with("L0  head -1-> 0 -1-> 5 -1-> 10 -1-> 15 -1->"));
+        assert!(lines[lines.len() - 1].ends_with("-1-> 95 -1-> nil"));
+        //every level starts at the head and ends at nil
+        for line in &lines {
+            assert!(line[4..].starts_with("head -"));
+            assert!(line.ends_with("-> nil"));
+        }
+
+        assert_eq!(SkipList::<i32, ()>::new().to_ascii(), "");
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut list = SkipList::new();
+        list.insert(2, "b");
+        list.insert(1, "a");
+        assert_eq!(format!("{:?}", list), r#"{1: "a", 2: "b"}"#);
+    }
+}
@@ -1,34 +1,388 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 //! A Skip List
-use std::cmp::{Ord, Ordering};
-use std::rc::Rc;
+//!
+//! The `std` feature is on by default. Without it the crate needs only `core` and `alloc`, and
+//! tower heights come from a small built-in generator instead of `rand`.
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate rand;
+#[cfg_attr(test, macro_use)]
+extern crate ordered_map;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+use core::cell::RefCell;
+use core::cmp::{Ord, Ordering};
+use core::mem::{MaybeUninit, take};
+use core::ops::Deref;
+use core::ptr::NonNull;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::AtomicU32;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::Ordering::Relaxed;
+use ordered_map::{OrderedMap, try_box};
+pub use ordered_map::AllocError;
+
+///counts an event in a list's own counters with the `metrics` feature, and compiles to
+///nothing without it
+#[cfg(feature = "metrics")]
+macro_rules! record {
+    ($list:expr, $event:ident()) => { $list.1.$event() }
+}
+#[cfg(not(feature = "metrics"))]
+macro_rules! record {
+    ($($ignored:tt)*) => {}
+}
+
+mod dump;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod stats;
+
+///the most levels a node can have
+const MAX_HEIGHT: usize = 32;
+
+///A link to the next node at one level. The width is the number of nodes the link skips
+///over, plus one. A link with no next node points one past the end of the list.
 struct Link<K:Ord, V> {
    width: i32,
-   next: Option<Rc<SkiplistNode<K,V>>>
+   next: Option<NodePtr<K,V>>
 }
+///A node is linked to from the node before it at every level of its tower, so it is reached
+///through shared `NodePtr`s and its links can only be changed through the `RefCell`.
 struct SkiplistNode<K: Ord, V> {
     key: K,
     val: V,
+    forward: RefCell<Vec<Link<K,V>>>
+}
+
+///A pointer to a node in the list. The list owns its nodes and frees each one when it is
+///unlinked or the list is dropped, which only happens through `&mut` access to the list, so a
+///pointer held while the list is borrowed always points to a live node.
+struct NodePtr<K: Ord, V>(NonNull<SkiplistNode<K,V>>);
+
+impl<K: Ord, V> Clone for NodePtr<K,V> {
+    fn clone(&self) -> Self {
+        NodePtr(self.0)
+    }
+}
+
+impl<K: Ord, V> Deref for NodePtr<K,V> {
+    type Target = SkiplistNode<K,V>;
+
+    fn deref(&self) -> &SkiplistNode<K,V> {
+        //see above: the node is live for as long as the pointer is in use
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl<K: Ord, V> NodePtr<K,V> {
+    ///frees a node that has been unlinked from every level, giving back what it held
+    ///
+    ///the caller must make sure no other pointer to the node is used afterwards
+    unsafe fn free(self) -> SkiplistNode<K,V> {
+        *Box::from_raw(self.0.as_ptr())
+    }
+}
+
+///The memory for a node with a tower of the given height, with room for its links, that does
+///not hold an entry yet.
+struct Slot<K: Ord, V> {
+    height: usize,
+    node: Box<MaybeUninit<SkiplistNode<K,V>>>,
     forward: Vec<Link<K,V>>
 }
 
+impl<K: Ord, V> Slot<K,V> {
+    fn new(height: usize) -> Self {
+        Slot { height, node: Box::new(MaybeUninit::uninit()), forward: Vec::with_capacity(height) }
+    }
+
+    ///like `new`, but gives back an error instead of aborting if the memory can not be
+    ///allocated
+    fn try_new(height: usize) -> Result<Self, AllocError> {
+        let mut forward = Vec::new();
+        forward.try_reserve_exact(height)?;
+        let node = try_box(MaybeUninit::uninit()).map_err(|(err, _)| err)?;
+        Ok(Slot { height, node, forward })
+    }
+
+    ///puts an entry in the node, which has no links yet, and hands it over to the list
+    fn fill(self, key: K, val: V) -> NodePtr<K,V> {
+        let node = Box::write(self.node, SkiplistNode { key, val, forward: RefCell::new(self.forward) });
+        NodePtr(NonNull::from(Box::leak(node)))
+    }
+}
+
+///Memory the list keeps between operations: the nodes set aside by `try_reserve`, and the path
+///used to find where a key goes.
+struct Spare<K: Ord, V> {
+    slots: Vec<Slot<K,V>>,
+    path: Vec<(Position<K,V>, i32)>
+}
+
 ///A struct used to iterate over the skiplist
 pub struct Iter<'a, K: Ord + 'a, V: 'a> {
-    //TODO
-    v1: &'a K,
-    v2: &'a V
+    queue: VecDeque<(&'a K,&'a V)>,
+    forwards: bool
+}
+
+impl<'a, K: 'a + Ord, V: 'a> Iter<'a,K, V> {
+
+    ///creates an iterator containing all elements between start and end, inclusive,
+    ///Unbounded if the None
+    fn new(list: &'a SkipList<K,V>, start: Option<&K>, end: Option<&K>, forwards: bool) -> Self {
+        let mut iter = Iter {
+            queue: VecDeque::new(),
+            forwards
+        };
+        let mut current = match start {
+            None => list.next_at(&None, 0),
+            Some(start) => {
+                //an empty list has no levels, so no path
+                let path = list.find_path(start);
+                path.first().and_then(|(pos, _)| list.next_at(pos, 0))
+            }
+        };
+        while let Some(node) = current {
+            let node = list.node_ref(&node);
+            let past_end = end.is_some_and(|end| {
+                record!(list, compared());
+                node.key > *end
+            });
+            if past_end {
+                break;
+            }
+            iter.queue.push_back((&node.key, &node.val));
+            current = node.forward.borrow()[0].next.clone();
+        }
+        iter
+    }
 }
 
+impl<'a, K: 'a + Ord , V: 'a> Iterator for Iter<'a,K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.forwards {
+            self.queue.pop_front()
+        } else {
+            self.queue.pop_back()
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.queue.len(), Some(self.queue.len()))
+    }
+}
+
+impl<'a, K: 'a + Ord, V: 'a> ExactSizeIterator for Iter<'a, K, V> {}
+
 ///A map based on a randomized skiplist.  Allows for insertion, deletion, search, and indexing in
 ///O(log n) time
-pub struct SkipList<K: Ord, V> (Vec<Link<K,V>>);
+///
+///With the `serde` feature, the list is serialized as a map in sorted order. Deserializing
+///links all of the entries at once rather than inserting them one at a time. The entries may
+///come in any order, and if a key appears more than once the last value for it is kept, just as
+///if the entries had been inserted one at a time.
+///
+///With the `metrics` feature, the list also counts the work its own operations do, read with
+///`counters`.
+pub struct SkipList<K: Ord, V> (Vec<Link<K,V>>, Meter, Spare<K,V>);
+
+//the counters a list keeps with the `metrics` feature, which take no space without it
+#[cfg(feature = "metrics")]
+use metrics::Meter;
+#[cfg(not(feature = "metrics"))]
+#[derive(Default)]
+struct Meter;
+
+///A position in the list that links can start from. None is the head of the list.
+type Position<K, V> = Option<NodePtr<K,V>>;
+
+///random bits for picking tower heights
+#[cfg(feature = "std")]
+fn random_bits() -> u32 {
+    rand::random::<u32>()
+}
+
+///random bits for picking tower heights, from a xorshift generator shared by every list. the
+///state is loaded and stored separately so only atomic loads and stores are needed; when two
+///threads race, one of them just repeats a number, which does no harm to a tower height
+#[cfg(not(feature = "std"))]
+fn random_bits() -> u32 {
+    static STATE: AtomicU32 = AtomicU32::new(0x9E37_79B9);
+    let mut x = STATE.load(Relaxed);
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    STATE.store(x, Relaxed);
+    x
+}
+
+///picks a tower height for a new node. each extra level is half as likely as the one below it
+fn random_height() -> usize {
+    (random_bits().trailing_ones() as usize + 1).min(MAX_HEIGHT)
+}
 
 impl<K: Ord, V> SkipList<K,V> {
 
     ///Creates an empty SkipList
     pub fn new() -> Self {
-        SkipList(Vec::new())
+        SkipList(Vec::new(), Default::default(), Spare { slots: Vec::new(), path: Vec::new() })
+    }
+
+    /// Gives the work this list's own operations have counted since it was created or
+    /// `reset_counters` was last called, including the searches made by iterating over
+    /// ranges. Only with the `metrics` feature.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use skiplist::SkipList;
+    ///
+    /// let mut list = SkipList::new();
+    /// list.insert(1, "a");
+    /// list.reset_counters();
+    /// list.get(&1);
+    /// let counters = list.counters();
+    /// assert_eq!(counters.comparisons, counters.levels);
+    /// assert_eq!(counters.hops, 0);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn counters(&self) -> metrics::Counters {
+        self.1.get()
+    }
+
+    /// Sets this list's counts back to zero. Only with the `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn reset_counters(&mut self) {
+        self.1.reset();
+    }
+
+    ///gives a reference to a node that lives as long as the borrow of the list
+    fn node_ref<'a>(&'a self, node: &NodePtr<K,V>) -> &'a SkiplistNode<K,V> {
+        //nodes are only ever unlinked or freed through `&mut self`, so a node that is
+        //reachable from the list stays alive and unmoved for as long as the list is borrowed
+        unsafe { &*node.0.as_ptr() }
+    }
+
+    ///the node after a position at the given level
+    fn next_at(&self, pos: &Position<K,V>, level: usize) -> Position<K,V> {
+        match *pos {
+            None => self.0.get(level).and_then(|link| link.next.clone()),
+            Some(ref node) => node.forward.borrow()[level].next.clone()
+        }
+    }
+
+    ///the width of the link after a position at the given level
+    fn width_at(&self, pos: &Position<K,V>, level: usize) -> i32 {
+        match *pos {
+            None => self.0[level].width,
+            Some(ref node) => node.forward.borrow()[level].width
+        }
+    }
+
+    ///runs f on the link after a position at the given level
+    fn with_link<T, F: FnOnce(&mut Link<K,V>) -> T>(&mut self, pos: &Position<K,V>, level: usize, f: F) -> T {
+        match *pos {
+            None => f(&mut self.0[level]),
+            Some(ref node) => f(&mut node.forward.borrow_mut()[level])
+        }
+    }
+
+    ///finds the last position before key at every level, along with the index of that position
+    ///in the list. The head has index 0 and the first node index 1.
+    fn find_path(&self, key: &K) -> Vec<(Position<K,V>, i32)> {
+        let mut path = Vec::with_capacity(self.0.len());
+        self.find_path_into(key, &mut path);
+        path
+    }
+
+    ///fills path like `find_path`, which only allocates if it has less room than there are
+    ///levels
+    fn find_path_into(&self, key: &K, path: &mut Vec<(Position<K,V>, i32)>) {
+        path.clear();
+        let mut pos = None;
+        let mut index = 0;
+        for level in (0..self.0.len()).rev() {
+            record!(self, descended());
+            while let Some(next) = self.next_at(&pos, level) {
+                record!(self, compared());
+                if next.key >= *key {
+                    break;
+                }
+                record!(self, hopped());
+                index += self.width_at(&pos, level);
+                pos = Some(next);
+            }
+            path.push((pos.clone(), index));
+        }
+        path.reverse();
+    }
+
+    ///the distance from the head to one past the end of the list, which is the number of nodes
+    ///plus one
+    fn total_width(&self) -> i32 {
+        match self.0.len() {
+            0 => 1,
+            height => {
+                let level = height - 1;
+                let mut pos = None;
+                let mut total = 0;
+                loop {
+                    total += self.width_at(&pos, level);
+                    match self.next_at(&pos, level) {
+                        None => return total,
+                        next => pos = next
+                    }
+                }
+            }
+        }
+    }
+
+    ///builds a list out of entries sorted by key with no duplicates, linking each node after
+    ///the last node of each level so far
+    #[cfg(feature = "serde")]
+    fn from_sorted_vec(entries: Vec<(K,V)>) -> Self {
+        let mut list = SkipList::new();
+        //the last position at each level, and its index
+        let mut last: Vec<(Position<K,V>, i32)> = Vec::new();
+        let mut index = 0;
+        for (key, val) in entries {
+            index += 1;
+            let height = random_height();
+            while list.0.len() < height {
+                list.0.push(Link { width: 0, next: None });
+                last.push((None, 0));
+            }
+            let node = Slot::new(height).fill(key, val);
+            for (level, last) in last.iter_mut().enumerate().take(height) {
+                let next = node.clone();
+                list.with_link(&last.0, level, |link| {
+                    link.width = index - last.1;
+                    link.next = Some(next);
+                });
+                node.forward.borrow_mut().push(Link { width: 0, next: None });
+                *last = (Some(node.clone()), index);
+            }
+        }
+        //the last link at every level points one past the end
+        for (level, last) in last.iter().enumerate() {
+            list.with_link(&last.0, level, |link| link.width = index + 1 - last.1);
+        }
+        list
     }
 
     /// Inserts a key,value pair into the list. Returns None if the key was
@@ -38,7 +392,7 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     /// ```
-    /// use skiplist::SkipList
+    /// use skiplist::SkipList;
     ///
     /// let mut list = SkipList::new();
     ///
@@ -48,17 +402,124 @@ impl<K: Ord, V> SkipList<K,V> {
     /// list.insert(37, "b");
     /// assert_eq!(list.insert(37, "c"), Some("b"));
     /// assert_eq!(list.get(&37), Some(&"c"));
+    /// ```
     pub fn insert(&mut self, key: K, val: V) -> Option<V> {
-        None
+        //nodes can not be changed once shared, so an old value is replaced along with its node
+        let mut path = take(&mut self.2.path);
+        let old = self.unlink(&key, &mut path);
+        let slot = self.2.slots.pop().unwrap_or_else(|| Slot::new(random_height()));
+        self.link(key, val, slot, &mut path);
+        self.2.path = path;
+        old
     }
-   
+
+    /// Inserts a key,value pair like `insert`, but gives back an error instead of aborting if
+    /// the memory for the new node can not be allocated, in which case the list is left as it
+    /// was and the key and value are handed back with the error. All of it is set aside before
+    /// the list is changed, and none is needed if room was set aside by `try_reserve`.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use skiplist::SkipList;
+    ///
+    /// let mut list = SkipList::new();
+    ///
+    /// assert_eq!(list.try_insert(37, "a"), Ok(None));
+    /// assert_eq!(list.try_insert(37, "b"), Ok(Some("a")));
+    /// assert_eq!(list.get(&37), Some(&"b"));
+    /// ```
+    pub fn try_insert(&mut self, key: K, val: V) -> Result<Option<V>, (AllocError, K, V)> {
+        let height = self.2.slots.last().map_or_else(random_height, |slot| slot.height);
+        let levels = self.0.len().max(height);
+        let reserved = self.2.path.try_reserve(levels)
+            .and_then(|()| self.0.try_reserve(levels - self.0.len()))
+            .map_err(AllocError::from)
+            .and_then(|()| match self.2.slots.pop() {
+                Some(slot) => Ok(slot),
+                None => Slot::try_new(height)
+            });
+        let slot = match reserved {
+            Ok(slot) => slot,
+            Err(err) => return Err((err, key, val))
+        };
+
+        let mut path = take(&mut self.2.path);
+        let old = self.unlink(&key, &mut path);
+        self.link(key, val, slot, &mut path);
+        self.2.path = path;
+        Ok(old)
+    }
+
+    /// Sets aside the memory for `additional` more new entries, so that the next that many
+    /// calls to `insert` or `try_insert` do not allocate and so can not fail. Gives back an
+    /// error if the memory can not be allocated, in which case some of it may have been set
+    /// aside all the same. Removing entries does not add to the room set aside.
+    ///
+    /// #Examples
+    ///
+    /// ```
+    /// use skiplist::SkipList;
+    ///
+    /// let mut list = SkipList::new();
+    /// assert_eq!(list.try_reserve(2), Ok(()));
+    ///
+    /// assert_eq!(list.try_insert(1, "a"), Ok(None));
+    /// assert_eq!(list.try_insert(2, "b"), Ok(None));
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        self.2.slots.try_reserve(additional)?;
+        for _ in 0..additional {
+            let slot = Slot::try_new(random_height())?;
+            self.2.slots.push(slot);
+        }
+        //the head and the path need a level for every level a new node can have
+        let levels = self.2.slots.iter().map(|slot| slot.height).fold(self.0.len(), usize::max);
+        self.0.try_reserve(levels - self.0.len())?;
+        self.2.path.try_reserve(levels)?;
+        Ok(())
+    }
+
+    ///links a new node into the list in the given slot, where the key is not present. path is
+    ///used to find where it goes, which does not allocate if it has room for every level.
+    fn link(&mut self, key: K, val: V, slot: Slot<K,V>, path: &mut Vec<(Position<K,V>, i32)>) {
+        let height = slot.height;
+        while self.0.len() < height {
+            let total = self.total_width();
+            self.0.push(Link { width: total, next: None });
+        }
+
+        self.find_path_into(&key, path);
+        let index = path[0].1 + 1;
+        let node = slot.fill(key, val);
+        for (level, &(ref pos, pos_index)) in path.iter().enumerate() {
+            if level < height {
+                //splice the new node in after pos
+                let link = self.with_link(pos, level, |link| Link {
+                    width: link.width - (index - pos_index) + 1,
+                    next: link.next.take()
+                });
+                node.forward.borrow_mut().push(link);
+                let next = node.clone();
+                self.with_link(pos, level, |link| {
+                    link.width = index - pos_index;
+                    link.next = Some(next);
+                });
+            } else {
+                //the link passes over the new node
+                self.with_link(pos, level, |link| link.width += 1);
+            }
+        }
+        path.clear();
+    }
+
 
     /// checks if the list is empty.
     ///
     ///  #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///
@@ -67,18 +528,19 @@ impl<K: Ord, V> SkipList<K,V> {
     ///  list.insert("hello","world");
     ///
     ///  assert!(!list.is_empty());
+    ///  ```
     pub fn is_empty(&self) -> bool {
-        self.0.len() == 0
+        self.0.is_empty()
     }
 
-    /// Takes a reference to something of type Key and 
+    /// Takes a reference to something of type Key and
     /// returns None if the key is not present, or a reference to the
     /// value if the key is present
     ///
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(37, "b");
@@ -89,6 +551,21 @@ impl<K: Ord, V> SkipList<K,V> {
     ///
     ///  ```
     pub fn get(&self, key: &K) -> Option<&V> {
+        let mut pos = None;
+        for level in (0..self.0.len()).rev() {
+            record!(self, descended());
+            while let Some(next) = self.next_at(&pos, level) {
+                record!(self, compared());
+                match next.key.cmp(key) {
+                    Ordering::Less => {
+                        record!(self, hopped());
+                        pos = Some(next);
+                    },
+                    Ordering::Equal => return Some(&self.node_ref(&next).val),
+                    Ordering::Greater => break
+                }
+            }
+        }
         None
     }
 
@@ -100,7 +577,7 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(37, "b");
@@ -112,16 +589,59 @@ impl<K: Ord, V> SkipList<K,V> {
     ///
     ///  ```
     pub fn remove(&mut self, key: &K) -> Option<V> {
-        None
+        let mut path = take(&mut self.2.path);
+        let old = self.unlink(key, &mut path);
+        self.2.path = path;
+        old
+    }
+
+    ///takes the key's node out of the list, using path to find it, which does not allocate if
+    ///it has room for every level. path is left empty, so it holds no pointers once a node is
+    ///freed
+    fn unlink(&mut self, key: &K, path: &mut Vec<(Position<K,V>, i32)>) -> Option<V> {
+        self.find_path_into(key, path);
+        let target = match path.first().and_then(|(pos, _)| self.next_at(pos, 0)) {
+            Some(ref node) if node.key == *key => node.clone(),
+            _ => {
+                path.clear();
+                return None;
+            }
+        };
+
+        for (level, (pos, _)) in path.iter().enumerate() {
+            let links_to_target = self.next_at(pos, level).is_some_and(|next| next.0 == target.0);
+            if links_to_target {
+                //take over the target's link at this level
+                let (width, next) = {
+                    let mut forward = target.forward.borrow_mut();
+                    (forward[level].width, forward[level].next.take())
+                };
+                self.with_link(pos, level, |link| {
+                    link.width += width - 1;
+                    link.next = next;
+                });
+            } else {
+                self.with_link(pos, level, |link| link.width -= 1);
+            }
+        }
+        path.clear();
+
+        //levels with nothing left on them are dropped so an empty list has no levels
+        while self.0.last().is_some_and(|link| link.next.is_none()) {
+            self.0.pop();
+        }
+
+        //the target is no longer linked from anywhere, and path has been cleared
+        Some(unsafe { target.free() }.val)
     }
 
     /// Takes a referenece to something of type Key and
-    /// checks if the key is present. 
+    /// checks if the key is present.
     ///
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(37, "b");
@@ -129,9 +649,9 @@ impl<K: Ord, V> SkipList<K,V> {
     ///
     ///  assert!(list.contains_key(&1));
     ///  assert!(!list.contains_key(&2));
-    ///
+    ///  ```
     pub fn contains_key(&self, key: &K) -> bool {
-        false
+        self.get(key).is_some()
     }
 
     /// Gives an iterator over the key-value pairs in the list, sorted by key.
@@ -139,16 +659,16 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(37, "b");
     ///  list.insert(1,"a");
     ///
     ///  assert_eq!(list.iter().next().unwrap(), (&1, &"a"));
-    ///
-    pub fn iter(&self) -> Iter<K,V> {
-        panic!("Not Done");
+    ///  ```
+    pub fn iter(&self) -> Iter<'_, K,V> {
+        Iter::new(self, None, None, true)
     }
 
     /// Gives an iterator over the key-value pairs in the list, sorted by key, in reverse order.
@@ -156,17 +676,16 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(37, "b");
     ///  list.insert(1,"a");
     ///
     ///  assert_eq!(list.reverse_iter().next().unwrap(), (&37, &"b"));
-    ///
-    pub fn reverse_iter(&self) -> Iter<K,V> {
-        panic!("Not Done");
-
+    ///  ```
+    pub fn reverse_iter(&self) -> Iter<'_, K,V> {
+        Iter::new(self, None, None, false)
     }
 
 
@@ -176,7 +695,7 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(1,"a");
@@ -186,10 +705,9 @@ impl<K: Ord, V> SkipList<K,V> {
     ///  list.insert(5,"a");
     ///
     ///  assert_eq!(list.range_iter(Some(&2), None).next().unwrap(), (&2, &"a"));
-    ///
-    pub fn range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<K,V> {
-        panic!("Not Done");
-
+    ///  ```
+    pub fn range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+        Iter::new(self, start, end, true)
     }
 
     /// Gives an iterator over the key-value pairs in the list that fall within the given start and
@@ -198,7 +716,7 @@ impl<K: Ord, V> SkipList<K,V> {
     /// #Examples
     ///
     ///  ```
-    ///  use skiplist::SkipList
+    ///  use skiplist::SkipList;
     ///
     ///  let mut list = SkipList::new();
     ///  list.insert(1,"a");
@@ -208,21 +726,265 @@ impl<K: Ord, V> SkipList<K,V> {
     ///  list.insert(5,"a");
     ///
     ///  assert_eq!(list.reverse_range_iter(None, Some(&3)).next().unwrap(), (&3, &"a"));
-    ///
-    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<K,V> {
-        panic!("Not Done");
-
+    ///  ```
+    pub fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K> ) -> Iter<'_, K,V> {
+        Iter::new(self, start, end, false)
     }
 
+}
 
-    
+impl<K: Ord, V> Default for SkipList<K,V> {
+    fn default() -> Self {
+        SkipList::new()
+    }
+}
 
+impl<K: Ord, V> Drop for SkipList<K,V> {
+    ///frees the nodes one at a time along the bottom level
+    fn drop(&mut self) {
+        self.2.path.clear();
+        let mut current = self.0.drain(..).next().and_then(|link| link.next);
+        while let Some(node) = current {
+            //every node is reached once along the bottom level
+            current = unsafe { node.free() }.forward.into_inner().drain(..).next().and_then(|link| link.next);
+        }
+    }
 }
 
 
+impl<K: Ord, V> OrderedMap<K,V> for SkipList<K,V> {
+    type Iter<'a> = Iter<'a,K,V> where K: 'a, V: 'a;
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        SkipList::insert(self, key, val)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        SkipList::get(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        SkipList::remove(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        SkipList::contains_key(self, key)
+    }
+
+    fn is_empty(&self) -> bool {
+        SkipList::is_empty(self)
+    }
+
+    fn iter(&self) -> Iter<'_, K,V> {
+        SkipList::iter(self)
+    }
+
+    fn reverse_iter(&self) -> Iter<'_, K,V> {
+        SkipList::reverse_iter(self)
+    }
+
+    fn range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        SkipList::range_iter(self, start, end)
+    }
+
+    fn reverse_range_iter(&self, start: Option<&K>, end: Option<&K>) -> Iter<'_, K,V> {
+        SkipList::reverse_range_iter(self, start, end)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use {AllocError, NodePtr, SkipList};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::ptr;
+
+    ///an allocator that fails every allocation made on a thread while that thread has
+    ///FAILING set, so allocation failures can be tested without disturbing other tests
+    struct Flaky;
+
+    thread_local!(static FAILING: Cell<bool> = const { Cell::new(false) });
+
+    unsafe impl GlobalAlloc for Flaky {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if FAILING.try_with(Cell::get).unwrap_or(false) { ptr::null_mut() } else { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Flaky = Flaky;
+
+    ///checks that every link's width is the distance between the nodes it joins
+    pub fn check_widths<K: Ord, V>(list: &SkipList<K,V>) {
+        let mut index = Vec::new();
+        let mut current = list.next_at(&None, 0);
+        while let Some(node) = current {
+            index.push(node.clone());
+            current = node.forward.borrow()[0].next.clone();
+        }
+        let position = |node: &Option<NodePtr<_,_>>| match *node {
+            None => index.len() as i32 + 1,
+            Some(ref node) => index.iter().position(|n| n.0 == node.0).unwrap() as i32 + 1
+        };
+        for (level, link) in list.0.iter().enumerate() {
+            assert_eq!(link.width, position(&link.next), "head level {}", level);
+        }
+        for (i, node) in index.iter().enumerate() {
+            for (level, link) in node.forward.borrow().iter().enumerate() {
+                assert_eq!(link.width, position(&link.next) - i as i32 - 1, "node {} level {}", i, level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut list = SkipList::new();
+        for num in 1..100 {
+            assert_eq!(list.insert(num, num),None);
+        }
+        check_widths(&list);
+
+        //check reinserting returns right thing
+        assert_eq!(list.insert(5,30), Some(5));
+        assert_eq!(list.insert(5,35), Some(30));
+        assert_eq!(list.insert(99,35), Some(99));
+        assert_eq!(list.insert(101,35), None);
+        assert_eq!(list.get(&5), Some(&35));
+        check_widths(&list);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut list = SkipList::new();
+        for num in 1..1000 {
+            assert_eq!(list.insert(num, num),None);
+        }
+
+        for num in 100..900 {
+            assert_eq!(list.remove(&num), Some(num));
+        }
+        check_widths(&list);
+
+        assert_eq!(list.remove(&50), Some(50));
+        assert_eq!(list.remove(&500), None);
+        assert!(!list.contains_key(&400));
+        assert!(list.contains_key(&900));
+
+        for num in (1..1000).filter(|n| *n < 100 || *n >= 900) {
+            list.remove(&num);
+        }
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_range_iter_empty() {
+        let list: SkipList<i32, i32> = SkipList::new();
+        assert_eq!(list.range_iter(Some(&1), Some(&5)).count(), 0);
+        assert_eq!(list.reverse_range_iter(Some(&1), None).count(), 0);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut list = SkipList::new();
+        for num in (1..1000).rev() {
+            assert_eq!(list.insert(num, num),None);
+        };
+
+        let mut c = 1;
+        //ensures the keys come back in order
+        for (key,val) in list.iter() {
+            assert!(key == val);
+            assert!(*key == c);
+            c += 1;
+        };
+        assert!(c == 1000);
+
+        c = 999;
+        for (key, _) in list.reverse_iter() {
+            assert!(*key == c);
+            c -= 1;
+        };
+
+        c = 100;
+        for (key, _) in list.range_iter(Some(&100), Some(&900)) {
+            assert!(*key == c);
+            c += 1;
+        }
+        assert!(c == 901);
+
+        c = 900;
+        for (key, _) in list.reverse_range_iter(None, Some(&900)) {
+            assert!(*key == c);
+            c -= 1;
+        }
+        assert!(c == 0);
+    }
+
     #[test]
-    fn it_works() {
+    fn test_try_insert() {
+        let mut list = SkipList::new();
+        for num in 0..100 {
+            assert_eq!(list.try_insert(num * 2, num), Ok(None));
+        }
+        check_widths(&list);
+
+        for key in 0..200 {
+            FAILING.with(|failing| failing.set(true));
+            let result = list.try_insert(key, 0);
+            FAILING.with(|failing| failing.set(false));
+            assert_eq!(result, Err((AllocError, key, 0)));
+        }
+        check_widths(&list);
+        assert_eq!(list.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(), (0..100).map(|num| (num * 2, num)).collect::<Vec<_>>());
+
+        assert_eq!(list.try_insert(50, 7), Ok(Some(25)));
+        assert_eq!(list.try_insert(51, 7), Ok(None));
+        check_widths(&list);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut list = SkipList::new();
+        for num in 0..100 {
+            list.insert(num * 2, num);
+        }
+        assert_eq!(list.try_reserve(50), Ok(()));
+
+        //the room set aside covers 50 inserts, of new keys or not, while nothing can be
+        //allocated, and removing does not need any
+        FAILING.with(|failing| failing.set(true));
+        let mut inserted = 0;
+        for key in 0..50 {
+            let r = list.try_insert(key, 0).is_ok(); if !r { FAILING.with(|f| f.set(false)); panic!("key {} slots {} levels {} cap {} path {}", key, list.2.slots.len(), list.0.len(), list.0.capacity(), list.2.path.capacity()); } inserted += 1;
+            list.remove(&(key + 100));
+        }
+        let after = list.try_insert(1000, 0);
+        FAILING.with(|failing| failing.set(false));
+        assert_eq!(inserted, 50);
+        assert_eq!(after, Err((AllocError, 1000, 0)));
+        check_widths(&list);
+        //25 of the keys were new, and 25 of the removed keys were there
+        assert_eq!(list.iter().count(), 100);
+        assert_eq!(list.get(&1), Some(&0));
+        assert_eq!(list.get(&100), None);
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        let mut list = SkipList::new();
+        for num in 0..200000 {
+            list.insert(num, ());
+        }
+        drop(list);
+    }
+
+    mod conformance {
+        use SkipList;
+
+        ordered_map_conformance!(SkipList<i32, i32>);
     }
 }
@@ -0,0 +1,110 @@
+//! Counters for the work SkipList operations do, with the `metrics` feature.
+//!
+//! Every `SkipList` counts the key comparisons, the links followed along a level and the levels
+//! searched by its own operations, read with `SkipList::counters` and cleared with
+//! `SkipList::reset_counters`. Without the feature none of this is compiled in, and the lists
+//! do no extra work.
+//!
+//! #Examples
+//!
+//! ```
+//! use skiplist::SkipList;
+//!
+//! let mut small = SkipList::new();
+//! let mut large = SkipList::new();
+//! for key in 0..1000 {
+//!     large.insert(key, "a");
+//! }
+//! large.reset_counters();
+//!
+//! small.insert(1, "a");
+//! large.get(&999);
+//! assert_eq!(small.counters().hops, 0);
+//! assert!(large.counters().hops > 0);
+//! ```
+use std::cell::Cell;
+
+/// The number of each kind of event counted for one list since it was created or its counters
+/// were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Nodes a key was compared against while searching for it
+    pub comparisons: u64,
+    /// Links followed from one node to the next along a level
+    pub hops: u64,
+    /// Levels searched, counting the top level a search starts on
+    pub levels: u64
+}
+
+///the counters kept in a list
+#[derive(Default)]
+pub(crate) struct Meter(Cell<Counters>);
+
+impl Meter {
+    pub(crate) fn get(&self) -> Counters {
+        self.0.get()
+    }
+
+    pub(crate) fn reset(&self) {
+        self.0.set(Counters::default());
+    }
+
+    ///adds to one of the counts
+    fn count<F: FnOnce(&mut Counters)>(&self, f: F) {
+        let mut counts = self.0.get();
+        f(&mut counts);
+        self.0.set(counts);
+    }
+
+    ///counts one comparison
+    pub(crate) fn compared(&self) {
+        self.count(|counts| counts.comparisons += 1);
+    }
+
+    ///counts one link followed along a level
+    pub(crate) fn hopped(&self) {
+        self.count(|counts| counts.hops += 1);
+    }
+
+    ///counts one level searched
+    pub(crate) fn descended(&self) {
+        self.count(|counts| counts.levels += 1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use SkipList;
+
+    #[test]
+    fn test_counters() {
+        let mut list = SkipList::new();
+        for key in 0..1000 {
+            list.insert(key, key);
+        }
+        list.reset_counters();
+        assert_eq!(list.get(&999), Some(&999));
+        let counters = list.counters();
+        assert!(counters.levels > 1);
+        //every hop needs a comparison, and so does stopping anywhere but the end of a level
+        assert!(counters.comparisons > counters.hops);
+        assert!(counters.comparisons <= counters.hops + counters.levels);
+        //far fewer than walking the bottom level
+        assert!(counters.hops < 200);
+
+        list.reset_counters();
+        list.remove(&500);
+        assert!(list.counters().comparisons > 0);
+
+        //a range searches for its start, then compares every key it walks over with its end
+        list.reset_counters();
+        assert_eq!(list.iter().count(), 999);
+        assert_eq!(list.counters().comparisons, 0);
+        assert_eq!(list.range_iter(None, Some(&10)).count(), 11);
+        assert_eq!(list.counters().comparisons, 12);
+        list.reset_counters();
+        assert_eq!(list.range_iter(Some(&10), Some(&20)).count(), 11);
+        assert!(list.counters().comparisons > 12);
+        assert!(list.counters().levels > 1);
+    }
+}
@@ -0,0 +1,82 @@
+//! Serialize and Deserialize for SkipList, enabled by the `serde` feature.
+use core::cmp::{Ord, min};
+use core::fmt;
+use core::marker::PhantomData;
+use alloc::vec::Vec;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+use ordered_map::sorted_unique;
+
+use SkipList;
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for SkipList<K,V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+struct ListVisitor<K, V> (PhantomData<(K, V)>);
+
+impl<'de, K, V> Visitor<'de> for ListVisitor<K, V>
+    where K: Ord + Deserialize<'de>, V: Deserialize<'de> {
+    type Value = SkipList<K,V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        //the size hint comes from the input, so do not trust it with a huge allocation
+        let mut entries = Vec::with_capacity(min(map.size_hint().unwrap_or(0), 4096));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(SkipList::from_sorted_vec(sorted_unique(entries)))
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for SkipList<K,V>
+    where K: Ord + Deserialize<'de>, V: Deserialize<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ListVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json;
+    use SkipList;
+    use tests::check_widths;
+
+    #[test]
+    fn test_round_trip() {
+        let mut list = SkipList::new();
+        for num in 0..1000 {
+            list.insert(num, num * 2);
+        }
+        let json = serde_json::to_string(&list).unwrap();
+        let mut back: SkipList<i32, i32> = serde_json::from_str(&json).unwrap();
+
+        check_widths(&back);
+        assert_eq!(back.iter().collect::<Vec<_>>(), list.iter().collect::<Vec<_>>());
+
+        //the bulk built list can still be changed as usual
+        back.remove(&500);
+        back.insert(2000, 0);
+        check_widths(&back);
+    }
+
+    #[test]
+    fn test_unsorted_and_duplicates() {
+        let json = r#"{"c": 3, "a": 1, "b": 2, "a": 4}"#;
+        let list: SkipList<String, i32> = serde_json::from_str(json).unwrap();
+
+        check_widths(&list);
+        let entries: Vec<_> = list.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(entries, vec![("a", 4), ("b", 2), ("c", 3)]);
+
+        let empty: SkipList<String, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+    }
+}
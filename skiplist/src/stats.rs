@@ -0,0 +1,190 @@
+//! Memory use and shape of a SkipList.
+use core::cmp::{Ord, Ordering, max};
+use core::mem::size_of;
+use alloc::vec::Vec;
+
+pub use ordered_map::heap_size::HeapSize;
+
+use {Link, Position, SkipList, SkiplistNode};
+
+/// Statistics about a SkipList, from `SkipList::stats` or `SkipList::stats_with_contents`.
+///
+/// The depth of an entry is the number of nodes a search for its key steps onto, the entry
+/// itself included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListStats {
+    /// The number of entries
+    pub len: usize,
+    /// The bytes allocated for the nodes, their towers of links and the head's links, along
+    /// with the memory set aside by `SkipList::try_reserve`. The nodes hold the keys and values
+    /// themselves
+    pub node_bytes: usize,
+    /// The bytes the keys and values own on the heap, if they were counted
+    pub content_bytes: Option<usize>,
+    /// The average depth of the entries, or 0 for an empty list
+    pub average_depth: f64,
+    /// The depth of the deepest entry, or 0 for an empty list
+    pub max_depth: usize,
+    /// How many nodes have each tower height: the first count is of nodes with one level,
+    /// the next of nodes with two, and so on up to the tallest tower
+    pub tower_heights: Vec<usize>
+}
+
+impl ListStats {
+    /// All of the heap bytes counted: the nodes, and the keys' and values' own memory if it
+    /// was counted
+    pub fn heap_bytes(&self) -> usize {
+        self.node_bytes + self.content_bytes.unwrap_or(0)
+    }
+}
+
+impl<K: Ord, V> SkipList<K,V> {
+
+    /// Gives the number of entries, the bytes used by the nodes, the depths of the entries
+    /// and the heights of the towers. Keys and values are only counted by their own size; use
+    /// `stats_with_contents` to count the memory they own as well. Finding every entry's
+    /// depth takes a search for each, so this runs in O(n log n).
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use skiplist::SkipList;
+    ///
+    ///  let mut list = SkipList::new();
+    ///  for key in 0..100 {
+    ///      list.insert(key, "a");
+    ///  }
+    ///
+    ///  let stats = list.stats();
+    ///  assert_eq!(stats.len, 100);
+    ///  assert_eq!(stats.tower_heights.iter().sum::<usize>(), 100);
+    ///  assert!(stats.max_depth <= 100);
+    ///  assert_eq!(stats.content_bytes, None);
+    ///  ```
+    pub fn stats(&self) -> ListStats {
+        let mut stats = ListStats {
+            len: 0,
+            node_bytes: self.0.capacity() * size_of::<Link<K,V>>() + self.spare_bytes(),
+            content_bytes: None,
+            average_depth: 0.0,
+            max_depth: 0,
+            tower_heights: Vec::new()
+        };
+        let mut total_depth = 0;
+        self.visit(|node| {
+            let tower = node.forward.borrow();
+            stats.len += 1;
+            stats.node_bytes += size_of::<SkiplistNode<K,V>>()
+                + tower.capacity() * size_of::<Link<K,V>>();
+            if stats.tower_heights.len() < tower.len() {
+                stats.tower_heights.resize(tower.len(), 0);
+            }
+            stats.tower_heights[tower.len() - 1] += 1;
+
+            let depth = self.depth(&node.key);
+            total_depth += depth;
+            stats.max_depth = max(stats.max_depth, depth);
+        });
+        if stats.len > 0 {
+            stats.average_depth = total_depth as f64 / stats.len as f64;
+        }
+        stats
+    }
+
+    /// Gives the same statistics as `stats`, and also counts the memory the keys and values
+    /// own on the heap through `HeapSize`.
+    ///
+    /// #Examples
+    ///
+    ///  ```
+    ///  use skiplist::SkipList;
+    ///
+    ///  let mut list = SkipList::new();
+    ///  list.insert(1, String::with_capacity(16));
+    ///  list.insert(2, String::with_capacity(8));
+    ///
+    ///  let stats = list.stats_with_contents();
+    ///  assert_eq!(stats.content_bytes, Some(24));
+    ///  assert_eq!(stats.heap_bytes(), stats.node_bytes + 24);
+    ///  ```
+    pub fn stats_with_contents(&self) -> ListStats where K: HeapSize, V: HeapSize {
+        let mut content_bytes = 0;
+        self.visit(|node| content_bytes += node.key.heap_size() + node.val.heap_size());
+        ListStats { content_bytes: Some(content_bytes), ..self.stats() }
+    }
+
+    ///the bytes of the path kept between operations and of the nodes set aside by
+    ///`try_reserve`
+    fn spare_bytes(&self) -> usize {
+        self.2.path.capacity() * size_of::<(Position<K,V>, i32)>()
+            + self.2.slots.iter().map(|slot| {
+                size_of::<SkiplistNode<K,V>>() + slot.forward.capacity() * size_of::<Link<K,V>>()
+            }).sum::<usize>()
+    }
+
+    ///runs f on every node in sorted order
+    fn visit<F: FnMut(&SkiplistNode<K,V>)>(&self, mut f: F) {
+        let mut current = self.next_at(&None, 0);
+        while let Some(node) = current {
+            let node = self.node_ref(&node);
+            f(node);
+            current = node.forward.borrow()[0].next.clone();
+        }
+    }
+
+    ///the number of nodes a search for a key in the list steps onto, the key's own included
+    fn depth(&self, key: &K) -> usize {
+        let mut pos = None;
+        let mut depth = 0;
+        for level in (0..self.0.len()).rev() {
+            while let Some(next) = self.next_at(&pos, level) {
+                match next.key.cmp(key) {
+                    Ordering::Less => {
+                        depth += 1;
+                        pos = Some(next);
+                    },
+                    Ordering::Equal => return depth + 1,
+                    Ordering::Greater => break
+                }
+            }
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::mem::size_of;
+    use {SkipList, SkiplistNode};
+
+    #[test]
+    fn test_stats() {
+        let empty: SkipList<u32, u32> = SkipList::new();
+        let stats = empty.stats();
+        assert_eq!((stats.len, stats.max_depth), (0, 0));
+        assert_eq!(stats.average_depth, 0.0);
+        assert!(stats.tower_heights.is_empty());
+
+        let mut list = SkipList::new();
+        for key in 0..1000u32 {
+            list.insert(key, vec![0u8; 4]);
+        }
+        let stats = list.stats_with_contents();
+        assert_eq!(stats.len, 1000);
+        assert_eq!(stats.tower_heights.iter().sum::<usize>(), 1000);
+        //about half of the towers stop at each level
+        assert!(stats.tower_heights[0] > 300 && stats.tower_heights[0] < 700);
+        assert!(stats.average_depth >= 1.0 && stats.average_depth < 100.0);
+        assert_eq!(stats.content_bytes, Some(4000));
+
+        //the first entry is always found in one step
+        list.insert(0, vec![]);
+        assert!(list.stats().max_depth >= 1);
+        assert_eq!(list.depth(&0), 1);
+
+        //room set aside is counted until it is used
+        let before = list.stats().node_bytes;
+        list.try_reserve(10).unwrap();
+        assert!(list.stats().node_bytes >= before + 10 * size_of::<SkiplistNode<u32, Vec<u8>>>());
+    }
+}